@@ -0,0 +1,163 @@
+use std::{error, fmt, fs::File, io::Read};
+
+use csv::StringRecord;
+use fraction::Zero;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    input_ingestion::get_csv_reader_from,
+    models::{
+        money::Money,
+        restrictions::Restriction,
+        shared::{Amount, ClientID},
+        transaction::currency_code,
+    },
+};
+
+#[derive(Debug)]
+pub enum AdminError {
+    Unreadable(String),
+    // The operation at this line of the admin file has no operator or reason, it's a freeze without a restriction,
+    // or it's a credit or debit without a positive amount.
+    InvalidOperation(u64),
+}
+
+impl fmt::Display for AdminError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdminError::Unreadable(reason) => {
+                write!(f, "Unable to read the admin operations: {}", reason)
+            }
+            AdminError::InvalidOperation(line) => {
+                write!(f, "The admin operation at line {} is not valid", line)
+            }
+        }
+    }
+}
+
+impl error::Error for AdminError {}
+
+// What an operator does to an account, whether it's locked or not.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AdminAction {
+    // Lifts the restriction from the account, or all of them when none is given.
+    Unlock(Option<Restriction>),
+    Freeze(Restriction),
+    // Adds the money to the total of the account.
+    Credit(Money),
+    // Takes the money from the total of the account, even if it goes negative.
+    Debit(Money),
+}
+
+// An operation of an operator on the account of a client, with who did it and why for the audit trail.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AdminOperation {
+    pub client_id: ClientID,
+    pub action: AdminAction,
+    pub operator: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum AdminActionType {
+    Unlock,
+    Freeze,
+    Credit,
+    Debit,
+}
+
+// A row of the admin file, for example:
+// action, client, amount, currency, restriction, operator, reason
+// unlock, 7, , , chargeback, jane, Chargeback reversed by the partner
+// credit, 7, 10.0, USD, , jane, Goodwill for the wrongful lock
+#[derive(Debug, Deserialize)]
+struct AdminRecord {
+    action: AdminActionType,
+    client: ClientID,
+    #[serde(default)]
+    amount: String,
+    #[serde(default)]
+    currency: Option<String>,
+    #[serde(default)]
+    restriction: Option<Restriction>,
+    #[serde(default)]
+    operator: String,
+    #[serde(default)]
+    reason: String,
+}
+
+// Reads every operation in the admin file, none if any of them is not valid.
+pub fn open_admin_operations(path: &str) -> Result<Vec<AdminOperation>, AdminError> {
+    let file = File::open(path).map_err(|e| AdminError::Unreadable(e.to_string()))?;
+    read_admin_operations(file)
+}
+
+pub fn read_admin_operations<R: Read>(input: R) -> Result<Vec<AdminOperation>, AdminError> {
+    let mut operations = vec![];
+    let mut reader = get_csv_reader_from(input);
+    let headers = reader
+        .headers()
+        .map_err(|e| AdminError::Unreadable(e.to_string()))?
+        .clone();
+    for result in reader.records() {
+        let row = result.map_err(|e| AdminError::Unreadable(e.to_string()))?;
+        operations.push(admin_operation_from(&row, &headers)?);
+    }
+    Ok(operations)
+}
+
+// The columns of the admin file, for rows coming without a headers row.
+pub fn admin_headers() -> StringRecord {
+    StringRecord::from(vec![
+        "action",
+        "client",
+        "amount",
+        "currency",
+        "restriction",
+        "operator",
+        "reason",
+    ])
+}
+
+// Reads the operation in one row of the admin file, with the given headers.
+pub fn admin_operation_from(
+    row: &StringRecord,
+    headers: &StringRecord,
+) -> Result<AdminOperation, AdminError> {
+    let line = row.position().map_or(0, |position| position.line());
+    let record: AdminRecord = row
+        .deserialize(Some(headers))
+        .map_err(|e| AdminError::Unreadable(e.to_string()))?;
+    if record.operator.is_empty() || record.reason.is_empty() {
+        return Err(AdminError::InvalidOperation(line));
+    }
+    let money = record
+        .amount
+        .parse::<Amount>()
+        .ok()
+        .filter(|amount| *amount > Amount::zero())
+        .map(|amount| Money {
+            amount,
+            currency: record.currency.as_deref().and_then(currency_code),
+        });
+    let action = match (record.action, money) {
+        (AdminActionType::Unlock, _) => AdminAction::Unlock(record.restriction),
+        (AdminActionType::Freeze, _) => match record.restriction {
+            Some(restriction) => AdminAction::Freeze(restriction),
+            None => return Err(AdminError::InvalidOperation(line)),
+        },
+        (AdminActionType::Credit, Some(money)) => AdminAction::Credit(money),
+        (AdminActionType::Debit, Some(money)) => AdminAction::Debit(money),
+        (AdminActionType::Credit | AdminActionType::Debit, None) => {
+            return Err(AdminError::InvalidOperation(line));
+        }
+    };
+    Ok(AdminOperation {
+        client_id: record.client,
+        action,
+        operator: record.operator,
+        reason: record.reason,
+    })
+}
@@ -0,0 +1,116 @@
+use std::{
+    error::Error,
+    io::{BufWriter, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+};
+
+use crate::{
+    admin::{admin_headers, admin_operation_from},
+    input_ingestion::csv_reader_builder,
+    models::{account::Accounts, disputes::Disputes, transaction::Transactions},
+    payments_engine::PaymentsEngine,
+    storage::{AccountStore, DisputeStore, TransactionStore},
+};
+
+// The line that makes the engine read its blocklist again, from the file it was loaded from at startup.
+pub const RELOAD_BLOCKLIST_COMMAND: &str = "reload-blocklist";
+
+// Serves operators over TCP, apart from the partners, on the engine they are served with.
+// It's meant to be listening on an address only operators can reach.
+//
+// Every connection sends rows in the same `action, client, amount, currency, restriction, operator, reason` format
+// as the admin file (the headers row is optional), each one applied and journaled as soon as it arrives and answered
+// with `applied` or why it was refused. A `reload-blocklist` line makes the engine read its blocklist again,
+// answering with how many clients and partners it blocks (or why it kept the previous one).
+// Every answer is followed by an empty line.
+pub struct AdminServer<A = Accounts, T = Transactions, D = Disputes> {
+    engine: Arc<Mutex<PaymentsEngine<A, T, D>>>,
+}
+
+impl<A, T, D> Clone for AdminServer<A, T, D> {
+    fn clone(&self) -> Self {
+        Self {
+            engine: Arc::clone(&self.engine),
+        }
+    }
+}
+
+impl<A, T, D> AdminServer<A, T, D>
+where
+    A: AccountStore + Send + 'static,
+    T: TransactionStore + Send + 'static,
+    D: DisputeStore + Send + 'static,
+{
+    pub fn new(engine: Arc<Mutex<PaymentsEngine<A, T, D>>>) -> Self {
+        Self { engine }
+    }
+
+    // Accepts connections forever, handling each one on its own thread.
+    pub fn serve(&self, listener: TcpListener) {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let server = self.clone();
+                    thread::spawn(move || {
+                        let peer = stream.peer_addr();
+                        if let Err(e) = server.handle(stream) {
+                            eprintln!("Closed admin connection with {:?}: {}", peer, e);
+                        }
+                    });
+                }
+                Err(e) => eprintln!("Unable to accept an admin connection: {}", e),
+            }
+        }
+    }
+
+    fn handle(&self, stream: TcpStream) -> Result<(), Box<dyn Error>> {
+        let mut responses = BufWriter::new(stream.try_clone()?);
+        let mut reader = csv_reader_builder().has_headers(false).from_reader(stream);
+        let headers = admin_headers();
+        for result in reader.records() {
+            let answer = match result {
+                Ok(record) if record.get(0) == Some("action") => continue,
+                Ok(record) if record.len() == 1 && &record[0] == RELOAD_BLOCKLIST_COMMAND => {
+                    self.reload_blocklist()
+                }
+                Ok(record) => match admin_operation_from(&record, &headers) {
+                    Ok(operation) => {
+                        let mut engine = self.engine();
+                        engine.check_journal()?;
+                        engine.apply_admin(operation)?;
+                        if let Some(journal) = engine.journal.as_mut() {
+                            journal.flush()?;
+                        }
+                        "applied".to_owned()
+                    }
+                    Err(e) => e.to_string(),
+                },
+                Err(e) if e.is_io_error() => return Err(Box::new(e)),
+                Err(e) => e.to_string(),
+            };
+            writeln!(responses, "{}", answer)?;
+            writeln!(responses)?;
+            responses.flush()?;
+        }
+        Ok(())
+    }
+
+    fn reload_blocklist(&self) -> String {
+        let mut engine = self.engine();
+        match engine.blocklist.reload() {
+            Ok(()) => {
+                let (clients, partners) = engine.blocklist.len();
+                format!("blocklist, {} clients, {} partners", clients, partners)
+            }
+            Err(e) => e.to_string(),
+        }
+    }
+
+    fn engine(&self) -> MutexGuard<'_, PaymentsEngine<A, T, D>> {
+        self.engine
+            .lock()
+            .expect("A connection panicked while using the engine")
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    collections::HashSet,
+    error, fmt,
+    fs::{self, File},
+    io::Read,
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    input_ingestion::get_csv_reader_from,
+    models::shared::{ClientID, PartnerID},
+};
+
+#[derive(Debug)]
+pub enum BlocklistError {
+    Unreadable(String),
+    // The entry at this line of the blocklist has no id, or not a valid one.
+    InvalidEntry(u64),
+    // It was not read from a file, so there is nothing to read again.
+    NothingToReload,
+}
+
+impl fmt::Display for BlocklistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlocklistError::Unreadable(reason) => {
+                write!(f, "Unable to read the blocklist: {}", reason)
+            }
+            BlocklistError::InvalidEntry(line) => {
+                write!(f, "The blocklist entry at line {} is not valid", line)
+            }
+            BlocklistError::NothingToReload => {
+                write!(f, "There is no blocklist file to reload from")
+            }
+        }
+    }
+}
+
+impl error::Error for BlocklistError {}
+
+// Whether a blocklist entry is for a client or for a partner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Blocked {
+    Client,
+    Partner,
+}
+
+// A row of the blocklist, for example:
+// scope, id
+// client, 7
+// partner, 3
+#[derive(Debug, Deserialize)]
+struct BlocklistRecord {
+    scope: Blocked,
+    #[serde(default)]
+    id: String,
+}
+
+// How often a watched blocklist looks at its file to tell if it changed.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+// The clients nothing can be processed for, and the partners nothing can come through,
+// as the last sanctions screening tells. It remembers the file it was read from so it can be read again,
+// and when that file was modified so it can be watched for changes.
+#[derive(Debug, Clone, Default)]
+pub struct Blocklist {
+    path: Option<String>,
+    modified: Option<SystemTime>,
+    watched: bool,
+    checked_at: Option<Instant>,
+    clients: HashSet<ClientID>,
+    partners: HashSet<PartnerID>,
+}
+
+impl Blocklist {
+    pub fn open(path: &str) -> Result<Self, BlocklistError> {
+        let file = File::open(path).map_err(|e| BlocklistError::Unreadable(e.to_string()))?;
+        let modified = file
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        Ok(Self {
+            path: Some(path.to_owned()),
+            modified,
+            ..Blocklist::read_from(file)?
+        })
+    }
+
+    pub fn read_from<R: Read>(input: R) -> Result<Self, BlocklistError> {
+        let mut blocklist = Blocklist::default();
+        let mut reader = get_csv_reader_from(input);
+        let headers = reader
+            .headers()
+            .map_err(|e| BlocklistError::Unreadable(e.to_string()))?
+            .clone();
+        for result in reader.records() {
+            let row = result.map_err(|e| BlocklistError::Unreadable(e.to_string()))?;
+            let line = row.position().map_or(0, |position| position.line());
+            let record: BlocklistRecord = row
+                .deserialize(Some(&headers))
+                .map_err(|e| BlocklistError::Unreadable(e.to_string()))?;
+            let inserted = match record.scope {
+                Blocked::Client => record
+                    .id
+                    .parse::<ClientID>()
+                    .map(|id| blocklist.clients.insert(id)),
+                Blocked::Partner => record
+                    .id
+                    .parse::<PartnerID>()
+                    .map(|id| blocklist.partners.insert(id)),
+            };
+            inserted.map_err(|_| BlocklistError::InvalidEntry(line))?;
+        }
+        Ok(blocklist)
+    }
+
+    // Reads again the file it was opened from, keeping what it had if the file can't be read or is not valid.
+    pub fn reload(&mut self) -> Result<(), BlocklistError> {
+        let path = self.path.clone().ok_or(BlocklistError::NothingToReload)?;
+        *self = Blocklist {
+            watched: self.watched,
+            checked_at: self.checked_at,
+            ..Blocklist::open(&path)?
+        };
+        Ok(())
+    }
+
+    // Reads the file again if it was modified since it was last read, telling if it did.
+    // A modification that can't be read is not tried again until the file is modified once more.
+    pub fn reload_if_modified(&mut self) -> Result<bool, BlocklistError> {
+        let modified = match &self.path {
+            Some(path) => fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok(),
+            None => return Ok(false),
+        };
+        if modified.is_none() || modified == self.modified {
+            return Ok(false);
+        }
+        self.modified = modified;
+        self.reload().map(|_| true)
+    }
+
+    // Has the file looked at for changes, so input streamed without any admin interface still gets the new blocklist.
+    pub fn watch(&mut self) {
+        self.watched = true;
+    }
+
+    // When watched, reloads the file if it was modified, looking at it at most once every `WATCH_INTERVAL`.
+    pub fn refresh(&mut self) -> Result<bool, BlocklistError> {
+        let now = Instant::now();
+        if !self.watched
+            || self
+                .checked_at
+                .is_some_and(|checked_at| now.duration_since(checked_at) < WATCH_INTERVAL)
+        {
+            return Ok(false);
+        }
+        self.checked_at = Some(now);
+        self.reload_if_modified()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty() && self.partners.is_empty()
+    }
+
+    pub fn blocks_client(&self, client_id: ClientID) -> bool {
+        self.clients.contains(&client_id)
+    }
+
+    pub fn blocks_partner(&self, partner: Option<PartnerID>) -> bool {
+        partner.is_some_and(|partner| self.partners.contains(&partner))
+    }
+
+    // How many clients and partners are blocked.
+    pub fn len(&self) -> (usize, usize) {
+        (self.clients.len(), self.partners.len())
+    }
+}
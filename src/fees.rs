@@ -0,0 +1,175 @@
+use std::{collections::HashMap, error, fmt, fs::File, io::Read};
+
+use fraction::Zero;
+use serde::Deserialize;
+
+use crate::{
+    input_ingestion::get_csv_reader_from,
+    models::{
+        shared::{Amount, ClientID, Currency},
+        transaction::{currency_code, Operation, Transaction, TransactionType},
+    },
+};
+
+#[derive(Debug)]
+pub enum FeesError {
+    Unreadable(String),
+    // The fee at this line of the fees file is not made of non negative amounts,
+    // or it is not for deposits or withdrawals.
+    InvalidFee(u64),
+}
+
+impl fmt::Display for FeesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FeesError::Unreadable(reason) => write!(f, "Unable to read the fees: {}", reason),
+            FeesError::InvalidFee(line) => write!(f, "The fee at line {} is not valid", line),
+        }
+    }
+}
+
+impl error::Error for FeesError {}
+
+// A row of the fees file, for example:
+// client, type, from_amount, flat, percentage, currency
+//       , withdrawal, 0, 0.5, 1,
+//       , withdrawal, 1000, 0, 0.5,
+//       , withdrawal, 0, 0.1, 1, USD
+//      7, withdrawal, 0, 0, 0,
+#[derive(Debug, Deserialize)]
+struct FeeRecord {
+    // Empty for the fee every client pays unless they have their own.
+    #[serde(default)]
+    client: Option<ClientID>,
+    #[serde(rename = "type")]
+    tx_type: TransactionType,
+    #[serde(default)]
+    from_amount: String,
+    #[serde(default)]
+    flat: String,
+    #[serde(default)]
+    percentage: String,
+    // An optional column, absent or empty for the default currency.
+    #[serde(default)]
+    currency: Option<String>,
+}
+
+// What a deposit or withdrawal of at least `from_amount` is charged: a flat amount plus a percentage of it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fee {
+    pub from_amount: Amount,
+    pub flat: Amount,
+    pub percentage: Amount,
+}
+
+impl Fee {
+    pub fn of(&self, amount: Amount) -> Amount {
+        self.flat + amount * self.percentage / Amount::from(100)
+    }
+}
+
+// The fees charged on deposits and withdrawals, collected into the house account.
+// The tiers of a type are the fees with different `from_amount`s, the one with the highest `from_amount`
+// not above the amount of the transaction applies. The fees of a client replace the ones of everybody else.
+// Fees are set per currency, in which their flat amounts and tiers are, so transactions in a currency without
+// fees are charged none.
+#[derive(Debug, Clone, Default)]
+pub struct FeeSchedule {
+    pub house: ClientID,
+    fees: HashMap<(Option<ClientID>, TransactionType, Option<Currency>), Vec<Fee>>,
+}
+
+impl FeeSchedule {
+    pub fn new(house: ClientID) -> Self {
+        Self {
+            house,
+            fees: HashMap::new(),
+        }
+    }
+
+    pub fn open(path: &str, house: ClientID) -> Result<Self, FeesError> {
+        let file = File::open(path).map_err(|e| FeesError::Unreadable(e.to_string()))?;
+        FeeSchedule::read_from(file, house)
+    }
+
+    pub fn read_from<R: Read>(input: R, house: ClientID) -> Result<Self, FeesError> {
+        let mut schedule = FeeSchedule::new(house);
+        let mut reader = get_csv_reader_from(input);
+        let headers = reader
+            .headers()
+            .map_err(|e| FeesError::Unreadable(e.to_string()))?
+            .clone();
+        for result in reader.records() {
+            let row = result.map_err(|e| FeesError::Unreadable(e.to_string()))?;
+            let line = row.position().map_or(0, |position| position.line());
+            let record: FeeRecord = row
+                .deserialize(Some(&headers))
+                .map_err(|e| FeesError::Unreadable(e.to_string()))?;
+            if !matches!(
+                record.tx_type,
+                TransactionType::Deposit | TransactionType::Withdrawal
+            ) {
+                return Err(FeesError::InvalidFee(line));
+            }
+            let fee = Fee {
+                from_amount: amount_from(&record.from_amount).ok_or(FeesError::InvalidFee(line))?,
+                flat: amount_from(&record.flat).ok_or(FeesError::InvalidFee(line))?,
+                percentage: amount_from(&record.percentage).ok_or(FeesError::InvalidFee(line))?,
+            };
+            let currency = record.currency.as_deref().and_then(currency_code);
+            schedule.insert(record.client, record.tx_type, currency, fee);
+        }
+        Ok(schedule)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fees.is_empty()
+    }
+
+    pub fn insert(
+        &mut self,
+        client: Option<ClientID>,
+        tx_type: TransactionType,
+        currency: Option<Currency>,
+        fee: Fee,
+    ) {
+        let fees = self.fees.entry((client, tx_type, currency)).or_default();
+        fees.retain(|tier| tier.from_amount != fee.from_amount);
+        fees.push(fee);
+        fees.sort_by_key(|tier| tier.from_amount);
+    }
+
+    // The fee charged on the transaction, in its currency. The house account pays none,
+    // and a deposit is never charged more than what it brings.
+    pub fn fee_for(&self, tx: &Transaction) -> Amount {
+        let amount = match tx.operation {
+            Operation::Deposit(val) | Operation::Withdrawal(val) if tx.client_id != self.house => {
+                val
+            }
+            _ => return Amount::zero(),
+        };
+        let (tx_type, currency) = (tx.tx_type(), tx.currency.clone());
+        let tiers = self
+            .fees
+            .get(&(Some(tx.client_id), tx_type, currency.clone()))
+            .or_else(|| self.fees.get(&(None, tx_type, currency)));
+        let fee = tiers
+            .and_then(|tiers| tiers.iter().rev().find(|tier| tier.from_amount <= amount))
+            .map_or_else(Amount::zero, |tier| tier.of(amount));
+        match tx.operation {
+            Operation::Deposit(_) => fee.min(amount),
+            _ => fee,
+        }
+    }
+}
+
+// Empty amounts stand for zero.
+fn amount_from(value: &str) -> Option<Amount> {
+    if value.is_empty() {
+        return Some(Amount::zero());
+    }
+    value
+        .parse::<Amount>()
+        .ok()
+        .filter(|amount| *amount >= Amount::zero())
+}
@@ -0,0 +1,399 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Serialize;
+use tokio::net::TcpListener;
+
+use crate::{
+    input_ingestion::get_csv_reader_from,
+    models::{
+        account::{Account, Balance, Processed},
+        disputes::{Dispute, DisputeState},
+        money::Rounding,
+        restrictions::{AccountStatus, Restriction},
+        shared::{ClientID, Currency, TransactionID},
+        transaction::Transaction,
+    },
+    payments_engine::{
+        deserialize_transaction, fatal_error_from, InputProcessingError, PaymentsEngine,
+    },
+    rejections::RejectionReason,
+    storage::{AccountStore, DisputeStore, TransactionStore},
+};
+
+// The engine shared by every request.
+pub type SharedEngine<A, T, D> = Arc<Mutex<PaymentsEngine<A, T, D>>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutcomeStatus {
+    Applied,
+    // Nothing changed, as for a repeated transaction ID or a resolve of a transaction that is not disputed.
+    Ignored,
+    Rejected,
+}
+
+// What happened to one submitted transaction, so partners get immediate feedback on it.
+#[derive(Debug, Serialize)]
+pub struct TransactionOutcome {
+    // Only for the rows of a batch, where the line in the CSV tells which one it is.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<u64>,
+    pub status: OutcomeStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<RejectionReason>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl TransactionOutcome {
+    fn applied() -> Self {
+        Self {
+            line: None,
+            status: OutcomeStatus::Applied,
+            reason: None,
+            error: None,
+        }
+    }
+
+    fn ignored() -> Self {
+        Self {
+            line: None,
+            status: OutcomeStatus::Ignored,
+            reason: None,
+            error: None,
+        }
+    }
+
+    fn rejected(reason: RejectionReason, error: String) -> Self {
+        Self {
+            line: None,
+            status: OutcomeStatus::Rejected,
+            reason: Some(reason),
+            error: Some(error),
+        }
+    }
+}
+
+// An account as rendered in the output, with its amounts printed with 4 digits of precision.
+// The funds in other currencies than the default one are in `currencies`.
+#[derive(Debug, Serialize)]
+pub struct AccountView {
+    pub client: ClientID,
+    pub available: String,
+    pub held: String,
+    pub total: String,
+    pub locked: bool,
+    pub status: AccountStatus,
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    pub restrictions: BTreeSet<Restriction>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub currencies: BTreeMap<Currency, BalanceView>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BalanceView {
+    pub available: String,
+    pub held: String,
+    pub total: String,
+}
+
+impl BalanceView {
+    fn new(balance: &Balance, rounding: Rounding) -> Self {
+        Self {
+            available: rounding.render(&balance.get_available()),
+            held: rounding.render(&balance.held),
+            total: rounding.render(&balance.total),
+        }
+    }
+}
+
+impl AccountView {
+    fn new(account: &Account, rounding: Rounding) -> Self {
+        Self {
+            client: account.client_id(),
+            available: rounding.render(&account.get_available()),
+            held: rounding.render(&account.held),
+            total: rounding.render(&account.total),
+            locked: account.is_locked(),
+            status: account.status(),
+            restrictions: account.restrictions.clone(),
+            currencies: account
+                .balances
+                .iter()
+                .map(|(currency, balance)| (currency.clone(), BalanceView::new(balance, rounding)))
+                .collect(),
+        }
+    }
+}
+
+// A dispute with every state it went through, and the amounts in dispute and charged back rendered as in the output.
+#[derive(Debug, Serialize)]
+pub struct DisputeView {
+    pub client_id: ClientID,
+    pub tx_id: TransactionID,
+    pub state: DisputeState,
+    pub reopenings: u32,
+    pub history: Vec<DisputeState>,
+    pub holds_funds: bool,
+    // Absent for disputes from before partial ones, which are always on the whole transaction.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outstanding: Option<String>,
+    pub charged_back: String,
+}
+
+impl DisputeView {
+    fn new(dispute: &Dispute, rounding: Rounding) -> Self {
+        Self {
+            client_id: dispute.client_id,
+            tx_id: dispute.tx_id,
+            state: dispute.state,
+            reopenings: dispute.reopenings,
+            history: dispute.history.clone(),
+            holds_funds: dispute.holds_funds,
+            outstanding: dispute
+                .outstanding
+                .map(|outstanding| rounding.render(&outstanding)),
+            charged_back: rounding.render(&dispute.charged_back),
+        }
+    }
+}
+
+// The errors that prevent the engine from processing anything, as opposed to a rejected transaction.
+pub struct ApiError(InputProcessingError);
+
+impl From<InputProcessingError> for ApiError {
+    fn from(error: InputProcessingError) -> Self {
+        ApiError(error)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match self.0 {
+            InputProcessingError::UnavailableStorage(_) => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, self.0.to_string()).into_response()
+    }
+}
+
+// The routes of the API:
+// - `POST /transactions` takes one transaction as JSON (with the amount as a string, like `"1.5"`, and an optional currency).
+// - `POST /transactions/batch` takes CSV in the same format as the input files, headers included.
+// - `GET /accounts/:client` returns one account.
+// - `GET /accounts/locked` returns every locked account.
+// - `GET /disputes` returns every pending dispute.
+// - `GET /disputes/:tx` returns the dispute of a transaction with every state it went through.
+pub fn router<A, T, D>(engine: SharedEngine<A, T, D>) -> Router
+where
+    A: AccountStore + Send + 'static,
+    T: TransactionStore + Send + 'static,
+    D: DisputeStore + Send + 'static,
+{
+    Router::new()
+        .route("/transactions", post(post_transaction::<A, T, D>))
+        .route("/transactions/batch", post(post_batch::<A, T, D>))
+        .route("/accounts/locked", get(get_locked_accounts::<A, T, D>))
+        .route("/accounts/:client", get(get_account::<A, T, D>))
+        .route("/disputes", get(get_disputes::<A, T, D>))
+        .route("/disputes/:tx", get(get_dispute::<A, T, D>))
+        .with_state(engine)
+}
+
+// Serves the engine, which may be shared with the admin interface.
+pub async fn serve<A, T, D>(listener: TcpListener, engine: SharedEngine<A, T, D>) -> io::Result<()>
+where
+    A: AccountStore + Send + 'static,
+    T: TransactionStore + Send + 'static,
+    D: DisputeStore + Send + 'static,
+{
+    axum::serve(listener, router(engine)).await
+}
+
+async fn post_transaction<A, T, D>(
+    State(engine): State<SharedEngine<A, T, D>>,
+    body: String,
+) -> Result<(StatusCode, Json<TransactionOutcome>), ApiError>
+where
+    A: AccountStore,
+    T: TransactionStore,
+    D: DisputeStore,
+{
+    let tx = serde_json::from_str::<Transaction>(&body)
+        .map_err(|e| TransactionOutcome::rejected(RejectionReason::from(&e), e.to_string()));
+    let mut pe = lock(&engine);
+    let outcome = match tx {
+        Ok(tx) => apply(&mut pe, tx)?,
+        Err(outcome) => outcome,
+    };
+    flush_journal(&mut pe)?;
+    let status = match outcome.status {
+        OutcomeStatus::Applied | OutcomeStatus::Ignored => StatusCode::OK,
+        OutcomeStatus::Rejected => StatusCode::UNPROCESSABLE_ENTITY,
+    };
+    Ok((status, Json(outcome)))
+}
+
+async fn post_batch<A, T, D>(
+    State(engine): State<SharedEngine<A, T, D>>,
+    body: String,
+) -> Result<Json<Vec<TransactionOutcome>>, ApiError>
+where
+    A: AccountStore,
+    T: TransactionStore,
+    D: DisputeStore,
+{
+    let mut reader = get_csv_reader_from(body.as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(InputProcessingError::UnreadableInput)?
+        .clone();
+    let mut outcomes = vec![];
+    let mut pe = lock(&engine);
+    for result in reader.records() {
+        let (line, mut outcome) = match result {
+            Ok(record) => {
+                let line = record.position().map(|position| position.line());
+                let outcome = match deserialize_transaction(&record, Some(&headers)) {
+                    Ok(tx) => apply(&mut pe, tx)?,
+                    Err(e) => {
+                        TransactionOutcome::rejected(RejectionReason::from(&e), e.to_string())
+                    }
+                };
+                (line, outcome)
+            }
+            Err(e) => (
+                e.position().map(|position| position.line()),
+                TransactionOutcome::rejected(RejectionReason::MalformedRecord, e.to_string()),
+            ),
+        };
+        outcome.line = line;
+        outcomes.push(outcome);
+    }
+    flush_journal(&mut pe)?;
+    Ok(Json(outcomes))
+}
+
+async fn get_account<A, T, D>(
+    State(engine): State<SharedEngine<A, T, D>>,
+    Path(client): Path<ClientID>,
+) -> Result<Response, ApiError>
+where
+    A: AccountStore,
+    T: TransactionStore,
+    D: DisputeStore,
+{
+    let pe = lock(&engine);
+    let account = pe
+        .accounts
+        .get_account(client)
+        .map_err(InputProcessingError::UnavailableStorage)?;
+    match account {
+        Some(account) => Ok(Json(AccountView::new(&account, pe.rounding)).into_response()),
+        None => Ok(StatusCode::NOT_FOUND.into_response()),
+    }
+}
+
+async fn get_locked_accounts<A, T, D>(
+    State(engine): State<SharedEngine<A, T, D>>,
+) -> Result<Json<Vec<AccountView>>, ApiError>
+where
+    A: AccountStore,
+    T: TransactionStore,
+    D: DisputeStore,
+{
+    let pe = lock(&engine);
+    let accounts = pe
+        .accounts
+        .all_accounts()
+        .map_err(InputProcessingError::UnavailableStorage)?;
+    Ok(Json(
+        accounts
+            .iter()
+            .filter(|account| account.is_locked())
+            .map(|account| AccountView::new(account, pe.rounding))
+            .collect(),
+    ))
+}
+
+async fn get_disputes<A, T, D>(
+    State(engine): State<SharedEngine<A, T, D>>,
+) -> Result<Json<Vec<DisputeView>>, ApiError>
+where
+    A: AccountStore,
+    T: TransactionStore,
+    D: DisputeStore,
+{
+    let pe = lock(&engine);
+    let disputes = pe
+        .pending_disputes()
+        .map_err(InputProcessingError::UnavailableStorage)?;
+    Ok(Json(
+        disputes
+            .iter()
+            .map(|dispute| DisputeView::new(dispute, pe.rounding))
+            .collect(),
+    ))
+}
+
+async fn get_dispute<A, T, D>(
+    State(engine): State<SharedEngine<A, T, D>>,
+    Path(tx): Path<TransactionID>,
+) -> Result<Response, ApiError>
+where
+    A: AccountStore,
+    T: TransactionStore,
+    D: DisputeStore,
+{
+    let pe = lock(&engine);
+    let dispute = pe
+        .dispute_history(tx)
+        .map_err(InputProcessingError::UnavailableStorage)?;
+    match dispute {
+        Some(dispute) => Ok(Json(DisputeView::new(&dispute, pe.rounding)).into_response()),
+        None => Ok(StatusCode::NOT_FOUND.into_response()),
+    }
+}
+
+// Processes the transaction, turning the reason it could not be processed into its outcome.
+fn apply<A: AccountStore, T: TransactionStore, D: DisputeStore>(
+    pe: &mut PaymentsEngine<A, T, D>,
+    tx: Transaction,
+) -> Result<TransactionOutcome, InputProcessingError> {
+    pe.check_journal()?;
+    match pe.process(tx) {
+        Ok(Processed::Applied { .. }) => Ok(TransactionOutcome::applied()),
+        Ok(Processed::Ignored) => Ok(TransactionOutcome::ignored()),
+        Err(e) => match RejectionReason::try_from(&e) {
+            Ok(reason) => Ok(TransactionOutcome::rejected(reason, e.to_string())),
+            Err(()) => Err(fatal_error_from(e)),
+        },
+    }
+}
+
+// Every request leaves what it applied in the journal, if any, before answering.
+fn flush_journal<A, T, D>(pe: &mut PaymentsEngine<A, T, D>) -> Result<(), InputProcessingError> {
+    match pe.journal.as_mut() {
+        Some(journal) => journal
+            .flush()
+            .map_err(InputProcessingError::UnwritableJournal),
+        None => Ok(()),
+    }
+}
+
+fn lock<A, T, D>(engine: &SharedEngine<A, T, D>) -> MutexGuard<'_, PaymentsEngine<A, T, D>> {
+    engine
+        .lock()
+        .expect("A request panicked while using the engine")
+}
@@ -1,20 +1,32 @@
 use std::{
     env, error, fmt,
     fs::{self, File},
+    io::{self, Read},
 };
 
 use csv::{Reader, ReaderBuilder, Trim};
 
+// The filename that, by convention, makes the program read its input from stdin.
+pub const STDIN_FILENAME: &str = "-";
+// The option that, given instead of a filename, makes the program read transactions from TCP connections.
+pub const SERVE_OPTION: &str = "--serve";
+// The option that, given instead of a filename, makes the program serve the HTTP API.
+pub const HTTP_OPTION: &str = "--http";
+
 #[derive(Debug)]
 pub enum InputAccessError {
     MissingInputFilename,
     FileNotFound(String),
     UnableToCreateReader(String),
+    InvalidOption(String),
 }
 
 impl fmt::Display for InputAccessError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Invalid input")
+        match self {
+            InputAccessError::InvalidOption(reason) => write!(f, "Invalid option: {}", reason),
+            _ => write!(f, "Invalid input"),
+        }
     }
 }
 
@@ -22,15 +34,58 @@ impl error::Error for InputAccessError {}
 
 // We expect to run the program like:
 // cargo run -- transactions.csv > accounts.csv
+// or, to have it reading the input from stdin:
+// cat transactions.csv | cargo run -- - > accounts.csv
 // Hence we use the first argument as input filename.
 pub fn get_input_filename() -> Option<String> {
     env::args().nth(1)
 }
 
+// Returns the value following the given option name, if present.
+// For example, `get_option("--rejections")` for:
+// cargo run -- transactions.csv --rejections rejected.csv
+pub fn get_option(name: &str) -> Option<String> {
+    let mut args = env::args().skip(2);
+    while let Some(arg) = args.next() {
+        if arg == name {
+            return args.next();
+        }
+    }
+    None
+}
+
+// Tells if the given flag, an option without a value, is present. For example, `has_flag("--output-status")` for:
+// cargo run -- transactions.csv --output-status
+pub fn has_flag(name: &str) -> bool {
+    env::args().skip(2).any(|arg| arg == name)
+}
+
+// Instead of processing an input file, the program can serve partners streaming their input over TCP:
+// cargo run -- --serve 127.0.0.1:7878
+pub fn get_serve_address() -> Option<String> {
+    get_address_of(SERVE_OPTION)
+}
+
+// Or it can serve them an HTTP API:
+// cargo run -- --http 127.0.0.1:8080
+pub fn get_http_address() -> Option<String> {
+    get_address_of(HTTP_OPTION)
+}
+
+fn get_address_of(server_option: &str) -> Option<String> {
+    match get_input_filename() {
+        Some(arg) if arg == server_option => env::args().nth(2),
+        _ => None,
+    }
+}
+
 pub fn input_filename() -> Result<String, InputAccessError> {
     match get_input_filename() {
         None => Err(InputAccessError::MissingInputFilename),
         Some(filename) => {
+            if filename == STDIN_FILENAME {
+                return Ok(filename);
+            }
             let file_exists = fs::metadata(&filename).is_ok();
             if !file_exists {
                 return Err(InputAccessError::FileNotFound(filename));
@@ -40,14 +95,31 @@ pub fn input_filename() -> Result<String, InputAccessError> {
     }
 }
 
-pub fn get_csv_reader(path: String) -> Result<Reader<File>, InputAccessError> {
-    let reader = ReaderBuilder::new()
+// The CSV configuration expected for the input, regardless of where it comes from.
+pub fn csv_reader_builder() -> ReaderBuilder {
+    let mut builder = ReaderBuilder::new();
+    // Flexible so rows with a missing trailing amount (disputes, resolves, chargebacks) are still read
+    // and malformed rows reach deserialization, where they can be reported as rejected.
+    builder
         .has_headers(true)
         .trim(Trim::All)
         .delimiter(b',')
-        .from_path(path);
-    match reader {
-        Ok(r) => Ok(r),
+        .flexible(true);
+    builder
+}
+
+// Creates a reader for any source of input (stdin, sockets, pipes, decompressors, etc).
+pub fn get_csv_reader_from<R: Read>(input: R) -> Reader<R> {
+    csv_reader_builder().from_reader(input)
+}
+
+// Creates a reader for the input at the given path or for stdin when the path is `STDIN_FILENAME`.
+pub fn get_csv_reader(path: String) -> Result<Reader<Box<dyn Read>>, InputAccessError> {
+    if path == STDIN_FILENAME {
+        return Ok(get_csv_reader_from(Box::new(io::stdin())));
+    }
+    match File::open(path) {
+        Ok(file) => Ok(get_csv_reader_from(Box::new(file))),
         Err(e) => Err(InputAccessError::UnableToCreateReader(e.to_string())),
     }
 }
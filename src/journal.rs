@@ -0,0 +1,239 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error, fmt,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+};
+
+use fraction::Zero;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    admin::AdminOperation,
+    models::{
+        account::{Account, Balance},
+        money::Money,
+        restrictions::Restriction,
+        shared::{Amount, ClientID, Currency, PartnerID, Timestamp, TransactionID},
+        transaction::{Operation, Transaction},
+    },
+};
+
+// One operation applied by the engine together with the state of its account right after it.
+// Amounts are serialized as exact fractions so replaying them is lossless.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub sequence: u64,
+    pub client_id: ClientID,
+    #[serde(flatten)]
+    pub applied: Applied,
+    pub total: Amount,
+    pub held: Amount,
+    pub locked: bool,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub restrictions: BTreeSet<Restriction>,
+    // The funds of the account in other currencies than the default one.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub balances: BTreeMap<Currency, Balance>,
+}
+
+// What the engine applied: a transaction of the input or an operation of an operator.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Applied {
+    Transaction(JournaledTransaction),
+    Admin { admin: AdminOperation },
+    // A fee collected into the house account (or given back from it) for a transaction of another account.
+    Fee { collected_fee: Money },
+    // The clock moved forward by a transaction that was then rejected, expiring the disputes pending for too long.
+    // The ones that got applied move it again when replayed.
+    Clock { clock: Timestamp },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JournaledTransaction {
+    pub tx_id: TransactionID,
+    pub operation: Operation,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<Currency>,
+    // The fee the transaction was charged, so replays don't depend on the fee schedule.
+    #[serde(default, skip_serializing_if = "Zero::is_zero")]
+    pub fee: Amount,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disputed_amount: Option<Amount>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<Timestamp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partner: Option<PartnerID>,
+}
+
+impl JournaledTransaction {
+    pub fn transaction(&self, client_id: ClientID) -> Transaction {
+        Transaction {
+            client_id,
+            tx_id: self.tx_id,
+            operation: self.operation.clone(),
+            currency: self.currency.clone(),
+            fee: self.fee,
+            disputed_amount: self.disputed_amount,
+            timestamp: self.timestamp,
+            partner: self.partner,
+        }
+    }
+}
+
+impl JournalEntry {
+    // Tells if the given account is in the state this entry recorded.
+    pub fn matches(&self, account: &Account) -> bool {
+        self.total == account.total
+            && self.held == account.held
+            && self.locked == account.is_locked()
+            // Entries from before restrictions existed only tell if the account was locked.
+            && (self.restrictions.is_empty() || self.restrictions == account.restrictions)
+            && self.balances == account.balances
+    }
+}
+
+#[derive(Debug)]
+pub enum JournalError {
+    Unreadable(String),
+    // Replaying the entry with this sequence number did not lead to the state it recorded.
+    Diverged(u64),
+}
+
+impl fmt::Display for JournalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JournalError::Unreadable(reason) => write!(f, "Unable to read the journal: {}", reason),
+            JournalError::Diverged(sequence) => write!(
+                f,
+                "Replaying journal entry {} did not reproduce its recorded account state",
+                sequence
+            ),
+        }
+    }
+}
+
+impl error::Error for JournalError {}
+
+// An append-only record, one JSON entry per line, of every operation the engine applied.
+// Write errors are kept for `check` so the engine can process and journal in one go and still stop as soon as
+// the journal is not reliable anymore. Nothing is appended after the first one, as replays can't tell the gap.
+pub struct Journal {
+    output: Box<dyn Write + Send>,
+    next_sequence: u64,
+    error: Option<io::Error>,
+}
+
+impl Journal {
+    pub fn new(output: impl Write + Send + 'static) -> Self {
+        Self {
+            output: Box::new(output),
+            next_sequence: 1,
+            error: None,
+        }
+    }
+
+    // Opens (or creates) the journal at the given path to keep appending to it.
+    pub fn open(path: &str) -> io::Result<Self> {
+        let existing_entries = match File::open(path) {
+            Ok(file) => BufReader::new(file).lines().count() as u64,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e),
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut journal = Journal::new(BufWriter::new(file));
+        journal.next_sequence = existing_entries + 1;
+        Ok(journal)
+    }
+
+    pub fn append(&mut self, tx: &Transaction, account: &Account) {
+        let applied = Applied::Transaction(JournaledTransaction {
+            tx_id: tx.tx_id,
+            operation: tx.operation.clone(),
+            currency: tx.currency.clone(),
+            fee: tx.fee,
+            disputed_amount: tx.disputed_amount,
+            timestamp: tx.timestamp,
+            partner: tx.partner,
+        });
+        self.write(applied, account);
+    }
+
+    // Operations of operators are journaled as any other, so they are part of the audit trail and replayed.
+    pub fn append_admin(&mut self, operation: &AdminOperation, account: &Account) {
+        self.write(
+            Applied::Admin {
+                admin: operation.clone(),
+            },
+            account,
+        );
+    }
+
+    // The house account is journaled with what it collects, so its balance is part of the audit trail too.
+    pub fn append_fee(&mut self, fee: &Money, house: &Account) {
+        self.write(
+            Applied::Fee {
+                collected_fee: fee.clone(),
+            },
+            house,
+        );
+    }
+
+    // Journaled with the account of the client of the rejected transaction, as expiring disputes may have changed it.
+    pub fn append_clock(&mut self, clock: Timestamp, account: &Account) {
+        self.write(Applied::Clock { clock }, account);
+    }
+
+    fn write(&mut self, applied: Applied, account: &Account) {
+        if self.error.is_some() {
+            return;
+        }
+        let entry = JournalEntry {
+            sequence: self.next_sequence,
+            client_id: account.client_id(),
+            applied,
+            total: account.total,
+            held: account.held,
+            locked: account.is_locked(),
+            restrictions: account.restrictions.clone(),
+            balances: account.balances.clone(),
+        };
+        let written = serde_json::to_writer(&mut self.output, &entry)
+            .map_err(io::Error::from)
+            .and_then(|_| self.output.write_all(b"\n"));
+        match written {
+            Ok(()) => self.next_sequence += 1,
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    // Returns the first error found while appending, if any, every time it's called.
+    pub fn check(&self) -> io::Result<()> {
+        match &self.error {
+            Some(e) => Err(io::Error::new(e.kind(), e.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.check()?;
+        if let Err(e) = self.output.flush() {
+            self.error = Some(e);
+        }
+        self.check()
+    }
+}
+
+// Reads the entries of a journal in the order they were appended.
+pub fn read_journal<R: io::Read>(
+    journal: R,
+) -> impl Iterator<Item = Result<JournalEntry, JournalError>> {
+    BufReader::new(journal)
+        .lines()
+        .filter(|line| !matches!(line, Ok(l) if l.trim().is_empty()))
+        .map(|line| {
+            let line = line.map_err(|e| JournalError::Unreadable(e.to_string()))?;
+            serde_json::from_str(&line).map_err(|e| JournalError::Unreadable(e.to_string()))
+        })
+}
@@ -1,5 +1,21 @@
+pub mod admin;
+pub mod admin_server;
+pub mod blocklist;
+pub mod fees;
+pub mod http_api;
 pub mod input_ingestion;
+pub mod journal;
+pub mod limits;
 pub mod models;
 pub mod payments_engine;
+pub mod rates;
+pub mod ratios;
+pub mod rejections;
+pub mod reports;
+pub mod rules;
+pub mod server;
+pub mod sharded_engine;
+pub mod snapshot;
+pub mod storage;
 #[cfg(test)]
 mod tests;
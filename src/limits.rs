@@ -0,0 +1,90 @@
+use std::{collections::HashMap, error, fmt, fs::File, io::Read};
+
+use fraction::Zero;
+use serde::Deserialize;
+
+use crate::{
+    input_ingestion::get_csv_reader_from,
+    models::shared::{Amount, ClientID},
+};
+
+#[derive(Debug)]
+pub enum LimitsError {
+    Unreadable(String),
+    // The credit limit at this line of the limits file is not a non negative amount.
+    InvalidLimit(u64),
+}
+
+impl fmt::Display for LimitsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitsError::Unreadable(reason) => {
+                write!(f, "Unable to read the credit limits: {}", reason)
+            }
+            LimitsError::InvalidLimit(line) => {
+                write!(f, "The credit limit at line {} is not valid", line)
+            }
+        }
+    }
+}
+
+impl error::Error for LimitsError {}
+
+// A row of the limits file, for example:
+// client, credit_limit
+//      7, 500.0
+#[derive(Debug, Deserialize)]
+struct LimitRecord {
+    client: ClientID,
+    credit_limit: String,
+}
+
+// How far below zero the available funds of each client can go, in the default currency.
+// Clients without a limit can only use what they have.
+#[derive(Debug, Clone, Default)]
+pub struct CreditLimits {
+    limits: HashMap<ClientID, Amount>,
+}
+
+impl CreditLimits {
+    pub fn open(path: &str) -> Result<Self, LimitsError> {
+        let file = File::open(path).map_err(|e| LimitsError::Unreadable(e.to_string()))?;
+        CreditLimits::read_from(file)
+    }
+
+    pub fn read_from<R: Read>(input: R) -> Result<Self, LimitsError> {
+        let mut limits = CreditLimits::default();
+        let mut reader = get_csv_reader_from(input);
+        let headers = reader
+            .headers()
+            .map_err(|e| LimitsError::Unreadable(e.to_string()))?
+            .clone();
+        for result in reader.records() {
+            let row = result.map_err(|e| LimitsError::Unreadable(e.to_string()))?;
+            let line = row.position().map_or(0, |position| position.line());
+            let record: LimitRecord = row
+                .deserialize(Some(&headers))
+                .map_err(|e| LimitsError::Unreadable(e.to_string()))?;
+            let limit = record
+                .credit_limit
+                .parse::<Amount>()
+                .ok()
+                .filter(|limit| *limit >= Amount::zero())
+                .ok_or(LimitsError::InvalidLimit(line))?;
+            limits.insert(record.client, limit);
+        }
+        Ok(limits)
+    }
+
+    pub fn insert(&mut self, client: ClientID, limit: Amount) {
+        self.limits.insert(client, limit);
+    }
+
+    // The credit limit of the client, zero when it has none.
+    pub fn of(&self, client: ClientID) -> Amount {
+        self.limits
+            .get(&client)
+            .copied()
+            .unwrap_or_else(Amount::zero)
+    }
+}
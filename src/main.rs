@@ -1,26 +1,404 @@
-use std::error::Error;
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    net::TcpListener,
+    sync::{Arc, Mutex},
+    thread,
+};
 
 use tpe::{
-    input_ingestion::{get_csv_reader, input_filename},
+    admin::open_admin_operations,
+    admin_server::AdminServer,
+    blocklist::Blocklist,
+    fees::FeeSchedule,
+    http_api,
+    input_ingestion::{
+        get_csv_reader, get_http_address, get_option, get_serve_address, has_flag, input_filename,
+        InputAccessError, STDIN_FILENAME,
+    },
+    journal::Journal,
+    limits::CreditLimits,
+    models::{
+        account::{render_accounts, render_accounts_with_status},
+        disputes::WithdrawalDisputePolicy,
+        money::Rounding,
+        shared::ClientID,
+    },
     payments_engine::PaymentsEngine,
+    rates::RateTable,
+    ratios::RatioMonitor,
+    rejections::{rejection_sink_to, IgnoredRejections, RejectionSink},
+    reports::{
+        write_dispute_report, write_flagged_report, write_ratio_report, write_stranded_report,
+    },
+    rules::RuleSet,
+    server::Server,
+    sharded_engine::ShardedPaymentsEngine,
+    snapshot::Snapshot,
+    storage::{sqlite, AccountStore, DisputeStore, TransactionStore},
 };
 
+// With `--serve <address>`, partners stream their input over TCP into one engine kept in memory
+// (or in the database given with `--sqlite`) until the program is stopped.
+fn serve(address: String) -> Result<(), Box<dyn Error>> {
+    match get_option("--sqlite") {
+        None => serve_with(address, PaymentsEngine::default()),
+        Some(path) => {
+            let (accounts, transactions, disputes) = sqlite::open(&path)?;
+            serve_with(
+                address,
+                PaymentsEngine::with_stores(accounts, transactions, disputes),
+            )
+        }
+    }
+}
+
+fn serve_with<A, T, D>(
+    address: String,
+    mut pe: PaymentsEngine<A, T, D>,
+) -> Result<(), Box<dyn Error>>
+where
+    A: AccountStore + Send + 'static,
+    T: TransactionStore + Send + 'static,
+    D: DisputeStore + Send + 'static,
+{
+    prepare(&mut pe)?;
+    let rejections = get_rejection_sink()?;
+    let listener = TcpListener::bind(&address)?;
+    eprintln!("Listening on {}", listener.local_addr()?);
+    let server = Server::new(pe, rejections);
+    serve_admin(server.engine_handle())?;
+    server.serve(listener);
+    Ok(())
+}
+
+// With `--http <address>`, partners submit transactions and query accounts through an HTTP API
+// served on top of one engine kept in memory (or in the database given with `--sqlite`).
+fn serve_http(address: String) -> Result<(), Box<dyn Error>> {
+    match get_option("--sqlite") {
+        None => serve_http_with(address, PaymentsEngine::default()),
+        Some(path) => {
+            let (accounts, transactions, disputes) = sqlite::open(&path)?;
+            serve_http_with(
+                address,
+                PaymentsEngine::with_stores(accounts, transactions, disputes),
+            )
+        }
+    }
+}
+
+fn serve_http_with<A, T, D>(
+    address: String,
+    mut pe: PaymentsEngine<A, T, D>,
+) -> Result<(), Box<dyn Error>>
+where
+    A: AccountStore + Send + 'static,
+    T: TransactionStore + Send + 'static,
+    D: DisputeStore + Send + 'static,
+{
+    prepare(&mut pe)?;
+    let engine = Arc::new(Mutex::new(pe));
+    serve_admin(Arc::clone(&engine))?;
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::bind(&address).await?;
+        eprintln!("Listening on http://{}", listener.local_addr()?);
+        http_api::serve(listener, engine).await
+    })?;
+    Ok(())
+}
+
+// With `--admin-serve <address>`, operators apply admin operations and reload the blocklist over TCP
+// on the served engine, on an address apart from the one of the partners.
+fn serve_admin<A, T, D>(engine: Arc<Mutex<PaymentsEngine<A, T, D>>>) -> Result<(), Box<dyn Error>>
+where
+    A: AccountStore + Send + 'static,
+    T: TransactionStore + Send + 'static,
+    D: DisputeStore + Send + 'static,
+{
+    if let Some(address) = get_option("--admin-serve") {
+        let listener = TcpListener::bind(&address)?;
+        eprintln!("Listening for operators on {}", listener.local_addr()?);
+        let admin = AdminServer::new(engine);
+        thread::spawn(move || admin.serve(listener));
+    }
+    Ok(())
+}
+
+// The engine keeps its state in memory unless a database is given with `--sqlite`.
+// With `--workers`, it processes in parallel with that many client-sharded workers (in memory only).
 fn process_input() -> Result<(), Box<dyn Error>> {
+    match (get_option("--sqlite"), get_workers()?) {
+        (None, None) => process_input_with(PaymentsEngine::default()),
+        (None, Some(workers)) => process_input_in_parallel(workers),
+        (Some(path), None) => {
+            let (accounts, transactions, disputes) = sqlite::open(&path)?;
+            process_input_with(PaymentsEngine::with_stores(
+                accounts,
+                transactions,
+                disputes,
+            ))
+        }
+        (Some(_), Some(_)) => Err(Box::new(InputAccessError::InvalidOption(
+            "--workers can't be used with --sqlite".to_owned(),
+        ))),
+    }
+}
+
+fn get_workers() -> Result<Option<usize>, InputAccessError> {
+    match get_option("--workers") {
+        None => Ok(None),
+        Some(workers) => match workers.parse::<usize>() {
+            Ok(count) if count > 0 => Ok(Some(count)),
+            _ => Err(InputAccessError::InvalidOption(format!(
+                "--workers must be a positive number, got {}",
+                workers
+            ))),
+        },
+    }
+}
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+fn get_days(option: &str) -> Result<Option<u64>, InputAccessError> {
+    match get_option(option) {
+        None => Ok(None),
+        Some(days) => days.parse::<u64>().map(Some).map_err(|_| {
+            InputAccessError::InvalidOption(format!(
+                "{} must be a number of days, got {}",
+                option, days
+            ))
+        }),
+    }
+}
+
+fn get_authorization_expiry() -> Result<Option<u32>, InputAccessError> {
+    match get_option("--authorization-expiry") {
+        None => Ok(None),
+        Some(transactions) => match transactions.parse::<u32>() {
+            Ok(count) if count > 0 => Ok(Some(count)),
+            _ => Err(InputAccessError::InvalidOption(format!(
+                "--authorization-expiry must be a positive number of transactions, got {}",
+                transactions
+            ))),
+        },
+    }
+}
+
+fn get_house_account() -> Result<ClientID, InputAccessError> {
+    match get_option("--house-account") {
+        None => Err(InputAccessError::InvalidOption(
+            "--fees needs the client collecting them with --house-account".to_owned(),
+        )),
+        Some(client) => client.parse::<ClientID>().map_err(|_| {
+            InputAccessError::InvalidOption(format!(
+                "--house-account must be a client ID, got {}",
+                client
+            ))
+        }),
+    }
+}
+
+fn process_input_in_parallel(workers: usize) -> Result<(), Box<dyn Error>> {
     let reader = get_csv_reader(input_filename()?).expect("CSV reader could not be created");
+    let mut rejections = get_rejection_sink()?;
     let mut pe = PaymentsEngine::default();
-    pe.process_transactions_from(reader)?;
-    render_output(&pe);
+    prepare(&mut pe)?;
+    let mut sharded = ShardedPaymentsEngine::split(pe, workers)?;
+    sharded.process_transactions_from(reader)?;
+    let pe = sharded.finish(rejections.as_mut())?;
+    save_snapshot(&pe)?;
+    save_dispute_report(&pe)?;
+    save_stranded_report(&pe)?;
+    save_flagged_report(&pe)?;
+    save_ratio_report(&pe)?;
+    render_output(&pe)?;
     Ok(())
 }
 
-fn render_output(payments_engine: &PaymentsEngine) {
-    println!("client, available, held, total, locked");
-    for (_, account) in payments_engine.accounts.iter() {
-        account.render_as_output_line();
+fn process_input_with<A: AccountStore, T: TransactionStore, D: DisputeStore>(
+    mut pe: PaymentsEngine<A, T, D>,
+) -> Result<(), Box<dyn Error>> {
+    let filename = input_filename()?;
+    let streaming = filename == STDIN_FILENAME;
+    let reader = get_csv_reader(filename).expect("CSV reader could not be created");
+    let mut rejections = get_rejection_sink()?;
+    prepare(&mut pe)?;
+    // Input streamed from stdin has no admin interface, so the blocklist is read again whenever its file changes.
+    if streaming {
+        pe.blocklist.watch();
     }
+    pe.process_transactions_reporting_to(reader, rejections.as_mut())?;
+    save_snapshot(&pe)?;
+    save_dispute_report(&pe)?;
+    save_stranded_report(&pe)?;
+    save_flagged_report(&pe)?;
+    save_ratio_report(&pe)?;
+    render_output(&pe)?;
+    Ok(())
+}
+
+// The engine starts from the state restored from the snapshot given with `--restore-from`, if any,
+// then rebuilds on top of it what is in the journal given with `--replay`, if any,
+// and appends what it applies to the journal given with `--journal`, if any.
+// The operations of operators in the file given with `--admin` are applied (and journaled) before any input.
+// Conversions use the rates given with `--rates`, and amounts are rendered with the rounding rule given with `--rounding`.
+// Deposits and withdrawals are charged the fees given with `--fees`, collected into the account given with `--house-account`.
+// Clients can go below zero down to the credit limits given with `--limits`.
+// Transactions are checked against the rules given with `--rules` before being processed.
+// Clients and partners whose dispute or chargeback ratio goes over the limits given with `--ratio-limits` can't deposit anymore.
+// Disputes on withdrawals follow the policy given with `--withdrawal-disputes`. Transactions can be disputed
+// for the days given with `--dispute-window-days`, and disputes pending for the days given with `--dispute-expiry-days` expire.
+// With `--settle-disputes-when-locked`, disputes already open on locked accounts can still be resolved or charged back.
+// Authorizations expire after the number of further transactions of their account given with `--authorization-expiry`.
+// Transactions of the clients, or through the partners, in the blocklist given with `--blocklist` are rejected.
+fn prepare<A: AccountStore, T: TransactionStore, D: DisputeStore>(
+    pe: &mut PaymentsEngine<A, T, D>,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = get_option("--rates") {
+        pe.rates = RateTable::open(&path)?;
+    }
+    if let Some(path) = get_option("--fees") {
+        pe.fees = FeeSchedule::open(&path, get_house_account()?)?;
+    }
+    if let Some(path) = get_option("--limits") {
+        pe.credit_limits = CreditLimits::open(&path)?;
+    }
+    if let Some(path) = get_option("--rules") {
+        pe.rules = RuleSet::open(&path)?;
+    }
+    if let Some(path) = get_option("--ratio-limits") {
+        pe.ratios = RatioMonitor::open(&path)?;
+    }
+    if let Some(policy) = get_option("--withdrawal-disputes") {
+        pe.dispute_rules.withdrawals = policy.parse::<WithdrawalDisputePolicy>()?;
+    }
+    if let Some(days) = get_days("--dispute-window-days")? {
+        pe.dispute_rules.filing_window = Some(days * SECONDS_PER_DAY);
+    }
+    if let Some(days) = get_days("--dispute-expiry-days")? {
+        pe.dispute_rules.expiry = Some(days * SECONDS_PER_DAY);
+    }
+    pe.dispute_rules.settle_when_locked = has_flag("--settle-disputes-when-locked");
+    pe.authorization_expiry = get_authorization_expiry()?;
+    if let Some(rounding) = get_option("--rounding") {
+        pe.rounding = rounding.parse::<Rounding>()?;
+    }
+    if let Some(path) = get_option("--restore-from") {
+        pe.restore(Snapshot::read_from(BufReader::new(File::open(path)?))?)?;
+    }
+    if let Some(path) = get_option("--replay") {
+        pe.replay(File::open(path)?)?;
+    }
+    // What was journaled was screened when it was applied, so only what comes after is checked against the blocklist.
+    if let Some(path) = get_option("--blocklist") {
+        pe.blocklist = Blocklist::open(&path)?;
+    }
+    if let Some(path) = get_option("--journal") {
+        pe.journal = Some(Journal::open(&path)?);
+    }
+    if let Some(path) = get_option("--admin") {
+        pe.apply_admin_operations(open_admin_operations(&path)?)?;
+    }
+    Ok(())
+}
+
+// The final state is saved to the file given with `--snapshot-to`, if any, so the next input can be processed on top of it.
+fn save_snapshot<A: AccountStore, T: TransactionStore, D: DisputeStore>(
+    payments_engine: &PaymentsEngine<A, T, D>,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = get_option("--snapshot-to") {
+        let mut output = BufWriter::new(File::create(path)?);
+        payments_engine.snapshot()?.write_to(&mut output)?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+// Every dispute of the run, with the states it went through, is written to the file given with `--disputes-report`, if any.
+fn save_dispute_report<A, T, D: DisputeStore>(
+    payments_engine: &PaymentsEngine<A, T, D>,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = get_option("--disputes-report") {
+        let disputes = payments_engine.disputes.all_disputes()?;
+        write_dispute_report(&disputes, BufWriter::new(File::create(path)?))?;
+    }
+    Ok(())
+}
+
+// The disputes left open on locked accounts are written to the file given with `--stranded-disputes-report`, if any.
+fn save_stranded_report<A: AccountStore, T: TransactionStore, D: DisputeStore>(
+    payments_engine: &PaymentsEngine<A, T, D>,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = get_option("--stranded-disputes-report") {
+        let stranded = payments_engine.stranded_disputes()?;
+        write_stranded_report(
+            &stranded,
+            payments_engine.rounding,
+            BufWriter::new(File::create(path)?),
+        )?;
+    }
+    Ok(())
+}
+
+// The transactions processed although rules flagged them are written to the file given with `--flagged-report`, if any.
+fn save_flagged_report<A, T, D>(
+    payments_engine: &PaymentsEngine<A, T, D>,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = get_option("--flagged-report") {
+        write_flagged_report(
+            &payments_engine.flagged,
+            BufWriter::new(File::create(path)?),
+        )?;
+    }
+    Ok(())
+}
+
+// Why clients and partners got restricted by their ratios is written to the file given with `--ratio-report`, if any.
+fn save_ratio_report<A, T, D>(
+    payments_engine: &PaymentsEngine<A, T, D>,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(path) = get_option("--ratio-report") {
+        write_ratio_report(
+            &payments_engine.ratios.triggers,
+            payments_engine.rounding,
+            BufWriter::new(File::create(path)?),
+        )?;
+    }
+    Ok(())
+}
+
+// Rejected records are written to the file given with `--rejections` (CSV, or JSONL if it ends in `.jsonl`).
+fn get_rejection_sink() -> Result<Box<dyn RejectionSink + Send>, Box<dyn Error>> {
+    match get_option("--rejections") {
+        None => Ok(Box::new(IgnoredRejections)),
+        Some(path) => Ok(rejection_sink_to(&path)?),
+    }
+}
+
+// With `--output-status`, the output also tells the status of every account and its restrictions.
+fn render_output<A: AccountStore, T, D>(
+    payments_engine: &PaymentsEngine<A, T, D>,
+) -> Result<(), Box<dyn Error>> {
+    let accounts = payments_engine.accounts.all_accounts()?;
+    let lines = match has_flag("--output-status") {
+        true => render_accounts_with_status(&accounts, payments_engine.rounding),
+        false => render_accounts(&accounts, payments_engine.rounding),
+    };
+    for line in lines {
+        println!("{}", line);
+    }
+    Ok(())
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
-    process_input()?;
+    if let Some(address) = get_serve_address() {
+        serve(address)?;
+    } else if let Some(address) = get_http_address() {
+        serve_http(address)?;
+    } else {
+        process_input()?;
+    }
     Ok(())
 }
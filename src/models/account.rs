@@ -1,21 +1,135 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
-use crate::models::transaction::{TransactionProcessingError, TransactionType};
+use fraction::Zero;
+use serde::{Deserialize, Serialize};
+
+use crate::models::transaction::{Operation, TransactionProcessingError};
+
+use crate::{
+    admin::AdminAction,
+    rates::RateTable,
+    storage::{DisputeStore, StorageError, TransactionStore},
+};
 
 use super::{
-    disputes::{Dispute, Disputes},
-    shared::{Amount, ClientID},
-    transaction::{Transaction, Transactions},
+    disputes::{Dispute, DisputeRules, WithdrawalDisputePolicy},
+    money::{Money, Rounding},
+    restrictions::{AccountStatus, Activity, Restriction},
+    shared::{Amount, ClientID, Currency, TransactionID},
+    transaction::Transaction,
 };
 
+// The headers row of the rendered accounts.
+pub const OUTPUT_HEADERS: &str = "client, available, held, total, locked";
+
+// The column added to the rendered accounts when there are funds in other currencies than the default one.
+pub const CURRENCY_HEADER: &str = "currency";
+
+// The column added to the rendered accounts when some account has a credit limit, with the credit it used.
+pub const CREDIT_HEADER: &str = "credit";
+
+// The columns added to the rendered accounts when their status is asked for.
+pub const STATUS_HEADERS: &str = "status, restrictions";
+
+// The funds of an account in one currency.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Balance {
+    pub total: Amount,
+    pub held: Amount,
+}
+
+impl Balance {
+    pub fn get_available(&self) -> Amount {
+        self.total - self.held
+    }
+}
+
+// What processing a transaction did to the account: nothing, when it repeated one already processed or there was no
+// open dispute or pending authorization to settle, or else it was applied, with the fee the house account gets
+// (or gives back) for it, if any.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Processed {
+    Ignored,
+    Applied { fee: Option<Money> },
+}
+
+// Funds held by an authorization until it's captured, released or it expires.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Authorization {
+    pub held: Money,
+    // How many transactions of the account were applied since it was authorized, when authorizations expire.
+    #[serde(default, skip_serializing_if = "Zero::is_zero")]
+    pub age: u32,
+}
+
 // An index to reach accounts by client ID
 pub type Accounts = HashMap<ClientID, Account>;
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "StoredAccount", into = "StoredAccount")]
 pub struct Account {
     client_id: ClientID,
+    // The funds in the default currency.
     pub total: Amount,
     pub held: Amount,
-    pub locked: bool,
+    // Why the account is restricted, which applies to its funds in every currency.
+    pub restrictions: BTreeSet<Restriction>,
+    // The funds in every other currency.
+    pub balances: BTreeMap<Currency, Balance>,
+    // How far below zero the available funds in the default currency can go, as the credit limits of the engine say.
+    pub credit_limit: Amount,
+    // The authorizations still holding funds, by their transaction ID.
+    pub authorizations: BTreeMap<TransactionID, Authorization>,
+}
+
+// The shape in which accounts are persisted. It still tells if the account is locked, so what reads it keeps working,
+// and accounts stored locked before restrictions existed come back restricted by a chargeback.
+#[derive(Serialize, Deserialize)]
+struct StoredAccount {
+    client_id: ClientID,
+    total: Amount,
+    held: Amount,
+    locked: bool,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    restrictions: BTreeSet<Restriction>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    balances: BTreeMap<Currency, Balance>,
+    #[serde(default, skip_serializing_if = "Zero::is_zero")]
+    credit_limit: Amount,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    authorizations: BTreeMap<TransactionID, Authorization>,
+}
+
+impl From<StoredAccount> for Account {
+    fn from(stored: StoredAccount) -> Self {
+        let mut restrictions = stored.restrictions;
+        if stored.locked && restrictions.is_empty() {
+            restrictions.insert(Restriction::Chargeback);
+        }
+        Self {
+            client_id: stored.client_id,
+            total: stored.total,
+            held: stored.held,
+            restrictions,
+            balances: stored.balances,
+            credit_limit: stored.credit_limit,
+            authorizations: stored.authorizations,
+        }
+    }
+}
+
+impl From<Account> for StoredAccount {
+    fn from(account: Account) -> Self {
+        Self {
+            client_id: account.client_id,
+            total: account.total,
+            held: account.held,
+            locked: account.is_locked(),
+            restrictions: account.restrictions,
+            balances: account.balances,
+            credit_limit: account.credit_limit,
+            authorizations: account.authorizations,
+        }
+    }
 }
 
 impl Account {
@@ -24,82 +138,399 @@ impl Account {
             client_id,
             total: Amount::from(0),
             held: Amount::from(0),
-            locked: false,
+            restrictions: BTreeSet::new(),
+            balances: BTreeMap::new(),
+            credit_limit: Amount::zero(),
+            authorizations: BTreeMap::new(),
+        }
+    }
+
+    pub fn client_id(&self) -> ClientID {
+        self.client_id
+    }
+
+    // Any restriction makes the account show up as locked, as it did before there were different ones.
+    pub fn is_locked(&self) -> bool {
+        !self.restrictions.is_empty()
+    }
+
+    pub fn status(&self) -> AccountStatus {
+        if self.restrictions.contains(&Restriction::Closed) {
+            AccountStatus::Closed
+        } else if self.restrictions.contains(&Restriction::Chargeback) {
+            AccountStatus::Locked
+        } else if self.is_locked() {
+            AccountStatus::Restricted
+        } else {
+            AccountStatus::Active
         }
     }
 
+    // If none of the restrictions of the account blocks the activity.
+    pub fn allows(&self, activity: Activity) -> bool {
+        !self
+            .restrictions
+            .iter()
+            .any(|restriction| restriction.blocks(activity))
+    }
+
     pub fn get_available(&self) -> Amount {
         self.total - self.held
     }
 
+    // The credit the account can use in the given currency, which is only granted in the default one.
+    fn credit_limit_in(&self, currency: Option<&str>) -> Amount {
+        match currency {
+            None => self.credit_limit,
+            Some(_) => Amount::zero(),
+        }
+    }
+
+    // What the account used of its credit in the given currency: how far below zero its available funds are,
+    // up to its credit limit.
+    pub fn used_credit_in(&self, currency: Option<&str>) -> Amount {
+        let below_zero = -self.balance_in(currency).get_available();
+        below_zero
+            .max(Amount::zero())
+            .min(self.credit_limit_in(currency))
+    }
+
+    // Why the account can't pay the amount in the given currency, if it can't: not having it available
+    // or, when it has credit, going over its credit limit.
+    fn shortfall(
+        &self,
+        currency: Option<&str>,
+        amount: Amount,
+    ) -> Option<fn((TransactionID, Amount)) -> TransactionProcessingError> {
+        let available = self.balance_in(currency).get_available();
+        let credit_limit = self.credit_limit_in(currency);
+        if amount <= available {
+            None
+        } else if credit_limit.is_zero() {
+            Some(TransactionProcessingError::InsufficientAvailableFunds)
+        } else if amount > available + credit_limit {
+            Some(TransactionProcessingError::CreditLimitExceeded)
+        } else {
+            None
+        }
+    }
+
+    // The funds in the given currency (`None` being the default one).
+    pub fn balance_in(&self, currency: Option<&str>) -> Balance {
+        match currency {
+            None => Balance {
+                total: self.total,
+                held: self.held,
+            },
+            Some(currency) => self.balances.get(currency).cloned().unwrap_or_default(),
+        }
+    }
+
+    // The total and held amounts in the given currency, ready to be updated.
+    fn funds_in(&mut self, currency: &Option<Currency>) -> (&mut Amount, &mut Amount) {
+        match currency {
+            None => (&mut self.total, &mut self.held),
+            Some(currency) => {
+                let balance = self.balances.entry(currency.clone()).or_default();
+                (&mut balance.total, &mut balance.held)
+            }
+        }
+    }
+
+    // Adds the fee charged on a transaction of another account, or takes it back when negative.
+    // It's collected even if this account is locked, since it's the other account which is operating.
+    pub fn collect_fee(&mut self, fee: &Money) {
+        let (total, _) = self.funds_in(&fee.currency);
+        *total += fee.amount;
+    }
+
+    // Transfers, and disputes, resolves and chargebacks on them, also act on the account of the client
+    // the transfer goes to, given as the counterparty.
     pub fn process(
         &mut self,
         tx: Transaction,
-        transactions: &mut Transactions,
-        disputes: &mut Disputes,
-    ) -> Result<(), TransactionProcessingError> {
-        if self.locked {
-            // For all types of operations, the locked account will prevent further processing of any kind.
+        transactions: &mut impl TransactionStore,
+        disputes: &mut impl DisputeStore,
+        rates: &RateTable,
+        dispute_rules: &DisputeRules,
+        counterparty: Option<&mut Account>,
+    ) -> Result<Processed, TransactionProcessingError> {
+        let activity = match tx.operation {
+            Operation::Deposit(_) => Activity::FundsIn,
+            Operation::Withdrawal(_)
+            | Operation::Convert(..)
+            | Operation::Transfer(..)
+            | Operation::Authorize(_)
+            | Operation::Capture => Activity::FundsOut,
+            // Releasing an authorization only gives the client back its own held funds, as a resolve does.
+            Operation::Dispute
+            | Operation::Resolve
+            | Operation::Chargeback
+            | Operation::Release => Activity::Disputes,
+        };
+        // Settling an already open dispute may be allowed even if the restrictions of the account block disputes,
+        // and so is releasing a pending authorization wherever resolves are.
+        let settling = match tx.operation {
+            Operation::Resolve | Operation::Chargeback => {
+                dispute_rules.settle_when_locked
+                    || disputes
+                        .get_dispute(tx.tx_id)?
+                        .is_some_and(|dispute| self.can_settle_rest_of(&dispute))
+            }
+            Operation::Release => {
+                dispute_rules.settle_when_locked && self.authorizations.contains_key(&tx.tx_id)
+            }
+            _ => false,
+        };
+        if !self.allows(activity) && !settling {
+            // The restrictions of the account prevent further processing of this kind (of any kind, once charged back).
             // The application should decide (handle) what to do with a TargetAccountLocked.
             return Err(TransactionProcessingError::TargetAccountLocked(tx.tx_id));
         }
 
-        // But if not locked, it moves on processing every case
-        match tx.tx_type {
-            TransactionType::Deposit => self.process_deposit(tx, transactions)?,
-            TransactionType::Withdrawal => self.process_withdrawal(tx, transactions)?,
-            TransactionType::Dispute => self.process_dispute(tx, transactions, disputes)?,
-            TransactionType::Resolve => self.process_resolve(tx, transactions, disputes)?,
-            TransactionType::Chargeback => self.process_chargeback(tx, transactions, disputes)?,
+        // But if allowed, it moves on processing every case.
+        // It tells whether it did anything, and for deposits, withdrawals and chargebacks the fee the house account gets
+        // (or gives back) for them.
+        match tx.operation {
+            Operation::Deposit(val) => self.process_deposit(tx, val, transactions),
+            Operation::Withdrawal(val) => self.process_withdrawal(tx, val, transactions),
+            Operation::Dispute => {
+                self.process_dispute(tx, transactions, disputes, dispute_rules, counterparty)
+            }
+            Operation::Resolve => self.process_resolve(tx, transactions, disputes, counterparty),
+            Operation::Chargeback => {
+                self.process_chargeback(tx, transactions, disputes, counterparty)
+            }
+            Operation::Convert(val, ref to) => {
+                let to = to.clone();
+                self.process_conversion(tx, val, to, transactions, rates)
+            }
+            Operation::Transfer(val, _) => {
+                let to = counterparty.expect("Transfers are processed with the account they go to");
+                self.process_transfer(tx, val, to, transactions)
+            }
+            Operation::Authorize(val) => self.process_authorization(tx, val, transactions),
+            Operation::Capture | Operation::Release => self.process_settlement(tx, transactions),
+        }
+    }
+
+    // Applies an operation of an operator, whatever the state of the account.
+    pub fn apply_admin(&mut self, action: &AdminAction) {
+        match action {
+            AdminAction::Unlock(Some(restriction)) => {
+                self.restrictions.remove(restriction);
+            }
+            AdminAction::Unlock(None) => self.restrictions.clear(),
+            AdminAction::Freeze(restriction) => {
+                self.restrictions.insert(*restriction);
+            }
+            AdminAction::Credit(money) => {
+                let (total, _) = self.funds_in(&money.currency);
+                *total += money.amount;
+            }
+            AdminAction::Debit(money) => {
+                let (total, _) = self.funds_in(&money.currency);
+                *total -= money.amount;
+            }
+        }
+    }
+
+    // The rest of a dispute that was partly charged back can still be resolved or charged back, as the lock it got from
+    // that chargeback would otherwise keep it open. Anything else locking the account still blocks it.
+    pub fn can_settle_rest_of(&self, dispute: &Dispute) -> bool {
+        dispute.is_open()
+            && !dispute.charged_back.is_zero()
+            && self.restrictions.iter().all(|restriction| {
+                *restriction == Restriction::Chargeback || !restriction.blocks(Activity::Disputes)
+            })
+    }
+
+    // The account where a dispute on the transaction holds funds: the one a transfer went to, since the funds are there,
+    // or this one for anything else.
+    fn holder<'a>(
+        &'a mut self,
+        t: &Transaction,
+        counterparty: Option<&'a mut Account>,
+    ) -> &'a mut Account {
+        match (&t.operation, counterparty) {
+            (Operation::Transfer(..), Some(to)) => to,
+            _ => self,
         }
-        Ok(())
     }
 
     fn process_deposit(
         &mut self,
         tx: Transaction,
-        transactions: &mut Transactions,
-    ) -> Result<(), TransactionProcessingError> {
-        match tx.amount {
-            None => {
-                unreachable!("There is always a valid amount for deposits")
-            }
-            Some(val) => {
-                // If there is a deposit at tx_id, then ignore the repeated deposit considering it as partner inconsistency 👀
-                transactions.entry(tx.tx_id).or_insert_with(|| {
-                    // Or, since it's absent, add the deposit transaction to the record and update the account total amount 👀
-                    // Note: If already present in transactions, it will be ignored.
-                    self.total += val;
-                    tx
-                });
-                Ok(())
-            }
+        val: Amount,
+        transactions: &mut impl TransactionStore,
+    ) -> Result<Processed, TransactionProcessingError> {
+        // If there is a deposit at tx_id, then ignore the repeated deposit considering it as partner inconsistency 👀
+        if transactions.get_transaction(tx.tx_id)?.is_some() {
+            return Ok(Processed::Ignored);
         }
+        // Or, since it's absent, add the deposit transaction to the record and update the account total amount, minus its fee 👀
+        let (currency, fee) = (tx.currency.clone(), tx.fee);
+        transactions.put_transaction(tx)?;
+        let (total, _) = self.funds_in(&currency);
+        *total += val - fee;
+        Ok(collected(fee, currency))
     }
 
     fn process_withdrawal(
         &mut self,
         tx: Transaction,
-        transactions: &mut Transactions,
-    ) -> Result<(), TransactionProcessingError> {
-        match tx.amount {
+        val: Amount,
+        transactions: &mut impl TransactionStore,
+    ) -> Result<Processed, TransactionProcessingError> {
+        let charged = val + tx.fee;
+        if let Some(error) = self.shortfall(tx.currency.as_deref(), charged) {
+            // Reject processing if there isn't enough available (or credit) in the currency of the withdrawal to pay for it and its fee
+            return Err(error((tx.tx_id, charged)));
+        }
+        // If already present in transactions, it will be ignored.
+        if transactions.get_transaction(tx.tx_id)?.is_some() {
+            return Ok(Processed::Ignored);
+        }
+        // Or, since it's absent, add the withdrawal transaction to the record and update the account total amount, plus its fee 👀
+        let (currency, fee) = (tx.currency.clone(), tx.fee);
+        transactions.put_transaction(tx)?;
+        let (total, _) = self.funds_in(&currency);
+        *total -= val + fee;
+        Ok(collected(fee, currency))
+    }
+
+    fn process_conversion(
+        &mut self,
+        tx: Transaction,
+        val: Amount,
+        to: Currency,
+        transactions: &mut impl TransactionStore,
+        rates: &RateTable,
+    ) -> Result<Processed, TransactionProcessingError> {
+        if let Some(error) = self.shortfall(tx.currency.as_deref(), val) {
+            // Reject processing if there isn't enough available (or credit) in the currency to convert from
+            return Err(error((tx.tx_id, val)));
+        }
+        // If already present in transactions, it will be ignored.
+        if transactions.get_transaction(tx.tx_id)?.is_some() {
+            return Ok(Processed::Ignored);
+        }
+        let money = tx.money().expect("Conversions always have an amount");
+        let converted = rates
+            .convert(&money, &to, tx.tx_id)
+            .ok_or(TransactionProcessingError::RateNotFound(tx.tx_id))?;
+        // Move the funds from one currency to the other, without any loss 👀
+        transactions.put_transaction(tx)?;
+        let (total, _) = self.funds_in(&money.currency);
+        *total -= val;
+        let (total, _) = self.funds_in(&converted.currency);
+        *total += converted.amount;
+        Ok(Processed::Applied { fee: None })
+    }
+
+    fn process_transfer(
+        &mut self,
+        tx: Transaction,
+        val: Amount,
+        to: &mut Account,
+        transactions: &mut impl TransactionStore,
+    ) -> Result<Processed, TransactionProcessingError> {
+        if !to.allows(Activity::FundsIn) {
+            // Funds can't reach an account that doesn't let them in either
+            return Err(TransactionProcessingError::TargetAccountLocked(tx.tx_id));
+        }
+        if let Some(error) = self.shortfall(tx.currency.as_deref(), val) {
+            // Reject processing if there isn't enough available (or credit) in the currency of the transfer
+            return Err(error((tx.tx_id, val)));
+        }
+        // If already present in transactions, it will be ignored.
+        if transactions.get_transaction(tx.tx_id)?.is_some() {
+            return Ok(Processed::Ignored);
+        }
+        // Move the funds from one account to the other, both legs at once 👀
+        let currency = tx.currency.clone();
+        transactions.put_transaction(tx)?;
+        let (total, _) = self.funds_in(&currency);
+        *total -= val;
+        let (total, _) = to.funds_in(&currency);
+        *total += val;
+        Ok(Processed::Applied { fee: None })
+    }
+
+    fn process_authorization(
+        &mut self,
+        tx: Transaction,
+        val: Amount,
+        transactions: &mut impl TransactionStore,
+    ) -> Result<Processed, TransactionProcessingError> {
+        if let Some(error) = self.shortfall(tx.currency.as_deref(), val) {
+            // Reject processing if there isn't enough available (or credit) in the currency of the authorization to hold it
+            return Err(error((tx.tx_id, val)));
+        }
+        // If already present in transactions, it will be ignored.
+        if transactions.get_transaction(tx.tx_id)?.is_some() {
+            return Ok(Processed::Ignored);
+        }
+        // Hold the funds until the authorization is captured or released 👀
+        let held = tx.money().expect("Authorizations always have an amount");
+        let tx_id = tx.tx_id;
+        transactions.put_transaction(tx)?;
+        let (_, account_held) = self.funds_in(&held.currency);
+        *account_held += val;
+        self.authorizations
+            .insert(tx_id, Authorization { held, age: 0 });
+        Ok(Processed::Applied { fee: None })
+    }
+
+    // Captures or releases the authorization with the transaction ID of the given tx.
+    fn process_settlement(
+        &mut self,
+        tx: Transaction,
+        transactions: &impl TransactionStore,
+    ) -> Result<Processed, TransactionProcessingError> {
+        let authorization = match self.authorizations.remove(&tx.tx_id) {
+            Some(authorization) => authorization,
             None => {
-                unreachable!("There is always a valid amount for withdrawals")
-            }
-            Some(val) => {
-                if val > self.get_available() {
-                    // Reject processing if there isn't enough available
-                    return Err(TransactionProcessingError::InsufficientAvailableFunds((
-                        tx.tx_id, val,
-                    )));
-                }
-                transactions.entry(tx.tx_id).or_insert_with(|| {
-                    // Or, since it's absent, add the withdrawal transaction to the record and update the account total amount 👀
-                    // Note: If already present in transactions, it will be ignored.
-                    self.total -= val;
-                    tx
-                });
-                Ok(())
+                return match transactions.get_transaction(tx.tx_id)? {
+                    None => Err(TransactionProcessingError::NotFound(tx.tx_id)),
+                    // Return an error if the referred tx belongs to another account or it's not an authorization.
+                    Some(t)
+                        if t.client_id != self.client_id
+                            || !matches!(t.operation, Operation::Authorize(_)) =>
+                    {
+                        Err(TransactionProcessingError::InconsistentOperation)
+                    }
+                    // Ignore processing it if the authorization was already captured, released or it expired.
+                    Some(_) => Ok(Processed::Ignored),
+                };
+            }
+        };
+        // A capture takes the held funds out of the account for good, a release gives them back to the available ones 👀
+        let (total, held) = self.funds_in(&authorization.held.currency);
+        *held -= authorization.held.amount;
+        if let Operation::Capture = tx.operation {
+            *total -= authorization.held.amount;
+        }
+        Ok(Processed::Applied { fee: None })
+    }
+
+    // Ages the pending authorizations after the transaction with the given ID was applied,
+    // releasing the ones that saw as many transactions as they are allowed to.
+    pub fn age_authorizations(&mut self, tx_id: TransactionID, expiry: u32) {
+        let mut expired = vec![];
+        for (id, authorization) in self.authorizations.iter_mut() {
+            if *id == tx_id {
+                continue;
+            }
+            authorization.age += 1;
+            if authorization.age >= expiry {
+                expired.push(*id);
+            }
+        }
+        for id in expired {
+            if let Some(authorization) = self.authorizations.remove(&id) {
+                let (_, held) = self.funds_in(&authorization.held.currency);
+                *held -= authorization.held.amount;
             }
         }
     }
@@ -107,14 +538,15 @@ impl Account {
     fn process_dispute(
         &mut self,
         tx: Transaction,
-        transactions: &mut Transactions,
-        disputes: &mut Disputes,
-    ) -> Result<(), TransactionProcessingError> {
+        transactions: &impl TransactionStore,
+        disputes: &mut impl DisputeStore,
+        rules: &DisputeRules,
+        counterparty: Option<&mut Account>,
+    ) -> Result<Processed, TransactionProcessingError> {
         // Ignore processing if there is a pending (unresolved) dispute already for this transaction.
-        if let Some(d) = disputes.get(&tx.tx_id) {
-            if d.tx_id == tx.tx_id {
-                return Ok(());
-            }
+        let existing = disputes.get_dispute(tx.tx_id)?;
+        if existing.as_ref().is_some_and(Dispute::is_open) {
+            return Ok(Processed::Ignored);
         }
 
         // Return an error if the given tx has a `ClientID` that is not the one of this account.
@@ -123,23 +555,78 @@ impl Account {
         }
 
         // Process this dispute
-        match transactions.get(&tx.tx_id) {
+        match transactions.get_transaction(tx.tx_id)? {
             None => Err(TransactionProcessingError::NotFound(tx.tx_id)),
             Some(t) => {
-                // Return an error if the referred tx of the given tx has a `ClientID` that is not the one of this account.
-                if t.client_id != self.client_id {
+                // Return an error if the referred tx of the given tx has a `ClientID` that is not the one of this account
+                // or if it's a conversion, which moved funds within the account and can't be disputed.
+                // Nor authorizations, which are captured or released instead.
+                if t.client_id != self.client_id
+                    || matches!(
+                        t.operation,
+                        Operation::Convert(..) | Operation::Authorize(_)
+                    )
+                {
                     return Err(TransactionProcessingError::InconsistentOperation);
                 }
-                if let Some(val) = t.amount {
-                    // Disputed, hence add it as pending and increase in val the value held 👀
-                    disputes.entry(tx.tx_id).or_insert(Dispute::from(tx));
-                    self.held += val;
+                // Return an error if it's too late to dispute it, when both times are known.
+                if let (Some(window), Some(filed_at), Some(made_at)) =
+                    (rules.filing_window, tx.timestamp, t.timestamp)
+                {
+                    if filed_at > made_at + window {
+                        return Err(TransactionProcessingError::DisputeWindowClosed(tx.tx_id));
+                    }
+                }
+                // Withdrawals are disputed as the policy for them says.
+                let holds_funds = match (&t.operation, rules.withdrawals) {
+                    (Operation::Withdrawal(_), WithdrawalDisputePolicy::Reject) => {
+                        return Err(TransactionProcessingError::WithdrawalDisputeRefused(
+                            tx.tx_id,
+                        ));
+                    }
+                    (Operation::Withdrawal(_), WithdrawalDisputePolicy::CreditOnChargebackOnly) => {
+                        false
+                    }
+                    _ => true,
+                };
+                if let Some(val) = t.amount() {
+                    // Only what was not charged back yet can be disputed, all of it unless the dispute says which part.
+                    let disputable =
+                        val - existing.as_ref().map_or(Amount::zero(), |d| d.charged_back);
+                    // A dispute charged back in full is over, whatever happens to the account afterwards.
+                    if existing.is_some() && disputable.is_zero() {
+                        return Ok(Processed::Ignored);
+                    }
+                    let amount = tx.disputed_amount.unwrap_or(disputable);
+                    if amount > disputable {
+                        return Err(TransactionProcessingError::ExceedsDisputedAmount((
+                            tx.tx_id, amount,
+                        )));
+                    }
+                    // Disputed (again if it was resolved, it expired or only part of it was charged back before), hence keep it as pending and increase in amount the value held 👀
+                    let opened_at = tx.timestamp;
+                    let mut dispute = match existing {
+                        Some(mut closed) => {
+                            closed.reopen();
+                            closed
+                        }
+                        None => Dispute::from(tx),
+                    };
+                    dispute.opened_at = opened_at;
+                    dispute.holds_funds = holds_funds;
+                    dispute.outstanding = Some(amount);
+                    disputes.put_dispute(dispute)?;
+                    // In the currency of the disputed transaction.
+                    if holds_funds {
+                        let (_, held) = self.holder(&t, counterparty).funds_in(&t.currency);
+                        *held += amount;
+                    }
                 } else {
                     unreachable!(
                         "There is always a valid amount for transactions aimed by a dispute"
                     );
                 }
-                Ok(())
+                Ok(Processed::Applied { fee: None })
             }
         }
     }
@@ -147,28 +634,50 @@ impl Account {
     fn process_resolve(
         &mut self,
         tx: Transaction,
-        transactions: &mut Transactions,
-        disputes: &mut Disputes,
-    ) -> Result<(), TransactionProcessingError> {
+        transactions: &impl TransactionStore,
+        disputes: &mut impl DisputeStore,
+        counterparty: Option<&mut Account>,
+    ) -> Result<Processed, TransactionProcessingError> {
         // Ignore processing this resolve if there is NOT a pending (unresolved) dispute for its referred transaction
-        if disputes.get(&tx.tx_id).is_none() {
-            return Ok(());
-        }
+        let mut dispute = match disputes.get_dispute(tx.tx_id)? {
+            Some(dispute) if dispute.is_open() => dispute,
+            _ => return Ok(Processed::Ignored),
+        };
 
         // Process this resolve
-        match transactions.get(&tx.tx_id) {
+        match transactions.get_transaction(tx.tx_id)? {
             None => Err(TransactionProcessingError::NotFound(tx.tx_id)),
             Some(t) => {
-                if let Some(val) = t.amount {
-                    // Resolved, hence decrease in val the value held and remove it from pending disputes 👀
-                    self.held -= val;
-                    disputes.remove(&tx.tx_id);
+                // Return an error if the referred tx of the given tx has a `ClientID` that is not the one of this account.
+                if t.client_id != self.client_id {
+                    return Err(TransactionProcessingError::InconsistentOperation);
+                }
+                if let Some(val) = t.amount() {
+                    // All of what is in dispute unless the resolve says which part.
+                    let outstanding = dispute.outstanding_of(val);
+                    let amount = tx.disputed_amount.unwrap_or(outstanding);
+                    if amount > outstanding {
+                        return Err(TransactionProcessingError::ExceedsDisputedAmount((
+                            tx.tx_id, amount,
+                        )));
+                    }
+                    // Resolved, hence decrease in amount the value held (if it held it)
+                    // and keep the dispute as resolved once nothing is in dispute anymore 👀
+                    if dispute.holds_funds {
+                        let (_, held) = self.holder(&t, counterparty).funds_in(&t.currency);
+                        *held -= amount;
+                    }
+                    dispute.outstanding = Some(outstanding - amount);
+                    if amount == outstanding {
+                        dispute.resolve();
+                    }
+                    disputes.put_dispute(dispute)?;
                 } else {
                     unreachable!(
                         "There is always a valid amount for transactions aimed by a resolution"
                     );
                 }
-                Ok(())
+                Ok(Processed::Applied { fee: None })
             }
         }
     }
@@ -176,63 +685,210 @@ impl Account {
     fn process_chargeback(
         &mut self,
         tx: Transaction,
-        transactions: &mut Transactions,
-        disputes: &mut Disputes,
-    ) -> Result<(), TransactionProcessingError> {
+        transactions: &impl TransactionStore,
+        disputes: &mut impl DisputeStore,
+        counterparty: Option<&mut Account>,
+    ) -> Result<Processed, TransactionProcessingError> {
         // Ignore processing this chargeback if there is NOT a pending (unresolved) dispute for its referred transaction
-        if disputes.get(&tx.tx_id).is_none() {
-            return Ok(());
-        }
+        let mut dispute = match disputes.get_dispute(tx.tx_id)? {
+            Some(dispute) if dispute.is_open() => dispute,
+            _ => return Ok(Processed::Ignored),
+        };
 
-        match transactions.get(&tx.tx_id) {
+        match transactions.get_transaction(tx.tx_id)? {
             None => Err(TransactionProcessingError::NotFound(tx.tx_id)),
             Some(t) => {
                 // Return an error if the referred tx of the given tx has a `ClientID` that is not the one of this account.
                 if t.client_id != self.client_id {
                     return Err(TransactionProcessingError::InconsistentOperation);
                 }
-                if let Some(val) = t.amount {
+                if let Some(val) = t.amount() {
+                    // All of what is in dispute unless the chargeback says which part.
+                    let outstanding = dispute.outstanding_of(val);
+                    let amount = tx.disputed_amount.unwrap_or(outstanding);
+                    if amount > outstanding {
+                        return Err(TransactionProcessingError::ExceedsDisputedAmount((
+                            tx.tx_id, amount,
+                        )));
+                    }
                     // Chargeback, hence 👀
-                    // 1. Decrease if deposit or increase if withdrawal the total value in this account by the previously disputed transaction's value.
-                    // 2. Decrease held of that value.
-                    // 3. Freeze the account.
-                    // 4. Remove the dispute from the record of disputes that are pending.
-                    match t.tx_type {
-                        TransactionType::Deposit => self.total -= val,
-                        TransactionType::Withdrawal => self.total += val,
+                    // 1. Decrease if deposit or increase if withdrawal or transfer the total value in this account by the charged back amount,
+                    //    taking it back from the account a transfer went to.
+                    // 2. Decrease held of that value, if the dispute held it, where it held it.
+                    // 3. Give back the same part of the fee charged on it, which the house account returns.
+                    // 4. Freeze the account.
+                    // 5. Keep the dispute as charged back once nothing is in dispute anymore.
+                    // All in the currency of the disputed transaction.
+                    let (total, _) = self.funds_in(&t.currency);
+                    match t.operation {
+                        Operation::Deposit(_) => *total -= amount,
+                        Operation::Withdrawal(_) | Operation::Transfer(..) => *total += amount,
                         _ => {
                             unreachable!("No valid case can reach this")
                         }
                     }
-                    self.held -= val;
-                    self.locked = true;
-                    disputes.remove(&tx.tx_id);
+                    let (total, held) = self.holder(&t, counterparty).funds_in(&t.currency);
+                    if let Operation::Transfer(..) = t.operation {
+                        *total -= amount;
+                    }
+                    if dispute.holds_funds {
+                        *held -= amount;
+                    }
+                    let fee = match val.is_zero() {
+                        true => Amount::zero(),
+                        false => t.fee * amount / val,
+                    };
+                    let (total, _) = self.funds_in(&t.currency);
+                    *total += fee;
+                    self.restrictions.insert(Restriction::Chargeback);
+                    dispute.outstanding = Some(outstanding - amount);
+                    dispute.charged_back += amount;
+                    if amount == outstanding {
+                        dispute.charge_back();
+                    }
+                    disputes.put_dispute(dispute)?;
+                    Ok(collected(-fee, t.currency))
                 } else {
                     unreachable!(
                         "There is always a valid amount for transactions aimed by a chargeback"
                     );
                 }
-                Ok(())
             }
         }
     }
 
+    // Releases what the dispute held, as a resolve of all of what is in dispute would, and keeps it as expired.
+    // It happens whether the account is locked or not, since it's not an operation of its client.
+    pub fn expire_dispute(
+        &mut self,
+        mut dispute: Dispute,
+        transactions: &impl TransactionStore,
+        counterparty: Option<&mut Account>,
+    ) -> Result<Dispute, StorageError> {
+        if let Some(t) = transactions.get_transaction(dispute.tx_id)? {
+            if let Some(val) = t.amount() {
+                if dispute.holds_funds {
+                    let (_, held) = self.holder(&t, counterparty).funds_in(&t.currency);
+                    *held -= dispute.outstanding_of(val);
+                }
+            }
+        }
+        dispute.outstanding = Some(Amount::zero());
+        dispute.expire();
+        Ok(dispute)
+    }
+
     // Render this account in its current state following the expected format
-    // as per `Rust Test.pdf`
-    // client, available, held, total, locked
-    pub fn render_as_output_line(&self) {
-        let output_line = format!(
+    // as per `Rust Test.pdf`, one line per currency it has funds in, the default currency first
+    // (unless the account only ever had funds in other currencies)
+    // client, available, held, total, locked[, status, restrictions][, credit][, currency]
+    pub fn output_lines(
+        &self,
+        with_status: bool,
+        with_credit: bool,
+        with_currency: bool,
+        rounding: Rounding,
+    ) -> Vec<String> {
+        let mut lines = vec![];
+        if self.balances.is_empty() || !self.total.is_zero() || !self.held.is_zero() {
+            lines.push(self.output_line(None, with_status, with_credit, with_currency, rounding));
+        }
+        for currency in self.balances.keys() {
+            lines.push(self.output_line(
+                Some(currency),
+                with_status,
+                with_credit,
+                with_currency,
+                rounding,
+            ));
+        }
+        lines
+    }
+
+    fn output_line(
+        &self,
+        currency: Option<&str>,
+        with_status: bool,
+        with_credit: bool,
+        with_currency: bool,
+        rounding: Rounding,
+    ) -> String {
+        let balance = self.balance_in(currency);
+        let mut line = format!(
             "{}, {}, {}, {}, {}",
             self.client_id,
-            get_amount_string_from(&self.get_available()),
-            get_amount_string_from(&self.held),
-            get_amount_string_from(&self.total),
-            self.locked
+            rounding.render(&balance.get_available()),
+            rounding.render(&balance.held),
+            rounding.render(&balance.total),
+            self.is_locked()
         );
-        println!("{}", output_line);
+        if with_status {
+            let restrictions: Vec<String> = self
+                .restrictions
+                .iter()
+                .map(Restriction::to_string)
+                .collect();
+            line = format!("{}, {}, {}", line, self.status(), restrictions.join("|"));
+        }
+        if with_credit {
+            line = format!(
+                "{}, {}",
+                line,
+                rounding.render(&self.used_credit_in(currency))
+            );
+        }
+        match with_currency {
+            true => format!("{}, {}", line, currency.unwrap_or_default()),
+            false => line,
+        }
+    }
+}
+
+// Applied, with the fee the house account gets, if any.
+fn collected(fee: Amount, currency: Option<Currency>) -> Processed {
+    Processed::Applied {
+        fee: (!fee.is_zero()).then_some(Money {
+            amount: fee,
+            currency,
+        }),
+    }
+}
+
+// Renders the accounts as expected in the output, headers row included.
+// The currency column is only there when some account has funds in other currencies than the default one,
+// and the credit column when some account has a credit limit, so the output of other inputs stays as it always was.
+pub fn render_accounts(accounts: &[Account], rounding: Rounding) -> Vec<String> {
+    render(accounts, false, rounding)
+}
+
+// Same as `render_accounts`, with the status of every account and the restrictions it has (separated by `|`)
+// right after the `locked` column.
+pub fn render_accounts_with_status(accounts: &[Account], rounding: Rounding) -> Vec<String> {
+    render(accounts, true, rounding)
+}
+
+fn render(accounts: &[Account], with_status: bool, rounding: Rounding) -> Vec<String> {
+    let with_credit = accounts
+        .iter()
+        .any(|account| !account.credit_limit.is_zero());
+    let with_currency = accounts.iter().any(|account| !account.balances.is_empty());
+    let mut headers = OUTPUT_HEADERS.to_owned();
+    if with_status {
+        headers = format!("{}, {}", headers, STATUS_HEADERS);
+    }
+    if with_credit {
+        headers = format!("{}, {}", headers, CREDIT_HEADER);
+    }
+    if with_currency {
+        headers = format!("{}, {}", headers, CURRENCY_HEADER);
+    }
+    let mut lines = vec![headers];
+    for account in accounts {
+        lines.extend(account.output_lines(with_status, with_credit, with_currency, rounding));
     }
+    lines
 }
 
-fn get_amount_string_from(amount: &Amount) -> String {
-    format!("{:#.4}", amount)
+pub fn get_amount_string_from(amount: &Amount) -> String {
+    Rounding::default().render(amount)
 }
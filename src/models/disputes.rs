@@ -1,17 +1,57 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt, str::FromStr};
+
+use fraction::Zero;
+use serde::{Deserialize, Serialize};
 
 use super::{
-    shared::{ClientID, TransactionID},
+    shared::{Amount, ClientID, Timestamp, TransactionID},
     transaction::Transaction,
 };
 
-// An index to reach what transactions are currently disputed by transaction ID
+// An index to reach the dispute of every transaction that was ever disputed by transaction ID
 pub type Disputes = HashMap<TransactionID, Dispute>;
 
-#[derive(Debug)]
+// A dispute starts `Open` and ends up either `Resolved` (from where it can be opened again), `ChargedBack`
+// (from where only what was not charged back yet can be disputed again) or `Expired` (from where it can be opened
+// again too).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DisputeState {
+    #[default]
+    Open,
+    Resolved,
+    ChargedBack,
+    // It was pending for longer than disputes can be, so what it held was released.
+    Expired,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Dispute {
     pub client_id: ClientID,
     pub tx_id: TransactionID,
+    #[serde(default)]
+    pub state: DisputeState,
+    // How many times it was opened again after being resolved.
+    #[serde(default)]
+    pub reopenings: u32,
+    // Every state it went through, in order, the current one being the last.
+    #[serde(default)]
+    pub history: Vec<DisputeState>,
+    // If it holds the disputed funds while open, which disputes on withdrawals don't with `CreditOnChargebackOnly`.
+    #[serde(default = "holds_funds_by_default")]
+    pub holds_funds: bool,
+    // The part of the disputed transaction in dispute, all of it when absent (as for the disputes from before partial ones).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outstanding: Option<Amount>,
+    // The part of the disputed transaction that was charged back.
+    #[serde(default, skip_serializing_if = "Zero::is_zero")]
+    pub charged_back: Amount,
+    // When it was opened (again, if it was), if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opened_at: Option<Timestamp>,
+}
+
+fn holds_funds_by_default() -> bool {
+    true
 }
 
 impl Dispute {
@@ -19,6 +59,105 @@ impl Dispute {
         Self {
             client_id: tx.client_id,
             tx_id: tx.tx_id,
+            state: DisputeState::Open,
+            reopenings: 0,
+            history: vec![DisputeState::Open],
+            holds_funds: true,
+            outstanding: tx.disputed_amount,
+            charged_back: Amount::zero(),
+            opened_at: tx.timestamp,
         }
     }
+
+    // What is in dispute of the disputed transaction, which moved the given amount.
+    pub fn outstanding_of(&self, val: Amount) -> Amount {
+        self.outstanding.unwrap_or(val)
+    }
+
+    // Pending disputes are the ones holding funds.
+    pub fn is_open(&self) -> bool {
+        self.state == DisputeState::Open
+    }
+
+    pub fn reopen(&mut self) {
+        self.reopenings += 1;
+        self.transition_to(DisputeState::Open);
+    }
+
+    pub fn resolve(&mut self) {
+        self.transition_to(DisputeState::Resolved);
+    }
+
+    pub fn charge_back(&mut self) {
+        self.transition_to(DisputeState::ChargedBack);
+    }
+
+    pub fn expire(&mut self) {
+        self.transition_to(DisputeState::Expired);
+    }
+
+    // When it expires if it's still pending by then, as per the given rules.
+    pub fn expires_at(&self, rules: &DisputeRules) -> Option<Timestamp> {
+        Some(self.opened_at? + rules.expiry?)
+    }
+
+    fn transition_to(&mut self, state: DisputeState) {
+        self.state = state;
+        self.history.push(state);
+    }
+}
+
+// What a dispute on a withdrawal does, since the funds it refers to already left the account.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WithdrawalDisputePolicy {
+    // Withdrawals can't be disputed.
+    Reject,
+    // The withdrawn amount is held, even if that makes the available funds negative,
+    // and it's credited back if charged back (what disputes on withdrawals always did).
+    HoldAsNegativeAvailable,
+    // Nothing is held while disputed, the withdrawn amount is only credited back if charged back.
+    // The default, since holding funds the client no longer has only makes their available funds negative.
+    #[default]
+    CreditOnChargebackOnly,
+}
+
+#[derive(Debug)]
+pub struct UnknownWithdrawalDisputePolicy(pub String);
+
+impl fmt::Display for UnknownWithdrawalDisputePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unknown policy for disputes on withdrawals {} (expected reject, hold-as-negative-available or credit-on-chargeback-only)",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for UnknownWithdrawalDisputePolicy {}
+
+impl FromStr for WithdrawalDisputePolicy {
+    type Err = UnknownWithdrawalDisputePolicy;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reject" => Ok(WithdrawalDisputePolicy::Reject),
+            "hold-as-negative-available" => Ok(WithdrawalDisputePolicy::HoldAsNegativeAvailable),
+            "credit-on-chargeback-only" => Ok(WithdrawalDisputePolicy::CreditOnChargebackOnly),
+            other => Err(UnknownWithdrawalDisputePolicy(other.to_owned())),
+        }
+    }
+}
+
+// How disputes are handled by the engine.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DisputeRules {
+    pub withdrawals: WithdrawalDisputePolicy,
+    // How long after a transaction it can be disputed, in seconds. Disputes after it are rejected.
+    pub filing_window: Option<u64>,
+    // How long a dispute can stay pending, in seconds. Disputes still pending after it expire.
+    pub expiry: Option<u64>,
+    // Whether disputes already open on an account whose restrictions block disputes can still be resolved or charged
+    // back. New disputes are rejected on it either way.
+    pub settle_when_locked: bool,
 }
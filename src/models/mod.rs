@@ -2,4 +2,6 @@ pub mod shared;
 
 pub mod account;
 pub mod disputes;
+pub mod money;
+pub mod restrictions;
 pub mod transaction;
@@ -0,0 +1,96 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use super::shared::{Amount, Currency};
+
+// The digits past the decimal point amounts are rendered with.
+pub const OUTPUT_PRECISION: u32 = 4;
+
+// An amount tagged with its currency (`None` being the default one).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Money {
+    pub amount: Amount,
+    pub currency: Option<Currency>,
+}
+
+// How amounts get rounded when rendered. The math is always done without loss,
+// so rounding only happens at this final presentation step.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rounding {
+    // Drops the digits past the precision (what the output always did).
+    #[default]
+    Truncate,
+    // To the nearest, halves away from zero.
+    HalfUp,
+    // To the nearest, halves to the even neighbour (banker's rounding).
+    HalfEven,
+    // Towards negative infinity.
+    Floor,
+    // Towards positive infinity.
+    Ceil,
+}
+
+impl Rounding {
+    pub fn round(&self, amount: Amount, precision: u32) -> Amount {
+        let scale = Amount::from(10u64.pow(precision));
+        let scaled = amount * scale;
+        let rounded = match self {
+            Rounding::Truncate => scaled.trunc(),
+            Rounding::HalfUp => scaled.round(),
+            Rounding::HalfEven => {
+                let floor = scaled.floor();
+                let half = Amount::from(1) / Amount::from(2);
+                let remainder = scaled - floor;
+                let floor_is_even = (floor / Amount::from(2)).fract() == Amount::from(0);
+                if remainder > half || (remainder == half && !floor_is_even) {
+                    floor + Amount::from(1)
+                } else {
+                    floor
+                }
+            }
+            Rounding::Floor => scaled.floor(),
+            Rounding::Ceil => scaled.ceil(),
+        };
+        rounded / scale
+    }
+
+    // Renders the amount with the output precision.
+    pub fn render(&self, amount: &Amount) -> String {
+        format!(
+            "{:#.*}",
+            OUTPUT_PRECISION as usize,
+            self.round(*amount, OUTPUT_PRECISION)
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownRounding(pub String);
+
+impl fmt::Display for UnknownRounding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unknown rounding rule {} (expected truncate, half-up, half-even, floor or ceil)",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for UnknownRounding {}
+
+impl FromStr for Rounding {
+    type Err = UnknownRounding;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "truncate" => Ok(Rounding::Truncate),
+            "half-up" => Ok(Rounding::HalfUp),
+            "half-even" => Ok(Rounding::HalfEven),
+            "floor" => Ok(Rounding::Floor),
+            "ceil" => Ok(Rounding::Ceil),
+            other => Err(UnknownRounding(other.to_owned())),
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+// Why an account is restricted. Each reason blocks some activities of the account and lets the others go on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Restriction {
+    // A transaction of the account was charged back. Blocks everything (what locking always did).
+    Chargeback,
+    // Funds can come in and disputes go on, but nothing can leave the account.
+    FraudReview,
+    // No funds can come in or leave the account, but disputes go on.
+    ComplianceHold,
+    // Blocks everything.
+    Closed,
+    // The dispute or chargeback ratio of the client went over its limit. No funds can come in.
+    ExcessiveDisputes,
+}
+
+// What an operation does to an account, which is what restrictions block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Activity {
+    // Deposits and transfers from other clients.
+    FundsIn,
+    // Withdrawals, conversions and transfers to other clients.
+    FundsOut,
+    // Disputes, resolves and chargebacks, and releases of authorizations.
+    Disputes,
+}
+
+impl Restriction {
+    pub fn blocks(&self, activity: Activity) -> bool {
+        match self {
+            Restriction::Chargeback | Restriction::Closed => true,
+            Restriction::FraudReview => activity == Activity::FundsOut,
+            Restriction::ComplianceHold => activity != Activity::Disputes,
+            Restriction::ExcessiveDisputes => activity == Activity::FundsIn,
+        }
+    }
+}
+
+impl fmt::Display for Restriction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Restriction::Chargeback => "chargeback",
+            Restriction::FraudReview => "fraud-review",
+            Restriction::ComplianceHold => "compliance-hold",
+            Restriction::Closed => "closed",
+            Restriction::ExcessiveDisputes => "excessive-disputes",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// The overall state of an account, from the restrictions it has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccountStatus {
+    // Without restrictions.
+    Active,
+    // Some activities are blocked.
+    Restricted,
+    // Everything is blocked since a chargeback.
+    Locked,
+    // Everything is blocked since it was closed.
+    Closed,
+}
+
+impl fmt::Display for AccountStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AccountStatus::Active => "active",
+            AccountStatus::Restricted => "restricted",
+            AccountStatus::Locked => "locked",
+            AccountStatus::Closed => "closed",
+        };
+        write!(f, "{}", name)
+    }
+}
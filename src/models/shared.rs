@@ -9,3 +9,13 @@ pub type TransactionID = u32;
 
 // As specified by `Rust Test.pdf``
 pub type ClientID = u16;
+
+// A currency code like `USD`, always in upper case.
+// Transactions without one are in the default currency.
+pub type Currency = String;
+
+// When a transaction happened, in seconds since the Unix epoch.
+pub type Timestamp = u64;
+
+// The partner a transaction came through, as numbered by us.
+pub type PartnerID = u16;
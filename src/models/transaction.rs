@@ -2,14 +2,19 @@ use std::{collections::HashMap, error, fmt};
 
 use crate::models::shared::Amount;
 use fraction::{Decimal, Zero};
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serialize};
 
-use super::shared::{ClientID, TransactionID};
+use crate::storage::StorageError;
+
+use super::{
+    money::Money,
+    shared::{ClientID, Currency, PartnerID, Timestamp, TransactionID},
+};
 
 // An index to reach transactions by transaction ID
 pub type Transactions = HashMap<TransactionID, Transaction>;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum TransactionType {
     Deposit,
@@ -17,18 +22,251 @@ pub enum TransactionType {
     Dispute,
     Resolve,
     Chargeback,
+    Convert,
+    Transfer,
+    Authorize,
+    Capture,
+    Release,
 }
 
-#[derive(Debug, Deserialize)]
+// What a transaction does to an account.
+// Only deposits, withdrawals, conversions, transfers and authorizations move funds, hence only them carry an amount.
+// Conversions also carry the currency the amount is converted to, and transfers the client they go to.
+// Captures and releases settle the authorization with their transaction ID.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Deposit(Amount),
+    Withdrawal(Amount),
+    Dispute,
+    Resolve,
+    Chargeback,
+    Convert(Amount, Currency),
+    Transfer(Amount, ClientID),
+    Authorize(Amount),
+    Capture,
+    Release,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "TransactionRecord")]
 pub struct Transaction {
-    #[serde(rename = "type")]    
-    pub tx_type: TransactionType,
-    #[serde(rename = "client")]    
     pub client_id: ClientID,
-    #[serde(rename = "tx")]    
     pub tx_id: TransactionID,
-    #[serde(deserialize_with = "decimal_from_string")]
-    pub amount: Option<Amount>,
+    pub operation: Operation,
+    // The currency of the funds moved by a deposit, withdrawal, conversion or transfer, `None` being the default currency.
+    // Disputes, resolves and chargebacks always apply in the currency of the transaction they refer to.
+    pub currency: Option<Currency>,
+    // What the house account collected on a deposit or withdrawal, in its currency.
+    pub fee: Amount,
+    // For disputes, resolves and chargebacks, the part of the referred transaction they act on.
+    // All of what can be disputed, or of what is in dispute, when absent.
+    pub disputed_amount: Option<Amount>,
+    // When it happened, if the input tells. Disputes, resolves and chargebacks without one
+    // happened at the latest time the engine knows of.
+    pub timestamp: Option<Timestamp>,
+    // The partner it came through, if the input tells.
+    pub partner: Option<PartnerID>,
+}
+
+impl Transaction {
+    pub fn tx_type(&self) -> TransactionType {
+        match self.operation {
+            Operation::Deposit(_) => TransactionType::Deposit,
+            Operation::Withdrawal(_) => TransactionType::Withdrawal,
+            Operation::Dispute => TransactionType::Dispute,
+            Operation::Resolve => TransactionType::Resolve,
+            Operation::Chargeback => TransactionType::Chargeback,
+            Operation::Convert(..) => TransactionType::Convert,
+            Operation::Transfer(..) => TransactionType::Transfer,
+            Operation::Authorize(_) => TransactionType::Authorize,
+            Operation::Capture => TransactionType::Capture,
+            Operation::Release => TransactionType::Release,
+        }
+    }
+
+    pub fn amount(&self) -> Option<Amount> {
+        match self.operation {
+            Operation::Deposit(val)
+            | Operation::Withdrawal(val)
+            | Operation::Convert(val, _)
+            | Operation::Transfer(val, _)
+            | Operation::Authorize(val) => Some(val),
+            Operation::Dispute
+            | Operation::Resolve
+            | Operation::Chargeback
+            | Operation::Capture
+            | Operation::Release => None,
+        }
+    }
+
+    // The amount tagged with its currency.
+    pub fn money(&self) -> Option<Money> {
+        Some(Money {
+            amount: self.amount()?,
+            currency: self.currency.clone(),
+        })
+    }
+}
+
+// The shape in which transactions are persisted (for example, in snapshots).
+// Unlike the input, it keeps amounts as exact fractions so nothing is lost on the way back.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StoredTransaction {
+    pub client_id: ClientID,
+    pub tx_id: TransactionID,
+    pub operation: Operation,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<Currency>,
+    #[serde(default, skip_serializing_if = "Zero::is_zero")]
+    pub fee: Amount,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<Timestamp>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub partner: Option<PartnerID>,
+}
+
+impl From<&Transaction> for StoredTransaction {
+    fn from(tx: &Transaction) -> Self {
+        Self {
+            client_id: tx.client_id,
+            tx_id: tx.tx_id,
+            operation: tx.operation.clone(),
+            currency: tx.currency.clone(),
+            fee: tx.fee,
+            timestamp: tx.timestamp,
+            partner: tx.partner,
+        }
+    }
+}
+
+impl From<StoredTransaction> for Transaction {
+    fn from(stored: StoredTransaction) -> Self {
+        Self {
+            client_id: stored.client_id,
+            tx_id: stored.tx_id,
+            operation: stored.operation,
+            currency: stored.currency,
+            fee: stored.fee,
+            disputed_amount: None,
+            timestamp: stored.timestamp,
+            partner: stored.partner,
+        }
+    }
+}
+
+// A row of the input as it comes, before making sure its amount is consistent with its type.
+#[derive(Debug, Deserialize)]
+struct TransactionRecord {
+    #[serde(rename = "type")]
+    tx_type: TransactionType,
+    #[serde(rename = "client")]
+    client_id: ClientID,
+    #[serde(rename = "tx")]
+    tx_id: TransactionID,
+    #[serde(default, deserialize_with = "decimal_from_string")]
+    amount: Option<Amount>,
+    // An optional column, absent or empty for the default currency.
+    #[serde(default)]
+    currency: Option<String>,
+    // Only for conversions, the currency to convert to.
+    #[serde(default)]
+    to_currency: Option<String>,
+    // Only for transfers, the client the funds go to.
+    #[serde(default)]
+    to_client: Option<ClientID>,
+    // An optional column, in seconds since the Unix epoch.
+    #[serde(default)]
+    timestamp: Option<Timestamp>,
+    // An optional column, the partner the transaction came through.
+    #[serde(default)]
+    partner: Option<PartnerID>,
+}
+
+impl TryFrom<TransactionRecord> for Transaction {
+    type Error = TransactionDeserializingError;
+
+    fn try_from(record: TransactionRecord) -> Result<Self, Self::Error> {
+        let currency = record.currency.as_deref().and_then(currency_code);
+        let mut disputed_amount = None;
+        let operation = match (record.tx_type, record.amount) {
+            (TransactionType::Deposit, Some(val)) => Operation::Deposit(val),
+            (TransactionType::Withdrawal, Some(val)) => Operation::Withdrawal(val),
+            (TransactionType::Convert, Some(val)) => {
+                // Both currencies have to be named for the rate between them to be known.
+                match (
+                    &currency,
+                    record.to_currency.as_deref().and_then(currency_code),
+                ) {
+                    (Some(_), Some(to)) => Operation::Convert(val, to),
+                    _ => return Err(TransactionDeserializingError::MissingCurrency(record.tx_id)),
+                }
+            }
+            (TransactionType::Transfer, Some(val)) => match record.to_client {
+                Some(to) => Operation::Transfer(val, to),
+                None => {
+                    return Err(TransactionDeserializingError::MissingDestination(
+                        record.tx_id,
+                    ))
+                }
+            },
+            (TransactionType::Authorize, Some(val)) => Operation::Authorize(val),
+            (
+                TransactionType::Deposit
+                | TransactionType::Withdrawal
+                | TransactionType::Convert
+                | TransactionType::Transfer
+                | TransactionType::Authorize,
+                None,
+            ) => {
+                return Err(TransactionDeserializingError::MissingAmount(record.tx_id));
+            }
+            // With an amount, they only act on that part of the referred transaction.
+            (
+                TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback,
+                val,
+            ) => {
+                disputed_amount = val;
+                match record.tx_type {
+                    TransactionType::Dispute => Operation::Dispute,
+                    TransactionType::Resolve => Operation::Resolve,
+                    _ => Operation::Chargeback,
+                }
+            }
+            // They settle the whole authorization, whatever the amount says.
+            (TransactionType::Capture, _) => Operation::Capture,
+            (TransactionType::Release, _) => Operation::Release,
+        };
+        let currency = match operation {
+            Operation::Deposit(_)
+            | Operation::Withdrawal(_)
+            | Operation::Convert(..)
+            | Operation::Transfer(..)
+            | Operation::Authorize(_) => currency,
+            Operation::Dispute
+            | Operation::Resolve
+            | Operation::Chargeback
+            | Operation::Capture
+            | Operation::Release => None,
+        };
+        Ok(Self {
+            client_id: record.client_id,
+            tx_id: record.tx_id,
+            operation,
+            currency,
+            // Charged when processed, by the fee schedule of the engine.
+            fee: Amount::zero(),
+            disputed_amount,
+            timestamp: record.timestamp,
+            partner: record.partner,
+        })
+    }
+}
+
+// Currency codes are case insensitive and empty ones stand for the default currency.
+pub(crate) fn currency_code(code: &str) -> Option<Currency> {
+    let code = code.trim().to_uppercase();
+    (!code.is_empty()).then_some(code)
 }
 
 // Helps SerDe to deserialize the expected float amounts found as string into a fraction::Decimal
@@ -39,7 +277,8 @@ where
     let s = String::deserialize(deserializer)?;
     if s.is_empty() {
         // There are entries that will not deserialize an amount value.
-        // So far these are: Dispute, Resolve and Chargeback
+        // So far these are: Dispute, Resolve, Chargeback, Capture and Release.
+        // Whether that is consistent with the type of the entry is checked when converting it to a `Transaction`.
         return Ok(None);
     }
     match s.parse::<Decimal>() {
@@ -57,29 +296,110 @@ where
     }
 }
 
+#[derive(Debug)]
 pub enum TransactionDeserializingError {
     UnableToParseAmount(String),
     NegativeAmount(String),
+    // A deposit, withdrawal, conversion, transfer or authorization came without the amount to move.
+    MissingAmount(TransactionID),
+    // A conversion came without the currency to convert from or the one to convert to.
+    MissingCurrency(TransactionID),
+    // A transfer came without the client to transfer to.
+    MissingDestination(TransactionID),
+}
+
+impl TransactionDeserializingError {
+    // A stable, machine-readable name for the variant.
+    // It prefixes the message of the error so it can be recognized after SerDe and csv wrapped it.
+    pub fn code(&self) -> &'static str {
+        match self {
+            TransactionDeserializingError::UnableToParseAmount(_) => "UnableToParseAmount",
+            TransactionDeserializingError::NegativeAmount(_) => "NegativeAmount",
+            TransactionDeserializingError::MissingAmount(_) => "MissingAmount",
+            TransactionDeserializingError::MissingCurrency(_) => "MissingCurrency",
+            TransactionDeserializingError::MissingDestination(_) => "MissingDestination",
+        }
+    }
 }
 
 impl fmt::Display for TransactionDeserializingError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "amount must be a positive float")
+        match self {
+            TransactionDeserializingError::UnableToParseAmount(val)
+            | TransactionDeserializingError::NegativeAmount(val) => {
+                write!(
+                    f,
+                    "{}: amount must be a positive float, got {}",
+                    self.code(),
+                    val
+                )
+            }
+            TransactionDeserializingError::MissingAmount(tx_id) => {
+                write!(
+                    f,
+                    "{}: deposits, withdrawals, conversions, transfers and authorizations must have an amount, transaction {} has none",
+                    self.code(),
+                    tx_id
+                )
+            }
+            TransactionDeserializingError::MissingCurrency(tx_id) => {
+                write!(
+                    f,
+                    "{}: conversions must have a currency and a currency to convert to, transaction {} misses one",
+                    self.code(),
+                    tx_id
+                )
+            }
+            TransactionDeserializingError::MissingDestination(tx_id) => {
+                write!(
+                    f,
+                    "{}: transfers must have a client to transfer to, transaction {} has none",
+                    self.code(),
+                    tx_id
+                )
+            }
+        }
     }
 }
 
 #[derive(Debug)]
 pub enum TransactionProcessingError {
     InsufficientAvailableFunds((TransactionID, Amount)),
+    // The account has credit, but the amount is more than its available funds plus its credit limit.
+    CreditLimitExceeded((TransactionID, Amount)),
+    // The account of the client, or the one a transfer goes to, is locked.
     TargetAccountLocked(TransactionID),
     NotFound(TransactionID),
     InconsistentOperation,
+    // There is no rate to convert between the currencies of the conversion.
+    RateNotFound(TransactionID),
+    // A dispute, resolve or chargeback came for more than what can be disputed, or than what is in dispute.
+    ExceedsDisputedAmount((TransactionID, Amount)),
+    // The dispute came later than the window to dispute the transaction allows.
+    DisputeWindowClosed(TransactionID),
+    // The disputed transaction is a withdrawal and the policy for disputes on withdrawals is to reject them.
+    WithdrawalDisputeRefused(TransactionID),
+    // The transaction breaks the rule with this name, which rejects what breaks it.
+    RuleViolated((TransactionID, String)),
+    // The deposit came through a partner restricted since the dispute or chargeback ratio of its clients went over a limit.
+    PartnerRestricted(TransactionID),
+    // The transaction is for a blocked client, goes to one, or came through a blocked partner.
+    Blocked(TransactionID),
+    // Not about the transaction itself but about where the engine keeps its state.
+    // The processing can't go on reliably after it.
+    StorageUnavailable(StorageError),
 }
 impl error::Error for TransactionProcessingError {}
 
+impl From<StorageError> for TransactionProcessingError {
+    fn from(error: StorageError) -> Self {
+        TransactionProcessingError::StorageUnavailable(error)
+    }
+}
+
 impl fmt::Display for TransactionProcessingError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
+        match self {
             TransactionProcessingError::NotFound(tx_id) => {
                 write!(f, "Unable to process {}, transaction not found. Assuming partner's data inconsistency.", tx_id)
             }
@@ -93,12 +413,69 @@ impl fmt::Display for TransactionProcessingError {
                     val, tx_id
                 )
             }
+            TransactionProcessingError::CreditLimitExceeded((tx_id, val)) => {
+                write!(
+                    f,
+                    "Unable to process {:.4} in transaction {}, it's over the credit limit",
+                    val, tx_id
+                )
+            }
             TransactionProcessingError::InconsistentOperation => {
                 write!(
                     f,
                     "The targeted account doesn't match the account of the referred transaction"
                 )
             }
+            TransactionProcessingError::RateNotFound(tx_id) => {
+                write!(
+                    f,
+                    "Unable to process {}, there is no rate between its currencies",
+                    tx_id
+                )
+            }
+            TransactionProcessingError::ExceedsDisputedAmount((tx_id, val)) => {
+                write!(
+                    f,
+                    "Unable to process {:.4} of transaction {}, it's more than what is in dispute",
+                    val, tx_id
+                )
+            }
+            TransactionProcessingError::DisputeWindowClosed(tx_id) => {
+                write!(
+                    f,
+                    "Unable to dispute {}, the window to dispute it is closed",
+                    tx_id
+                )
+            }
+            TransactionProcessingError::WithdrawalDisputeRefused(tx_id) => {
+                write!(
+                    f,
+                    "Unable to dispute {}, withdrawals can't be disputed",
+                    tx_id
+                )
+            }
+            TransactionProcessingError::RuleViolated((tx_id, rule)) => {
+                write!(
+                    f,
+                    "Unable to process {}, it breaks the rule {}",
+                    tx_id, rule
+                )
+            }
+            TransactionProcessingError::PartnerRestricted(tx_id) => {
+                write!(
+                    f,
+                    "Unable to process {}, deposits through its partner are restricted",
+                    tx_id
+                )
+            }
+            TransactionProcessingError::Blocked(tx_id) => {
+                write!(
+                    f,
+                    "Unable to process {}, it involves a blocked client or partner",
+                    tx_id
+                )
+            }
+            TransactionProcessingError::StorageUnavailable(e) => write!(f, "{}", e),
         }
     }
 }
@@ -1,93 +1,712 @@
-use std::fs::File;
+use std::{
+    error, fmt,
+    io::{self, Read},
+};
 
-use csv::Reader;
+use csv::{Reader, StringRecord};
+use fraction::Zero;
 
-use crate::models::{
-    account::{Account, Accounts},
-    disputes::Disputes,
-    transaction::{Transaction, TransactionProcessingError, Transactions},
+use crate::{
+    admin::AdminOperation,
+    blocklist::Blocklist,
+    fees::FeeSchedule,
+    journal::{read_journal, Applied, Journal, JournalError},
+    limits::CreditLimits,
+    models::{
+        account::{Account, Accounts, Processed},
+        disputes::{Dispute, DisputeRules, Disputes},
+        money::{Money, Rounding},
+        restrictions::Activity,
+        shared::{Amount, ClientID, PartnerID, Timestamp, TransactionID},
+        transaction::{
+            Operation, StoredTransaction, Transaction, TransactionProcessingError, Transactions,
+        },
+    },
+    rates::RateTable,
+    ratios::RatioMonitor,
+    rejections::{IgnoredRejections, Rejection, RejectionReason, RejectionSink},
+    reports::StrandedDispute,
+    rules::{FlaggedTransaction, RuleSet},
+    snapshot::Snapshot,
+    storage::{AccountStore, DisputeStore, StorageError, TransactionStore},
 };
 
-#[derive(Default)]
-pub struct PaymentsEngine {
-    pub accounts: Accounts,
-    pub transactions: Transactions,
-    pub disputes: Disputes,
+#[derive(Debug)]
+pub enum InputProcessingError {
+    // The input itself could not be read anymore (not to be confused with a record that is invalid).
+    UnreadableInput(csv::Error),
+    // The rejected records could not be reported.
+    UnwritableRejections(io::Error),
+    // The applied operations could not be journaled.
+    UnwritableJournal(io::Error),
+    // The state of the engine could not be read or written.
+    UnavailableStorage(StorageError),
+}
+
+impl fmt::Display for InputProcessingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputProcessingError::UnreadableInput(e) => {
+                write!(f, "Unable to read the input: {}", e)
+            }
+            InputProcessingError::UnwritableRejections(e) => {
+                write!(f, "Unable to report rejected records: {}", e)
+            }
+            InputProcessingError::UnwritableJournal(e) => {
+                write!(f, "Unable to journal applied operations: {}", e)
+            }
+            InputProcessingError::UnavailableStorage(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for InputProcessingError {}
+
+impl From<io::Error> for InputProcessingError {
+    fn from(error: io::Error) -> Self {
+        InputProcessingError::UnwritableRejections(error)
+    }
+}
+
+// The engine keeps its state in the given stores, which by default are the in-memory `HashMap` indexes.
+pub struct PaymentsEngine<A = Accounts, T = Transactions, D = Disputes> {
+    pub accounts: A,
+    pub transactions: T,
+    pub disputes: D,
+    // When present, every applied operation gets appended to it.
+    pub journal: Option<Journal>,
+    // The rates conversions are made with.
+    pub rates: RateTable,
+    // The fees charged on deposits and withdrawals, and the account collecting them.
+    pub fees: FeeSchedule,
+    // How far below zero the available funds of each client can go.
+    pub credit_limits: CreditLimits,
+    // What transactions are checked against before being processed.
+    pub rules: RuleSet,
+    // The transactions processed although they broke rules flagging them.
+    pub flagged: Vec<FlaggedTransaction>,
+    // The dispute and chargeback ratios of the clients and partners, restricting the deposits of the ones over the limits.
+    pub ratios: RatioMonitor,
+    // The clients and partners nothing is processed for.
+    pub blocklist: Blocklist,
+    // What disputes on withdrawals do, and for how long transactions can be disputed and disputes stay pending.
+    pub dispute_rules: DisputeRules,
+    // After how many further transactions of its account a pending authorization expires, releasing what it held.
+    pub authorization_expiry: Option<u32>,
+    // The latest time the engine knows of, from the timestamps of the transactions it processed.
+    pub clock: Option<Timestamp>,
+    // The earliest time a pending dispute may expire at, so they are only looked at once the clock reaches it.
+    pub(crate) next_expiry: Timestamp,
+    // How amounts get rounded when the accounts are rendered.
+    pub rounding: Rounding,
+}
+
+impl Default for PaymentsEngine {
+    fn default() -> Self {
+        PaymentsEngine::with_stores(
+            Accounts::default(),
+            Transactions::default(),
+            Disputes::default(),
+        )
+    }
 }
 
 // This engine will process transactions and operations related to these and their respective accounts.
 // It's designed to preserve the accounts integrity and continuous operation.
 // For example, it ignores and move on processing the next piece of input when some input record could not be parsed or,
 // after being parsed, when there was any `TransactionProcessingError` case that prevented completing an operation.
-impl PaymentsEngine {
-    pub fn process_transactions_from(
+// Every ignored record is reported to a `RejectionSink` so it can be followed up with the partner.
+impl<A: AccountStore, T: TransactionStore, D: DisputeStore> PaymentsEngine<A, T, D> {
+    pub fn with_stores(accounts: A, transactions: T, disputes: D) -> Self {
+        Self {
+            accounts,
+            transactions,
+            disputes,
+            journal: None,
+            rates: RateTable::default(),
+            fees: FeeSchedule::default(),
+            credit_limits: CreditLimits::default(),
+            rules: RuleSet::default(),
+            flagged: vec![],
+            ratios: RatioMonitor::default(),
+            blocklist: Blocklist::default(),
+            dispute_rules: DisputeRules::default(),
+            authorization_expiry: None,
+            clock: None,
+            next_expiry: 0,
+            rounding: Rounding::default(),
+        }
+    }
+
+    // Processes the transactions coming from any source of input (files, stdin, sockets, pipes, decompressors, etc).
+    pub fn process_transactions_from<R: Read>(
         &mut self,
-        mut reader: Reader<File>,
-    ) -> Result<(), TransactionProcessingError> {
-        // With flatten here it ignores issues during parsing
-        for tx in reader.deserialize::<Transaction>().flatten() {
-            match self.process(tx) {
-                Ok(()) => {}
-                Err(TransactionProcessingError::TargetAccountLocked(_tx_id)) => {
-                    // Ignore and continue processing the next input operation.
-                    // In a real system tx_id should be logged or published an event to a queue for follow up?
-                }
-                Err(TransactionProcessingError::NotFound(_tx_id)) => {
-                    // Ignore and continue processing the next input operation.
-                    // In a real system tx_id should be logged or published an event to a queue for follow up?
-                }
-                Err(TransactionProcessingError::InsufficientAvailableFunds(_tx_id)) => {
-                    // Ignore and continue processing the next input operation.
-                    // In a real system tx_id should be logged or published an event to a queue for follow up?
+        reader: Reader<R>,
+    ) -> Result<(), InputProcessingError> {
+        self.process_transactions_reporting_to(reader, &mut IgnoredRejections)
+    }
+
+    // Same as `process_transactions_from` but reporting every ignored record to the given sink.
+    pub fn process_transactions_reporting_to<R: Read>(
+        &mut self,
+        mut reader: Reader<R>,
+        rejections: &mut dyn RejectionSink,
+    ) -> Result<(), InputProcessingError> {
+        let headers = if reader.has_headers() {
+            Some(
+                reader
+                    .headers()
+                    .map_err(InputProcessingError::UnreadableInput)?
+                    .clone(),
+            )
+        } else {
+            None
+        };
+        for result in reader.records() {
+            match result {
+                Ok(record) => self.process_record(&record, headers.as_ref(), rejections)?,
+                Err(e) if e.is_io_error() => {
+                    return Err(InputProcessingError::UnreadableInput(e));
                 }
-                Err(TransactionProcessingError::InconsistentOperation) => {
-                    // Note: In a real payment engine, cases like this would typically generate system events
-                    // that are published to a high-capacity shared queue, which can be observed by other
-                    // programs. These observer programs can be decoupled client applications with the
-                    // appropriate concerns to handle policies for reacting to such cases.
-                    //
-                    // For example, should they just re-try after a while? Or, if an operation was inconsistent,
-                    // it might require logging or queuing for investigation with a partner.
+                Err(e) => rejections.reject(Rejection::unreadable(&e))?,
+            }
+        }
+        rejections.flush()?;
+        if let Some(journal) = self.journal.as_mut() {
+            journal
+                .flush()
+                .map_err(InputProcessingError::UnwritableJournal)?;
+        }
+        Ok(())
+    }
+
+    // Deserializes and processes one input record, reporting it to the sink if it had to be ignored.
+    pub fn process_record(
+        &mut self,
+        record: &StringRecord,
+        headers: Option<&StringRecord>,
+        rejections: &mut dyn RejectionSink,
+    ) -> Result<(), InputProcessingError> {
+        self.check_journal()?;
+        // A blocklist that can't be read again is reported, and the previous one is kept.
+        if let Err(e) = self.blocklist.refresh() {
+            eprintln!("Unable to reload the blocklist: {}", e);
+        }
+        let outcome = match deserialize_transaction(record, headers) {
+            Err(e) => Err(Rejection::new(record, RejectionReason::from(&e))),
+            // Note: In a real payment engine, the `TransactionProcessingError` cases would typically generate system events
+            // that are published to a high-capacity shared queue, which can be observed by other
+            // programs. These observer programs can be decoupled client applications with the
+            // appropriate concerns to handle policies for reacting to such cases.
+            //
+            // For example, should they just re-try after a while? Or, if an operation was inconsistent,
+            // it might require queuing for investigation with a partner.
+            Ok(tx) => match self.process(tx) {
+                Ok(_) => Ok(()),
+                Err(e) => match RejectionReason::try_from(&e) {
+                    Ok(reason) => Err(Rejection::new(record, reason).with_rule_of(&e)),
+                    Err(()) => return Err(fatal_error_from(e)),
+                },
+            },
+        };
+        if let Err(rejection) = outcome {
+            rejections.reject(rejection)?;
+        }
+        // Stop as soon as the journal is not reliable anymore.
+        self.check_journal()
+    }
+
+    // Once the journal failed, nothing else can be applied: it would be missing from the journal for good.
+    pub fn check_journal(&self) -> Result<(), InputProcessingError> {
+        match self.journal.as_ref() {
+            Some(journal) => journal
+                .check()
+                .map_err(InputProcessingError::UnwritableJournal),
+            None => Ok(()),
+        }
+    }
+
+    // Everything processing the transaction writes to the stores is kept at once, or none of it if one write fails,
+    // so both legs of a transfer (and the fee it paid) are applied or neither is.
+    // It tells whether the transaction was applied or ignored, as repeats are.
+    pub fn process(
+        &mut self,
+        transaction: Transaction,
+    ) -> Result<Processed, TransactionProcessingError> {
+        self.process_as(transaction, false)
+    }
+
+    // Replayed transactions are charged the fee they were journaled with, whatever the schedule of the engine is,
+    // and leave the house account to the journal entries crediting it.
+    fn process_as(
+        &mut self,
+        transaction: Transaction,
+        replayed: bool,
+    ) -> Result<Processed, TransactionProcessingError> {
+        let (client_id, clock) = (transaction.client_id, self.clock);
+        self.begin()?;
+        let outcome = self.apply_transaction(transaction, replayed);
+        match outcome {
+            Err(TransactionProcessingError::StorageUnavailable(_)) => {
+                // The write that failed is what gets reported, whether rolling back works or not.
+                let _ = self.rollback();
+            }
+            Err(_) if self.clock != clock => {
+                self.commit()?;
+                self.journal_clock(client_id)?;
+            }
+            _ => self.commit()?,
+        }
+        outcome
+    }
+
+    // Rejected transactions are not journaled, but the time they tell still moves the clock (and expires disputes).
+    fn journal_clock(&mut self, client_id: ClientID) -> Result<(), StorageError> {
+        if let (Some(_), Some(clock)) = (self.journal.as_ref(), self.clock) {
+            let account = self
+                .accounts
+                .get_account(client_id)?
+                .unwrap_or_else(|| Account::new(client_id));
+            if let Some(journal) = self.journal.as_mut() {
+                journal.append_clock(clock, &account);
+            }
+        }
+        Ok(())
+    }
+
+    fn begin(&mut self) -> Result<(), StorageError> {
+        self.accounts.begin()?;
+        self.transactions.begin()?;
+        self.disputes.begin()
+    }
+
+    fn commit(&mut self) -> Result<(), StorageError> {
+        self.accounts.commit()?;
+        self.transactions.commit()?;
+        self.disputes.commit()
+    }
+
+    fn rollback(&mut self) -> Result<(), StorageError> {
+        self.accounts.rollback()?;
+        self.transactions.rollback()?;
+        self.disputes.rollback()
+    }
+
+    fn apply_transaction(
+        &mut self,
+        mut transaction: Transaction,
+        replayed: bool,
+    ) -> Result<Processed, TransactionProcessingError> {
+        // Nothing touching a blocked client, or coming through a blocked partner, goes any further.
+        if self.is_blocked(&transaction)? {
+            return Err(TransactionProcessingError::Blocked(transaction.tx_id));
+        }
+        // The rules only know the time the input tells, whatever the clock of the engine (or of a shard) is.
+        let made_at = transaction.timestamp;
+        // Time only moves forward, expiring the disputes pending for too long on its way.
+        if let Some(timestamp) = transaction.timestamp {
+            self.advance_clock_to(timestamp)?;
+        }
+        let is_dispute = matches!(transaction.operation, Operation::Dispute);
+        if is_dispute
+            || matches!(
+                transaction.operation,
+                Operation::Resolve | Operation::Chargeback
+            )
+        {
+            transaction.timestamp = transaction.timestamp.or(self.clock);
+        }
+        let opened_at = transaction.timestamp;
+        // Fees are charged as the schedule of the engine says, and journaled with the transaction.
+        if !replayed {
+            transaction.fee = self.fees.fee_for(&transaction);
+        }
+        // Funds can only be transferred to another client.
+        if let Operation::Transfer(_, to) = transaction.operation {
+            if to == transaction.client_id {
+                return Err(TransactionProcessingError::InconsistentOperation);
+            }
+        }
+        // Nothing comes in anymore through a partner whose clients dispute too much.
+        if let (Operation::Deposit(_), Some(partner)) =
+            (&transaction.operation, transaction.partner)
+        {
+            if self.ratios.restricts(partner) {
+                return Err(TransactionProcessingError::PartnerRestricted(
+                    transaction.tx_id,
+                ));
+            }
+        }
+        // The rules are checked before anything is applied, and remember what was applied.
+        let flagged: Vec<FlaggedTransaction> = self
+            .rules
+            .check(&transaction, made_at)?
+            .into_iter()
+            .map(|rule| FlaggedTransaction {
+                tx_id: transaction.tx_id,
+                client_id: transaction.client_id,
+                rule,
+            })
+            .collect();
+        let checked = (!self.rules.is_empty()).then(|| transaction.clone());
+        let monitored = match self.ratios.is_empty() {
+            true => None,
+            false => Some((transaction.clone(), self.partner_of(&transaction)?)),
+        };
+        let tx_id = transaction.tx_id;
+        // Only worth copying the transaction if it's going to be journaled.
+        let journaled = self.journal.as_ref().map(|_| transaction.clone());
+        // Accounts are created on demand, with the credit limit the engine has for them,
+        // but only kept if the transaction creating them is applied (not rejected nor ignored).
+        let existing = self.accounts.get_account(transaction.client_id)?;
+        let is_new = existing.is_none();
+        let mut account = existing.unwrap_or_else(|| Account::new(transaction.client_id));
+        account.credit_limit = self.credit_limits.of(transaction.client_id);
+        let mut counterparty = match self.counterparty_of(&transaction)? {
+            Some(client_id) => Some(
+                self.accounts
+                    .get_account(client_id)?
+                    .unwrap_or_else(|| Account::new(client_id)),
+            ),
+            None => None,
+        };
+        let outcome = account.process(
+            transaction,
+            &mut self.transactions,
+            &mut self.disputes,
+            &self.rates,
+            &self.dispute_rules,
+            counterparty.as_mut(),
+        );
+        // Only what did something counts, not repeats nor settling disputes that are not open.
+        let applied = matches!(outcome, Ok(Processed::Applied { .. }));
+        // Every applied transaction ages the other authorizations of the account.
+        if let (true, Some(expiry)) = (applied, self.authorization_expiry) {
+            account.age_authorizations(tx_id, expiry);
+        }
+        // Nor for the rules and the ratios.
+        if let (true, Some(tx)) = (applied, checked) {
+            self.rules.record(&tx, made_at);
+        }
+        if applied {
+            self.flagged.extend(flagged);
+        }
+        if let (true, Some((tx, partner))) = (applied, monitored) {
+            self.ratios.record(&tx, partner, &mut account);
+        }
+        // Only journaled once it's in the stores, as they may fail to keep it.
+        let journaled = match (&outcome, journaled) {
+            (Ok(_), Some(tx)) => Some((tx, account.clone())),
+            _ => None,
+        };
+        if applied || !is_new {
+            self.accounts.put_account(account)?;
+        }
+        // Nothing happens to the other account when the operation was not applied, not even being created.
+        if let (true, Some(counterparty)) = (applied, counterparty) {
+            self.accounts.put_account(counterparty)?;
+        }
+        let processed = outcome?;
+        let collected = match &processed {
+            Processed::Applied { fee: Some(fee) } if !replayed => Some(self.collect_fee(fee)?),
+            _ => None,
+        };
+        if let (true, Some(expiry), Some(opened_at)) =
+            (is_dispute, self.dispute_rules.expiry, opened_at)
+        {
+            self.next_expiry = self.next_expiry.min(opened_at + expiry);
+        }
+        if let (Some(journal), Some((tx, account))) = (self.journal.as_mut(), journaled) {
+            journal.append(&tx, &account);
+            if let Some((fee, house)) = collected {
+                journal.append_fee(&fee, &house);
+            }
+        }
+        Ok(processed)
+    }
+
+    // If the transaction is for a blocked client, goes to one, or comes through a blocked partner.
+    fn is_blocked(&self, tx: &Transaction) -> Result<bool, StorageError> {
+        if self.blocklist.is_empty() {
+            return Ok(false);
+        }
+        Ok(self.blocklist.blocks_client(tx.client_id)
+            || self.blocklist.blocks_partner(tx.partner)
+            || self
+                .counterparty_of(tx)?
+                .is_some_and(|client_id| self.blocklist.blocks_client(client_id)))
+    }
+
+    // The other client a transaction acts on: the one a transfer goes to,
+    // also for the disputes, resolves and chargebacks of the transfer.
+    fn counterparty_of(&self, tx: &Transaction) -> Result<Option<ClientID>, StorageError> {
+        let referred = match tx.operation {
+            Operation::Transfer(_, to) => return Ok(Some(to)),
+            Operation::Dispute | Operation::Resolve | Operation::Chargeback => {
+                self.transactions.get_transaction(tx.tx_id)?
+            }
+            _ => None,
+        };
+        Ok(match referred {
+            Some(Transaction {
+                client_id,
+                operation: Operation::Transfer(_, to),
+                ..
+            }) if client_id == tx.client_id => Some(to),
+            _ => None,
+        })
+    }
+
+    // The partner a transaction is attributed to: the one it came through,
+    // or for disputes, resolves and chargebacks the one the disputed transaction came through.
+    fn partner_of(&self, tx: &Transaction) -> Result<Option<PartnerID>, StorageError> {
+        Ok(match tx.operation {
+            Operation::Dispute | Operation::Resolve | Operation::Chargeback => self
+                .transactions
+                .get_transaction(tx.tx_id)?
+                .and_then(|referred| referred.partner),
+            _ => tx.partner,
+        })
+    }
+
+    // Moves the clock of the engine forward to the given time, if it's later than the one it knows of.
+    pub fn advance_clock_to(&mut self, now: Timestamp) -> Result<(), StorageError> {
+        if self.clock.is_some_and(|clock| clock >= now) {
+            return Ok(());
+        }
+        self.clock = Some(now);
+        if self.dispute_rules.expiry.is_some() && now >= self.next_expiry {
+            self.expire_disputes(now)?;
+        }
+        Ok(())
+    }
+
+    // Expires the disputes pending for longer than the rules allow by the given time, releasing what they held.
+    fn expire_disputes(&mut self, now: Timestamp) -> Result<(), StorageError> {
+        let mut next_expiry = Timestamp::MAX;
+        for dispute in self.pending_disputes()? {
+            match dispute.expires_at(&self.dispute_rules) {
+                Some(expires_at) if expires_at <= now => {
+                    let mut account = self
+                        .accounts
+                        .get_account(dispute.client_id)?
+                        .unwrap_or_else(|| Account::new(dispute.client_id));
+                    let mut recipient = match self.transactions.get_transaction(dispute.tx_id)? {
+                        Some(Transaction {
+                            operation: Operation::Transfer(_, to),
+                            ..
+                        }) => self.accounts.get_account(to)?,
+                        _ => None,
+                    };
+                    let dispute =
+                        account.expire_dispute(dispute, &self.transactions, recipient.as_mut())?;
+                    self.disputes.put_dispute(dispute)?;
+                    self.accounts.put_account(account)?;
+                    if let Some(recipient) = recipient {
+                        self.accounts.put_account(recipient)?;
+                    }
                 }
+                Some(expires_at) => next_expiry = next_expiry.min(expires_at),
+                None => {}
             }
         }
+        self.next_expiry = next_expiry;
         Ok(())
     }
 
-    pub fn process_transactions_streaming_input(
+    // Applies the operations of operators, in order, on top of the current state.
+    pub fn apply_admin_operations(
         &mut self,
-        mut reader: Reader<&[u8]>,
-    ) -> Result<(), TransactionProcessingError> {
-        // With flatten here it ignores issues during parsing
-        for tx in reader.deserialize::<Transaction>().flatten() {
-            match self.process(tx) {
+        operations: Vec<AdminOperation>,
+    ) -> Result<(), InputProcessingError> {
+        for operation in operations {
+            self.apply_admin(operation)
+                .map_err(InputProcessingError::UnavailableStorage)?;
+        }
+        if let Some(journal) = self.journal.as_mut() {
+            journal
+                .flush()
+                .map_err(InputProcessingError::UnwritableJournal)?;
+        }
+        Ok(())
+    }
+
+    // Applies an operation of an operator on the account of its client, locked or not, journaling it as any other.
+    pub fn apply_admin(&mut self, operation: AdminOperation) -> Result<(), StorageError> {
+        let mut account = self
+            .accounts
+            .get_account(operation.client_id)?
+            .unwrap_or_else(|| Account::new(operation.client_id));
+        account.apply_admin(&operation.action);
+        if let Some(journal) = self.journal.as_mut() {
+            journal.append_admin(&operation, &account);
+        }
+        self.accounts.put_account(account)
+    }
+
+    // Moves the fee into the house account, created on demand as any other, telling what the account became.
+    fn collect_fee(&mut self, fee: &Money) -> Result<(Money, Account), StorageError> {
+        let account = self.credit_house(self.fees.house, fee)?;
+        Ok((fee.clone(), account))
+    }
+
+    fn credit_house(&mut self, house: ClientID, fee: &Money) -> Result<Account, StorageError> {
+        let mut account = self
+            .accounts
+            .get_account(house)?
+            .unwrap_or_else(|| Account::new(house));
+        account.collect_fee(fee);
+        self.accounts.put_account(account.clone())?;
+        Ok(account)
+    }
+
+    // Rebuilds the accounts, transactions and disputes by processing again every operation in the journal
+    // on top of the current state, making sure each one leads to the very same account state that was recorded for it.
+    pub fn replay<R: Read>(&mut self, journal: R) -> Result<(), JournalError> {
+        for entry in read_journal(journal) {
+            let entry = entry?;
+            let outcome = match &entry.applied {
+                Applied::Transaction(journaled) => self
+                    .process_as(journaled.transaction(entry.client_id), true)
+                    .map(|_| ()),
+                Applied::Fee { collected_fee } => self
+                    .credit_house(entry.client_id, collected_fee)
+                    .map(|_| ())
+                    .map_err(TransactionProcessingError::from),
+                Applied::Admin { admin } => self
+                    .apply_admin(admin.clone())
+                    .map_err(TransactionProcessingError::from),
+                Applied::Clock { clock } => self
+                    .advance_clock_to(*clock)
+                    .map_err(TransactionProcessingError::from),
+            };
+            match outcome {
                 Ok(()) => {}
-                Err(TransactionProcessingError::TargetAccountLocked(_tx_id)) => {
-                    // Ignore and continue processing the next input operation.
-                    // In a real system tx_id should be logged or published an event to a queue for follow up?
-                }
-                Err(TransactionProcessingError::NotFound(_tx_id)) => {
-                    // Ignore and continue processing the next input operation.
-                    // In a real system tx_id should be logged or published an event to a queue for follow up?
-                }
-                Err(TransactionProcessingError::InsufficientAvailableFunds(_tx_id)) => {
-                    // Ignore and continue processing the next input operation.
-                    // In a real system tx_id should be logged or published an event to a queue for follow up?
-                }
-                Err(TransactionProcessingError::InconsistentOperation) => {
-                    // Note: same observations as noted in `process_transactions_from`
+                Err(TransactionProcessingError::StorageUnavailable(e)) => {
+                    return Err(JournalError::Unreadable(e.to_string()));
                 }
+                Err(_) => return Err(JournalError::Diverged(entry.sequence)),
+            }
+            let mut account = self
+                .accounts
+                .get_account(entry.client_id)
+                .map_err(|e| JournalError::Unreadable(e.to_string()))?;
+            // The client of a rejected transaction moving the clock, or of an ignored one, may have no account.
+            if let Applied::Clock { .. } | Applied::Transaction(_) = entry.applied {
+                account = account.or_else(|| Some(Account::new(entry.client_id)));
+            }
+            if !account.is_some_and(|account| entry.matches(&account)) {
+                return Err(JournalError::Diverged(entry.sequence));
             }
         }
         Ok(())
     }
 
-    pub fn process(&mut self, transaction: Transaction) -> Result<(), TransactionProcessingError> {
-        let account = self
-            .accounts
-            .entry(transaction.client_id)
-            .or_insert(Account::new(transaction.client_id));
-        account.process(transaction, &mut self.transactions, &mut self.disputes)?;
+    // The dispute of the given transaction, if it was ever disputed, with every state it went through.
+    pub fn dispute_history(&self, tx_id: TransactionID) -> Result<Option<Dispute>, StorageError> {
+        self.disputes.get_dispute(tx_id)
+    }
+
+    // The disputes still holding funds, sorted by the ID of the disputed transaction.
+    pub fn pending_disputes(&self) -> Result<Vec<Dispute>, StorageError> {
+        let mut disputes = self.disputes.all_disputes()?;
+        disputes.retain(Dispute::is_open);
+        Ok(disputes)
+    }
+
+    // The open disputes on accounts whose restrictions block disputes, with the funds they keep held.
+    // Unless the dispute rules let them be settled, nothing but an operator can release those funds.
+    pub fn stranded_disputes(&self) -> Result<Vec<StrandedDispute>, StorageError> {
+        let mut stranded = vec![];
+        for dispute in self.pending_disputes()? {
+            let account = match self.accounts.get_account(dispute.client_id)? {
+                Some(account) if !account.allows(Activity::Disputes) => account,
+                _ => continue,
+            };
+            let transaction = self.transactions.get_transaction(dispute.tx_id)?;
+            let held = Money {
+                amount: match (&transaction, dispute.holds_funds) {
+                    (Some(t), true) => t
+                        .amount()
+                        .map_or(Amount::zero(), |val| dispute.outstanding_of(val)),
+                    _ => Amount::zero(),
+                },
+                currency: transaction.and_then(|t| t.currency),
+            };
+            stranded.push(StrandedDispute {
+                tx_id: dispute.tx_id,
+                client_id: dispute.client_id,
+                held,
+                status: account.status(),
+                restrictions: account.restrictions.clone(),
+                settleable: self.dispute_rules.settle_when_locked
+                    || account.can_settle_rest_of(&dispute),
+            });
+        }
+        Ok(stranded)
+    }
+
+    // Captures the current state so it can be restored later on, for example, to process tomorrow's input on top of it.
+    // Entries are sorted by ID so the same state always produces the same snapshot.
+    pub fn snapshot(&self) -> Result<Snapshot, StorageError> {
+        Ok(Snapshot {
+            accounts: self.accounts.all_accounts()?,
+            transactions: self
+                .transactions
+                .all_transactions()?
+                .iter()
+                .map(StoredTransaction::from)
+                .collect(),
+            disputes: self.disputes.all_disputes()?,
+            clock: self.clock,
+            ratios: self.ratios.state(),
+        })
+    }
+
+    // Puts everything in the snapshot into the stores of this engine.
+    pub fn restore(&mut self, snapshot: Snapshot) -> Result<(), StorageError> {
+        for account in snapshot.accounts {
+            self.accounts.put_account(account)?;
+        }
+        for tx in snapshot.transactions {
+            self.transactions.put_transaction(Transaction::from(tx))?;
+        }
+        for dispute in snapshot.disputes {
+            self.disputes.put_dispute(dispute)?;
+        }
+        // Time only moves forward, and the restored disputes are looked at again the next time it does.
+        if snapshot.clock > self.clock {
+            self.clock = snapshot.clock;
+        }
+        self.next_expiry = 0;
+        self.ratios.restore(snapshot.ratios);
         Ok(())
     }
 }
+
+pub(crate) fn fatal_error_from(error: TransactionProcessingError) -> InputProcessingError {
+    match error {
+        TransactionProcessingError::StorageUnavailable(e) => {
+            InputProcessingError::UnavailableStorage(e)
+        }
+        other => unreachable!("{} is a reason to reject a record", other),
+    }
+}
+
+pub(crate) fn deserialize_transaction(
+    record: &StringRecord,
+    headers: Option<&StringRecord>,
+) -> Result<Transaction, csv::Error> {
+    match headers {
+        Some(headers) if record.len() < headers.len() => {
+            // Complete the missing trailing fields (like the amount of a dispute) as empty ones,
+            // otherwise csv would not deserialize a record shorter than the headers.
+            let mut padded = record.clone();
+            while padded.len() < headers.len() {
+                padded.push_field("");
+            }
+            padded.deserialize(Some(headers))
+        }
+        _ => record.deserialize(headers),
+    }
+}
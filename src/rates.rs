@@ -0,0 +1,126 @@
+use std::{collections::HashMap, error, fmt, fs::File, io::Read};
+
+use fraction::Zero;
+use serde::Deserialize;
+
+use crate::{
+    input_ingestion::get_csv_reader_from,
+    models::{
+        money::Money,
+        shared::{Amount, Currency, TransactionID},
+    },
+};
+
+#[derive(Debug)]
+pub enum RatesError {
+    Unreadable(String),
+    // The rate at this line of the rates file is not a positive number.
+    InvalidRate(u64),
+}
+
+impl fmt::Display for RatesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RatesError::Unreadable(reason) => write!(f, "Unable to read the rates: {}", reason),
+            RatesError::InvalidRate(line) => {
+                write!(f, "The rate at line {} is not a positive number", line)
+            }
+        }
+    }
+}
+
+impl error::Error for RatesError {}
+
+// A row of the rates file, for example:
+// from, to, rate, effective_from_tx
+// USD, EUR, 0.92, 1
+#[derive(Debug, Deserialize)]
+struct RateRecord {
+    from: Currency,
+    to: Currency,
+    rate: String,
+    effective_from_tx: TransactionID,
+}
+
+// The conversion rates between currencies. Each rate is effective for the transactions with an ID
+// starting at its `effective_from_tx` and until the one of the next rate of the same currencies.
+// When there is no rate from one currency to another, the inverse of the rate the other way around is used.
+#[derive(Debug, Clone, Default)]
+pub struct RateTable {
+    rates: HashMap<(Currency, Currency), Vec<(TransactionID, Amount)>>,
+}
+
+impl RateTable {
+    pub fn open(path: &str) -> Result<Self, RatesError> {
+        let file = File::open(path).map_err(|e| RatesError::Unreadable(e.to_string()))?;
+        RateTable::read_from(file)
+    }
+
+    pub fn read_from<R: Read>(input: R) -> Result<Self, RatesError> {
+        let mut table = RateTable::default();
+        let mut reader = get_csv_reader_from(input);
+        let headers = reader
+            .headers()
+            .map_err(|e| RatesError::Unreadable(e.to_string()))?
+            .clone();
+        for result in reader.records() {
+            let row = result.map_err(|e| RatesError::Unreadable(e.to_string()))?;
+            let line = row.position().map_or(0, |position| position.line());
+            let record: RateRecord = row
+                .deserialize(Some(&headers))
+                .map_err(|e| RatesError::Unreadable(e.to_string()))?;
+            let rate = match record.rate.parse::<Amount>() {
+                Ok(rate) if rate > Amount::zero() => rate,
+                _ => return Err(RatesError::InvalidRate(line)),
+            };
+            table.insert(
+                &record.from.to_uppercase(),
+                &record.to.to_uppercase(),
+                record.effective_from_tx,
+                rate,
+            );
+        }
+        Ok(table)
+    }
+
+    pub fn insert(&mut self, from: &str, to: &str, effective_from_tx: TransactionID, rate: Amount) {
+        let rates = self
+            .rates
+            .entry((from.to_owned(), to.to_owned()))
+            .or_default();
+        rates.retain(|(tx_id, _)| *tx_id != effective_from_tx);
+        rates.push((effective_from_tx, rate));
+        rates.sort_by_key(|(tx_id, _)| *tx_id);
+    }
+
+    // The rate to convert from one currency to another effective for the given transaction.
+    pub fn rate(&self, from: &str, to: &str, tx_id: TransactionID) -> Option<Amount> {
+        if from == to {
+            return Some(Amount::from(1));
+        }
+        effective_rate(self.rates.get(&(from.to_owned(), to.to_owned())), tx_id).or_else(|| {
+            effective_rate(self.rates.get(&(to.to_owned(), from.to_owned())), tx_id)
+                .map(|rate| rate.recip())
+        })
+    }
+
+    // The money in the other currency, converted with the rate effective for the given transaction.
+    pub fn convert(&self, money: &Money, to: &str, tx_id: TransactionID) -> Option<Money> {
+        let rate = self.rate(money.currency.as_deref()?, to, tx_id)?;
+        Some(Money {
+            amount: money.amount * rate,
+            currency: Some(to.to_owned()),
+        })
+    }
+}
+
+fn effective_rate(
+    rates: Option<&Vec<(TransactionID, Amount)>>,
+    tx_id: TransactionID,
+) -> Option<Amount> {
+    rates?
+        .iter()
+        .rev()
+        .find(|(effective_from_tx, _)| *effective_from_tx <= tx_id)
+        .map(|(_, rate)| *rate)
+}
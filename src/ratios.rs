@@ -0,0 +1,293 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    error, fmt,
+    fs::File,
+    io::Read,
+};
+
+use fraction::Zero;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    input_ingestion::get_csv_reader_from,
+    models::{
+        account::Account,
+        restrictions::Restriction,
+        shared::{Amount, ClientID, PartnerID, TransactionID},
+        transaction::{Operation, Transaction},
+    },
+};
+
+#[derive(Debug)]
+pub enum RatiosError {
+    Unreadable(String),
+    // The limit at this line of the ratio limits file is not a non negative amount.
+    InvalidLimit(u64),
+}
+
+impl fmt::Display for RatiosError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RatiosError::Unreadable(reason) => {
+                write!(f, "Unable to read the ratio limits: {}", reason)
+            }
+            RatiosError::InvalidLimit(line) => {
+                write!(f, "The ratio limit at line {} is not valid", line)
+            }
+        }
+    }
+}
+
+impl error::Error for RatiosError {}
+
+// Who a ratio is tracked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Client,
+    Partner,
+}
+
+// The client or the partner a ratio is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Subject {
+    Client(ClientID),
+    Partner(PartnerID),
+}
+
+impl Subject {
+    pub fn scope(&self) -> Scope {
+        match self {
+            Subject::Client(_) => Scope::Client,
+            Subject::Partner(_) => Scope::Partner,
+        }
+    }
+}
+
+// What is counted per deposit, withdrawal or transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Ratio {
+    Dispute,
+    Chargeback,
+}
+
+// A limit over which the client or partner is restricted, once it made at least `min_transactions`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RatioLimit {
+    pub scope: Scope,
+    pub ratio: Ratio,
+    pub limit: Amount,
+    pub min_transactions: u64,
+}
+
+// A row of the ratio limits file, for example:
+// scope, ratio, limit, min_transactions
+// client, dispute, 0.2, 10
+// partner, chargeback, 0.01, 1000
+#[derive(Debug, Deserialize)]
+struct RatioLimitRecord {
+    scope: Scope,
+    ratio: Ratio,
+    #[serde(default)]
+    limit: String,
+    #[serde(default)]
+    min_transactions: Option<u64>,
+}
+
+// The deposits, withdrawals and transfers of a client or partner, and the disputes and chargebacks on them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Counts {
+    pub transactions: u64,
+    pub disputes: u64,
+    pub chargebacks: u64,
+}
+
+impl Counts {
+    // The disputes or chargebacks per transaction, none before any transaction.
+    pub fn of(&self, ratio: Ratio) -> Option<Amount> {
+        let count = match ratio {
+            Ratio::Dispute => self.disputes,
+            Ratio::Chargeback => self.chargebacks,
+        };
+        (self.transactions > 0).then(|| Amount::from(count) / Amount::from(self.transactions))
+    }
+}
+
+// Why a client or a partner got restricted: the ratio that went over its limit, and the transaction that did it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RatioTrigger {
+    pub tx_id: TransactionID,
+    pub subject: Subject,
+    pub ratio: Ratio,
+    pub value: Amount,
+    pub limit: Amount,
+    pub counts: Counts,
+}
+
+// What a monitor counted and the partners it restricted, for snapshots to keep them (the limits come from the options).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RatioState {
+    #[serde(default)]
+    pub counts: Vec<(Subject, Counts)>,
+    #[serde(default)]
+    pub restricted_partners: Vec<PartnerID>,
+}
+
+impl RatioState {
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty() && self.restricted_partners.is_empty()
+    }
+}
+
+// Tracks the dispute and chargeback ratios of every client and partner since the engine started,
+// restricting the deposits of the ones going over the limits.
+// Disputes and chargebacks count for the partner the disputed transaction came through.
+// Snapshots keep what it counted, but the stores don't, so with SQLite it only knows what happened since it started.
+#[derive(Debug, Clone, Default)]
+pub struct RatioMonitor {
+    limits: Vec<RatioLimit>,
+    counts: HashMap<Subject, Counts>,
+    restricted_partners: BTreeSet<PartnerID>,
+    // Every time a client or partner got restricted, in the order it happened.
+    pub triggers: Vec<RatioTrigger>,
+}
+
+impl RatioMonitor {
+    pub fn new(limits: Vec<RatioLimit>) -> Self {
+        Self {
+            limits,
+            ..Default::default()
+        }
+    }
+
+    pub fn open(path: &str) -> Result<Self, RatiosError> {
+        let file = File::open(path).map_err(|e| RatiosError::Unreadable(e.to_string()))?;
+        RatioMonitor::read_from(file)
+    }
+
+    pub fn read_from<R: Read>(input: R) -> Result<Self, RatiosError> {
+        let mut limits = vec![];
+        let mut reader = get_csv_reader_from(input);
+        let headers = reader
+            .headers()
+            .map_err(|e| RatiosError::Unreadable(e.to_string()))?
+            .clone();
+        for result in reader.records() {
+            let row = result.map_err(|e| RatiosError::Unreadable(e.to_string()))?;
+            let line = row.position().map_or(0, |position| position.line());
+            let record: RatioLimitRecord = row
+                .deserialize(Some(&headers))
+                .map_err(|e| RatiosError::Unreadable(e.to_string()))?;
+            let limit = record
+                .limit
+                .parse::<Amount>()
+                .ok()
+                .filter(|limit| *limit >= Amount::zero())
+                .ok_or(RatiosError::InvalidLimit(line))?;
+            limits.push(RatioLimit {
+                scope: record.scope,
+                ratio: record.ratio,
+                limit,
+                min_transactions: record.min_transactions.unwrap_or(0),
+            });
+        }
+        Ok(RatioMonitor::new(limits))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.limits.is_empty()
+    }
+
+    // If the deposits coming through the partner are refused.
+    pub fn restricts(&self, partner: PartnerID) -> bool {
+        self.restricted_partners.contains(&partner)
+    }
+
+    pub fn counts_of(&self, subject: Subject) -> Counts {
+        self.counts.get(&subject).copied().unwrap_or_default()
+    }
+
+    // Counts the applied transaction for the client of the account and for the partner it's attributed to,
+    // restricting the account or the partner when a dispute or chargeback takes them over a limit.
+    pub fn record(&mut self, tx: &Transaction, partner: Option<PartnerID>, account: &mut Account) {
+        let ratio = match tx.operation {
+            Operation::Deposit(_) | Operation::Withdrawal(_) | Operation::Transfer(..) => None,
+            Operation::Dispute => Some(Ratio::Dispute),
+            Operation::Chargeback => Some(Ratio::Chargeback),
+            _ => return,
+        };
+        let mut subjects = vec![Subject::Client(tx.client_id)];
+        subjects.extend(partner.map(Subject::Partner));
+        for subject in subjects {
+            let counts = self.counts.entry(subject).or_default();
+            match ratio {
+                None => counts.transactions += 1,
+                Some(Ratio::Dispute) => counts.disputes += 1,
+                Some(Ratio::Chargeback) => counts.chargebacks += 1,
+            }
+            let counts = *counts;
+            let restricted = match subject {
+                Subject::Client(_) => account
+                    .restrictions
+                    .contains(&Restriction::ExcessiveDisputes),
+                Subject::Partner(partner) => self.restricts(partner),
+            };
+            // Only the ratio that went up can go over its limit, and only once while it's restricted.
+            let Some(ratio) = ratio.filter(|_| !restricted) else {
+                continue;
+            };
+            let Some(limit) = self.limit_over(subject.scope(), ratio, &counts) else {
+                continue;
+            };
+            match subject {
+                Subject::Client(_) => {
+                    account.restrictions.insert(Restriction::ExcessiveDisputes);
+                }
+                Subject::Partner(partner) => {
+                    self.restricted_partners.insert(partner);
+                }
+            }
+            self.triggers.push(RatioTrigger {
+                tx_id: tx.tx_id,
+                subject,
+                ratio,
+                value: counts.of(ratio).unwrap_or_else(Amount::zero),
+                limit,
+                counts,
+            });
+        }
+    }
+
+    // What it counted and the partners it restricted, sorted so the same state always looks the same.
+    pub fn state(&self) -> RatioState {
+        let mut counts: Vec<(Subject, Counts)> = self
+            .counts
+            .iter()
+            .map(|(subject, counts)| (*subject, *counts))
+            .collect();
+        counts.sort_by_key(|(subject, _)| *subject);
+        RatioState {
+            counts,
+            restricted_partners: self.restricted_partners.iter().copied().collect(),
+        }
+    }
+
+    // Goes on from what another monitor counted and the partners it restricted.
+    pub fn restore(&mut self, state: RatioState) {
+        self.counts.extend(state.counts);
+        self.restricted_partners.extend(state.restricted_partners);
+    }
+
+    // The limit of the ratio the counts are over, if any.
+    fn limit_over(&self, scope: Scope, ratio: Ratio, counts: &Counts) -> Option<Amount> {
+        let value = counts.of(ratio)?;
+        self.limits
+            .iter()
+            .filter(|limit| limit.scope == scope && limit.ratio == ratio)
+            .filter(|limit| counts.transactions >= limit.min_transactions)
+            .find(|limit| value > limit.limit)
+            .map(|limit| limit.limit)
+    }
+}
@@ -0,0 +1,263 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use csv::{DeserializeErrorKind, ErrorKind, StringRecord, Terminator, Writer, WriterBuilder};
+use serde::Serialize;
+
+use crate::models::transaction::TransactionProcessingError;
+
+// The machine-readable reason for which an input record was not applied to the accounts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum RejectionReason {
+    NegativeAmount,
+    UnableToParseAmount,
+    MissingAmount,
+    MissingCurrency,
+    MissingDestination,
+    // The record could not be read as a transaction at all (unknown type, missing fields, invalid ids, etc).
+    MalformedRecord,
+    TargetAccountLocked,
+    NotFound,
+    InsufficientAvailableFunds,
+    // Over what the available funds and the credit limit of the account allow.
+    CreditLimitExceeded,
+    InconsistentOperation,
+    RateNotFound,
+    // More than what can be disputed, or than what is in dispute.
+    ExceedsDisputedAmount,
+    // Too late to dispute the transaction.
+    DisputeWindowClosed,
+    // The policy for disputes on withdrawals is to reject them.
+    WithdrawalDisputeRefused,
+    // A rule of the engine rejects it (the error processing it tells which one).
+    RuleViolated,
+    // The deposit came through a partner whose dispute or chargeback ratio went over a limit.
+    PartnerRestricted,
+    // It's for a client in the blocklist, goes to one, or came through a partner in it.
+    Blocked,
+}
+
+// Not every processing error is about the record being processed, hence not every one is a reason to reject it.
+impl TryFrom<&TransactionProcessingError> for RejectionReason {
+    type Error = ();
+
+    fn try_from(error: &TransactionProcessingError) -> Result<Self, Self::Error> {
+        match error {
+            TransactionProcessingError::InsufficientAvailableFunds(_) => {
+                Ok(RejectionReason::InsufficientAvailableFunds)
+            }
+            TransactionProcessingError::CreditLimitExceeded(_) => {
+                Ok(RejectionReason::CreditLimitExceeded)
+            }
+            TransactionProcessingError::TargetAccountLocked(_) => {
+                Ok(RejectionReason::TargetAccountLocked)
+            }
+            TransactionProcessingError::NotFound(_) => Ok(RejectionReason::NotFound),
+            TransactionProcessingError::InconsistentOperation => {
+                Ok(RejectionReason::InconsistentOperation)
+            }
+            TransactionProcessingError::RateNotFound(_) => Ok(RejectionReason::RateNotFound),
+            TransactionProcessingError::ExceedsDisputedAmount(_) => {
+                Ok(RejectionReason::ExceedsDisputedAmount)
+            }
+            TransactionProcessingError::DisputeWindowClosed(_) => {
+                Ok(RejectionReason::DisputeWindowClosed)
+            }
+            TransactionProcessingError::WithdrawalDisputeRefused(_) => {
+                Ok(RejectionReason::WithdrawalDisputeRefused)
+            }
+            TransactionProcessingError::RuleViolated(_) => Ok(RejectionReason::RuleViolated),
+            TransactionProcessingError::PartnerRestricted(_) => {
+                Ok(RejectionReason::PartnerRestricted)
+            }
+            TransactionProcessingError::Blocked(_) => Ok(RejectionReason::Blocked),
+            TransactionProcessingError::StorageUnavailable(_) => Err(()),
+        }
+    }
+}
+
+impl From<&csv::Error> for RejectionReason {
+    fn from(error: &csv::Error) -> Self {
+        if let ErrorKind::Deserialize { err, .. } = error.kind() {
+            if let DeserializeErrorKind::Message(message) = err.kind() {
+                return RejectionReason::from_deserializing_message(message);
+            }
+        }
+        RejectionReason::MalformedRecord
+    }
+}
+
+// Same as for csv, for transactions submitted as JSON.
+impl From<&serde_json::Error> for RejectionReason {
+    fn from(error: &serde_json::Error) -> Self {
+        RejectionReason::from_deserializing_message(&error.to_string())
+    }
+}
+
+impl RejectionReason {
+    // Errors raised by our own deserializers reach here wrapped by SerDe and csv as a message
+    // that starts with the code of the `TransactionDeserializingError` variant (see `TransactionDeserializingError::code`).
+    fn from_deserializing_message(message: &str) -> Self {
+        match message.split(':').next() {
+            Some("NegativeAmount") => RejectionReason::NegativeAmount,
+            Some("UnableToParseAmount") => RejectionReason::UnableToParseAmount,
+            Some("MissingAmount") => RejectionReason::MissingAmount,
+            Some("MissingCurrency") => RejectionReason::MissingCurrency,
+            Some("MissingDestination") => RejectionReason::MissingDestination,
+            _ => RejectionReason::MalformedRecord,
+        }
+    }
+}
+
+// An input record that was dropped, with enough information to tell the partner which of their rows was ignored and why.
+#[derive(Debug, Serialize)]
+pub struct Rejection {
+    pub line: u64,
+    pub byte_offset: u64,
+    pub record: String,
+    pub reason: RejectionReason,
+    // The name of the rule rejecting it, when it's `RuleViolated`.
+    pub rule: Option<String>,
+}
+
+impl Rejection {
+    pub fn new(record: &StringRecord, reason: RejectionReason) -> Self {
+        let (line, byte_offset) = match record.position() {
+            Some(position) => (position.line(), position.byte()),
+            None => (0, 0),
+        };
+        Self {
+            line,
+            byte_offset,
+            record: row_of(record),
+            reason,
+            rule: None,
+        }
+    }
+
+    // Tells which rule rejected it, if the error processing it was a rule being broken.
+    pub fn with_rule_of(mut self, error: &TransactionProcessingError) -> Self {
+        if let TransactionProcessingError::RuleViolated((_, rule)) = error {
+            self.rule = Some(rule.clone());
+        }
+        self
+    }
+
+    // For the cases where the reader failed before having a record to show (for example, invalid UTF-8).
+    pub fn unreadable(error: &csv::Error) -> Self {
+        let (line, byte_offset) = match error.position() {
+            Some(position) => (position.line(), position.byte()),
+            None => (0, 0),
+        };
+        Self {
+            line,
+            byte_offset,
+            record: String::new(),
+            reason: RejectionReason::MalformedRecord,
+            rule: None,
+        }
+    }
+}
+
+// The record as a CSV row, with the fields quoted as they need to be so the ones with commas or quotes stay as they were.
+fn row_of(record: &StringRecord) -> String {
+    let mut writer = WriterBuilder::new()
+        .terminator(Terminator::Any(b'\n'))
+        .from_writer(vec![]);
+    writer
+        .write_record(record)
+        .expect("Writing a record in memory can't fail");
+    let mut row = writer
+        .into_inner()
+        .expect("Writing a record in memory can't fail");
+    row.pop();
+    String::from_utf8(row).expect("The fields of a record are valid UTF-8")
+}
+
+// Where the engine reports every input record it had to ignore.
+pub trait RejectionSink {
+    fn reject(&mut self, rejection: Rejection) -> io::Result<()>;
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// The sink to use when nobody is interested in the rejected records.
+pub struct IgnoredRejections;
+
+impl RejectionSink for IgnoredRejections {
+    fn reject(&mut self, _rejection: Rejection) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Keeps the rejected records in memory.
+impl RejectionSink for Vec<Rejection> {
+    fn reject(&mut self, rejection: Rejection) -> io::Result<()> {
+        self.push(rejection);
+        Ok(())
+    }
+}
+
+// Writes the rejected records as CSV with a headers row.
+pub struct CsvRejectionWriter<W: Write> {
+    writer: Writer<W>,
+}
+
+impl<W: Write> CsvRejectionWriter<W> {
+    pub fn new(output: W) -> Self {
+        Self {
+            writer: Writer::from_writer(output),
+        }
+    }
+}
+
+impl<W: Write> RejectionSink for CsvRejectionWriter<W> {
+    fn reject(&mut self, rejection: Rejection) -> io::Result<()> {
+        self.writer.serialize(rejection).map_err(io::Error::from)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+// Writes the rejected records as one JSON object per line.
+pub struct JsonlRejectionWriter<W: Write> {
+    output: W,
+}
+
+impl<W: Write> JsonlRejectionWriter<W> {
+    pub fn new(output: W) -> Self {
+        Self { output }
+    }
+}
+
+impl<W: Write> RejectionSink for JsonlRejectionWriter<W> {
+    fn reject(&mut self, rejection: Rejection) -> io::Result<()> {
+        serde_json::to_writer(&mut self.output, &rejection)?;
+        self.output.write_all(b"\n")
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+// Creates the file at the given path and a sink writing into it.
+// The format is JSONL when the path ends in `.jsonl` and CSV otherwise.
+pub fn rejection_sink_to(path: &str) -> io::Result<Box<dyn RejectionSink + Send>> {
+    let output = BufWriter::new(File::create(path)?);
+    let is_jsonl = Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension == "jsonl");
+    if is_jsonl {
+        Ok(Box::new(JsonlRejectionWriter::new(output)))
+    } else {
+        Ok(Box::new(CsvRejectionWriter::new(output)))
+    }
+}
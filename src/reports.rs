@@ -0,0 +1,194 @@
+use std::{
+    collections::BTreeSet,
+    io::{self, Write},
+};
+
+use csv::Writer;
+use serde::Serialize;
+
+use crate::{
+    models::{
+        disputes::{Dispute, DisputeState},
+        money::{Money, Rounding},
+        restrictions::{AccountStatus, Restriction},
+        shared::{ClientID, TransactionID},
+    },
+    ratios::{Ratio, RatioTrigger, Scope, Subject},
+    rules::FlaggedTransaction,
+};
+
+#[derive(Serialize)]
+struct DisputeReportRow {
+    tx: TransactionID,
+    client: ClientID,
+    state: DisputeState,
+    reopenings: u32,
+    history: String,
+}
+
+impl From<&Dispute> for DisputeReportRow {
+    fn from(dispute: &Dispute) -> Self {
+        Self {
+            tx: dispute.tx_id,
+            client: dispute.client_id,
+            state: dispute.state,
+            reopenings: dispute.reopenings,
+            history: dispute
+                .history
+                .iter()
+                .map(|state| format!("{:?}", state))
+                .collect::<Vec<String>>()
+                .join(" > "),
+        }
+    }
+}
+
+// Writes every dispute as CSV with a headers row, for example:
+// tx,client,state,reopenings,history
+// 7,2,ChargedBack,1,Open > Resolved > Open > ChargedBack
+pub fn write_dispute_report<W: Write>(disputes: &[Dispute], output: W) -> io::Result<()> {
+    let mut writer = Writer::from_writer(output);
+    for dispute in disputes {
+        writer
+            .serialize(DisputeReportRow::from(dispute))
+            .map_err(io::Error::from)?;
+    }
+    writer.flush()
+}
+
+// A dispute still open on an account whose restrictions block disputes, with what it keeps held there.
+// It's only settleable if the engine resolves and charges back open disputes of such accounts,
+// or if what locked the account was charging back part of it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrandedDispute {
+    pub tx_id: TransactionID,
+    pub client_id: ClientID,
+    pub held: Money,
+    pub status: AccountStatus,
+    pub restrictions: BTreeSet<Restriction>,
+    pub settleable: bool,
+}
+
+#[derive(Serialize)]
+struct StrandedReportRow {
+    tx: TransactionID,
+    client: ClientID,
+    held: String,
+    currency: String,
+    status: String,
+    restrictions: String,
+    settleable: bool,
+}
+
+impl StrandedReportRow {
+    fn from(stranded: &StrandedDispute, rounding: Rounding) -> Self {
+        Self {
+            tx: stranded.tx_id,
+            client: stranded.client_id,
+            held: rounding.render(&stranded.held.amount),
+            currency: stranded.held.currency.clone().unwrap_or_default(),
+            status: stranded.status.to_string(),
+            restrictions: stranded
+                .restrictions
+                .iter()
+                .map(Restriction::to_string)
+                .collect::<Vec<String>>()
+                .join("|"),
+            settleable: stranded.settleable,
+        }
+    }
+}
+
+// Writes every stranded dispute as CSV with a headers row, for example:
+// tx,client,held,currency,status,restrictions,settleable
+// 4,1,5.0000,,locked,chargeback,false
+pub fn write_stranded_report<W: Write>(
+    stranded: &[StrandedDispute],
+    rounding: Rounding,
+    output: W,
+) -> io::Result<()> {
+    let mut writer = Writer::from_writer(output);
+    for dispute in stranded {
+        writer
+            .serialize(StrandedReportRow::from(dispute, rounding))
+            .map_err(io::Error::from)?;
+    }
+    writer.flush()
+}
+
+#[derive(Serialize)]
+struct FlaggedReportRow<'a> {
+    tx: TransactionID,
+    client: ClientID,
+    rule: &'a str,
+}
+
+// Writes every flagged transaction as CSV with a headers row, sorted by transaction ID, for example:
+// tx,client,rule
+// 12,2,large-withdrawal
+pub fn write_flagged_report<W: Write>(flagged: &[FlaggedTransaction], output: W) -> io::Result<()> {
+    let mut sorted: Vec<&FlaggedTransaction> = flagged.iter().collect();
+    sorted.sort_by_key(|flagged| flagged.tx_id);
+    let mut writer = Writer::from_writer(output);
+    for flagged in sorted {
+        writer
+            .serialize(FlaggedReportRow {
+                tx: flagged.tx_id,
+                client: flagged.client_id,
+                rule: &flagged.rule,
+            })
+            .map_err(io::Error::from)?;
+    }
+    writer.flush()
+}
+
+#[derive(Serialize)]
+struct RatioReportRow {
+    tx: TransactionID,
+    scope: Scope,
+    id: u16,
+    ratio: Ratio,
+    value: String,
+    limit: String,
+    transactions: u64,
+    disputes: u64,
+    chargebacks: u64,
+}
+
+impl RatioReportRow {
+    fn from(trigger: &RatioTrigger, rounding: Rounding) -> Self {
+        let id = match trigger.subject {
+            Subject::Client(client_id) => client_id,
+            Subject::Partner(partner_id) => partner_id,
+        };
+        Self {
+            tx: trigger.tx_id,
+            scope: trigger.subject.scope(),
+            id,
+            ratio: trigger.ratio,
+            value: rounding.render(&trigger.value),
+            limit: rounding.render(&trigger.limit),
+            transactions: trigger.counts.transactions,
+            disputes: trigger.counts.disputes,
+            chargebacks: trigger.counts.chargebacks,
+        }
+    }
+}
+
+// Writes every client or partner restriction the ratios triggered as CSV with a headers row, in the order they
+// happened, with the counts the ratio came from at the transaction that took it over its limit, for example:
+// tx,scope,id,ratio,value,limit,transactions,disputes,chargebacks
+// 9,partner,3,dispute,0.5000,0.2500,4,2,0
+pub fn write_ratio_report<W: Write>(
+    triggers: &[RatioTrigger],
+    rounding: Rounding,
+    output: W,
+) -> io::Result<()> {
+    let mut writer = Writer::from_writer(output);
+    for trigger in triggers {
+        writer
+            .serialize(RatioReportRow::from(trigger, rounding))
+            .map_err(io::Error::from)?;
+    }
+    writer.flush()
+}
@@ -0,0 +1,372 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    error, fmt,
+    fs::File,
+    io::Read,
+};
+
+use fraction::Zero;
+use serde::Deserialize;
+
+use crate::{
+    input_ingestion::get_csv_reader_from,
+    models::{
+        shared::{Amount, ClientID, Currency, Timestamp, TransactionID},
+        transaction::{
+            currency_code, Operation, Transaction, TransactionProcessingError, TransactionType,
+        },
+    },
+};
+
+#[derive(Debug)]
+pub enum RulesError {
+    Unreadable(String),
+    // The rule at this line of the rules file has no name, its limit is not a non negative amount,
+    // it's a max-amount rule for a type without amounts, it's a max-count or max-sum rule without a type
+    // or without exactly one window, or it has a currency without limiting amounts.
+    InvalidRule(u64),
+}
+
+impl fmt::Display for RulesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RulesError::Unreadable(reason) => write!(f, "Unable to read the rules: {}", reason),
+            RulesError::InvalidRule(line) => write!(f, "The rule at line {} is not valid", line),
+        }
+    }
+}
+
+impl error::Error for RulesError {}
+
+// What a rule limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RuleKind {
+    // The amount of a single transaction of the type.
+    MaxAmount,
+    // How many transactions of the type the client makes within the window.
+    MaxCount,
+    // The sum of the amounts of the transactions of the type the client makes within the window.
+    MaxSum,
+    // The disputes of the client per deposit, withdrawal or transfer it made.
+    MaxDisputeRate,
+}
+
+// What happens to a transaction breaking the rule.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleAction {
+    // It's not processed.
+    #[default]
+    Reject,
+    // It's processed, and reported as flagged by the rule.
+    Flag,
+}
+
+// Which transactions of the client a max-count or max-sum rule looks at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    // The last ones, the transaction being checked included.
+    LastTransactions(usize),
+    // The ones made up to these seconds before the transaction being checked.
+    // Transactions without a time are never in it.
+    Seconds(u64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub name: String,
+    pub kind: RuleKind,
+    pub tx_type: Option<TransactionType>,
+    pub limit: Amount,
+    // The currency of the amounts a max-amount or max-sum rule limits, `None` being the default currency.
+    pub currency: Option<Currency>,
+    pub window: Option<Window>,
+    pub action: RuleAction,
+}
+
+// A row of the rules file, for example:
+// name, rule, type, limit, last_transactions, window_seconds, action, currency
+// large-withdrawal, max-amount, withdrawal, 5000, , , flag,
+// large-usd-withdrawal, max-amount, withdrawal, 1000, , , flag, USD
+// frequent-withdrawals, max-count, withdrawal, 3, 10, , reject,
+// daily-withdrawals, max-sum, withdrawal, 10000, , 86400, reject,
+// disputes, max-dispute-rate, , 0.1, , , reject,
+#[derive(Debug, Deserialize)]
+struct RuleRecord {
+    #[serde(default)]
+    name: String,
+    rule: RuleKind,
+    #[serde(default, rename = "type")]
+    tx_type: Option<TransactionType>,
+    #[serde(default)]
+    limit: String,
+    #[serde(default)]
+    last_transactions: Option<usize>,
+    #[serde(default)]
+    window_seconds: Option<u64>,
+    // Empty to reject.
+    #[serde(default)]
+    action: Option<RuleAction>,
+    // An optional column, absent or empty for the default currency.
+    #[serde(default)]
+    currency: Option<String>,
+}
+
+// A transaction that was processed although it broke a rule flagging it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlaggedTransaction {
+    pub tx_id: TransactionID,
+    pub client_id: ClientID,
+    pub rule: String,
+}
+
+// A transaction the rules saw applied, as the rules looking at the previous ones of its client remember it.
+#[derive(Debug, Clone)]
+struct Seen {
+    tx_type: TransactionType,
+    amount: Amount,
+    currency: Option<Currency>,
+    at: Option<Timestamp>,
+}
+
+// What the rules know about the previous transactions of a client.
+#[derive(Debug, Clone, Default)]
+struct ClientActivity {
+    // Only as many as the rules look at.
+    recent: VecDeque<Seen>,
+    // The deposits, withdrawals and transfers it made, which could be disputed.
+    disputable: u64,
+    disputes: u64,
+}
+
+impl ClientActivity {
+    // The previous transactions of the given type within the window, for a transaction made at the given time.
+    fn within(
+        &self,
+        window: Window,
+        tx_type: Option<TransactionType>,
+        now: Option<Timestamp>,
+    ) -> Vec<&Seen> {
+        let seen: Vec<&Seen> = match window {
+            Window::LastTransactions(count) => self
+                .recent
+                .iter()
+                .rev()
+                .take(count.saturating_sub(1))
+                .collect(),
+            Window::Seconds(seconds) => self
+                .recent
+                .iter()
+                .filter(|seen| is_within_seconds(seen.at, now, seconds))
+                .collect(),
+        };
+        seen.into_iter()
+            .filter(|seen| Some(seen.tx_type) == tx_type)
+            .collect()
+    }
+}
+
+fn is_within_seconds(at: Option<Timestamp>, now: Option<Timestamp>, seconds: u64) -> bool {
+    match (at, now) {
+        (Some(at), Some(now)) => at + seconds >= now,
+        _ => false,
+    }
+}
+
+impl Rule {
+    // If the transaction, made at the given time, breaks the rule given what its client did before.
+    fn is_broken_by(
+        &self,
+        tx: &Transaction,
+        now: Option<Timestamp>,
+        activity: Option<&ClientActivity>,
+    ) -> bool {
+        if self.kind == RuleKind::MaxDisputeRate {
+            // Only disputes raise the rate, and there is no rate before the client made anything.
+            return matches!(tx.operation, Operation::Dispute)
+                && activity.is_some_and(|activity| {
+                    activity.disputable > 0
+                        && Amount::from(activity.disputes + 1) / Amount::from(activity.disputable)
+                            > self.limit
+                });
+        }
+        if Some(tx.tx_type()) != self.tx_type {
+            return false;
+        }
+        // Amounts are only compared and added up in the currency of the rule.
+        let limits_amounts = matches!(self.kind, RuleKind::MaxAmount | RuleKind::MaxSum);
+        if limits_amounts && tx.currency != self.currency {
+            return false;
+        }
+        let amount = tx.amount().unwrap_or_else(Amount::zero);
+        let previous = match (self.window, activity) {
+            (Some(window), Some(activity)) => activity.within(window, self.tx_type, now),
+            _ => vec![],
+        };
+        match self.kind {
+            RuleKind::MaxAmount => amount > self.limit,
+            RuleKind::MaxCount => Amount::from(previous.len() + 1) > self.limit,
+            RuleKind::MaxSum => {
+                previous
+                    .iter()
+                    .filter(|seen| seen.currency == self.currency)
+                    .fold(amount, |sum, seen| sum + seen.amount)
+                    > self.limit
+            }
+            RuleKind::MaxDisputeRate => unreachable!("Dispute rates are checked above"),
+        }
+    }
+}
+
+// The rules transactions are checked against before being processed, with what they need to know about
+// the previous transactions of every client. That is only known since the engine started, it's not persisted.
+#[derive(Debug, Clone, Default)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+    activity: HashMap<ClientID, ClientActivity>,
+}
+
+impl RuleSet {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Self {
+            rules,
+            activity: HashMap::new(),
+        }
+    }
+
+    pub fn open(path: &str) -> Result<Self, RulesError> {
+        let file = File::open(path).map_err(|e| RulesError::Unreadable(e.to_string()))?;
+        RuleSet::read_from(file)
+    }
+
+    pub fn read_from<R: Read>(input: R) -> Result<Self, RulesError> {
+        let mut rules = vec![];
+        let mut reader = get_csv_reader_from(input);
+        let headers = reader
+            .headers()
+            .map_err(|e| RulesError::Unreadable(e.to_string()))?
+            .clone();
+        for result in reader.records() {
+            let row = result.map_err(|e| RulesError::Unreadable(e.to_string()))?;
+            let line = row.position().map_or(0, |position| position.line());
+            let record: RuleRecord = row
+                .deserialize(Some(&headers))
+                .map_err(|e| RulesError::Unreadable(e.to_string()))?;
+            let limit = record
+                .limit
+                .parse::<Amount>()
+                .ok()
+                .filter(|limit| *limit >= Amount::zero())
+                .ok_or(RulesError::InvalidRule(line))?;
+            let window = match (record.last_transactions, record.window_seconds) {
+                (Some(count), None) if count > 0 => Some(Window::LastTransactions(count)),
+                (None, Some(seconds)) => Some(Window::Seconds(seconds)),
+                (None, None) => None,
+                _ => return Err(RulesError::InvalidRule(line)),
+            };
+            let currency = record.currency.as_deref().and_then(currency_code);
+            let valid = match record.rule {
+                RuleKind::MaxAmount => record.tx_type.is_some_and(has_amount),
+                RuleKind::MaxCount => {
+                    record.tx_type.is_some() && window.is_some() && currency.is_none()
+                }
+                RuleKind::MaxSum => record.tx_type.is_some_and(has_amount) && window.is_some(),
+                RuleKind::MaxDisputeRate => currency.is_none(),
+            };
+            if record.name.is_empty() || !valid {
+                return Err(RulesError::InvalidRule(line));
+            }
+            rules.push(Rule {
+                name: record.name,
+                kind: record.rule,
+                tx_type: record.tx_type,
+                limit,
+                currency,
+                window,
+                action: record.action.unwrap_or_default(),
+            });
+        }
+        Ok(RuleSet::new(rules))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    // Checks the transaction, made at the given time, against every rule. It fails with the first rule rejecting it,
+    // otherwise it tells the names of the rules flagging it.
+    pub fn check(
+        &self,
+        tx: &Transaction,
+        now: Option<Timestamp>,
+    ) -> Result<Vec<String>, TransactionProcessingError> {
+        let activity = self.activity.get(&tx.client_id);
+        let mut flagged = vec![];
+        for rule in self.rules.iter() {
+            if !rule.is_broken_by(tx, now, activity) {
+                continue;
+            }
+            match rule.action {
+                RuleAction::Reject => {
+                    return Err(TransactionProcessingError::RuleViolated((
+                        tx.tx_id,
+                        rule.name.clone(),
+                    )));
+                }
+                RuleAction::Flag => flagged.push(rule.name.clone()),
+            }
+        }
+        Ok(flagged)
+    }
+
+    // Remembers the applied transaction, made at the given time, for the rules looking at the previous ones.
+    pub fn record(&mut self, tx: &Transaction, now: Option<Timestamp>) {
+        if self.rules.is_empty() {
+            return;
+        }
+        // Only as many transactions as the longest window in transactions, or as recent as the longest one in time.
+        let (mut kept_transactions, mut kept_seconds) = (0, None);
+        for rule in self.rules.iter() {
+            match rule.window {
+                Some(Window::LastTransactions(count)) => {
+                    kept_transactions = kept_transactions.max(count)
+                }
+                Some(Window::Seconds(seconds)) => kept_seconds = kept_seconds.max(Some(seconds)),
+                None => {}
+            }
+        }
+        let activity = self.activity.entry(tx.client_id).or_default();
+        match tx.operation {
+            Operation::Deposit(_) | Operation::Withdrawal(_) | Operation::Transfer(..) => {
+                activity.disputable += 1
+            }
+            Operation::Dispute => activity.disputes += 1,
+            _ => {}
+        }
+        activity.recent.push_back(Seen {
+            tx_type: tx.tx_type(),
+            amount: tx.amount().unwrap_or_else(Amount::zero),
+            currency: tx.currency.clone(),
+            at: now,
+        });
+        while activity.recent.len() > kept_transactions
+            && activity.recent.front().is_some_and(|seen| {
+                !kept_seconds.is_some_and(|seconds| is_within_seconds(seen.at, now, seconds))
+            })
+        {
+            activity.recent.pop_front();
+        }
+    }
+}
+
+fn has_amount(tx_type: TransactionType) -> bool {
+    matches!(
+        tx_type,
+        TransactionType::Deposit
+            | TransactionType::Withdrawal
+            | TransactionType::Convert
+            | TransactionType::Transfer
+            | TransactionType::Authorize
+    )
+}
@@ -0,0 +1,149 @@
+use std::{
+    error::Error,
+    io::{BufWriter, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+};
+
+use csv::StringRecord;
+
+use crate::{
+    input_ingestion::csv_reader_builder,
+    models::{
+        account::{render_accounts, Accounts},
+        disputes::Disputes,
+        transaction::Transactions,
+    },
+    payments_engine::PaymentsEngine,
+    rejections::{Rejection, RejectionSink},
+    storage::{AccountStore, DisputeStore, TransactionStore},
+};
+
+// The line a partner sends to get the current state of every account.
+pub const ACCOUNTS_QUERY: &str = "accounts";
+
+// Serves partners streaming their input over TCP into one shared engine.
+//
+// Every connection sends rows in the same `type, client, tx, amount[, currency[, to_currency[, timestamp[, to_client[, partner]]]]]` format as the input files
+// (the headers row is optional) and can send an `accounts` line at any time to get back the state of
+// every account rendered as in the output, followed by an empty line. Rows sent on a connection are
+// processed in the order they were sent, so a query always reflects every row sent before it on the
+// same connection. Rows from different connections are interleaved as they arrive.
+pub struct Server<A = Accounts, T = Transactions, D = Disputes> {
+    engine: Arc<Mutex<PaymentsEngine<A, T, D>>>,
+    rejections: Arc<Mutex<Box<dyn RejectionSink + Send>>>,
+}
+
+impl<A, T, D> Clone for Server<A, T, D> {
+    fn clone(&self) -> Self {
+        Self {
+            engine: Arc::clone(&self.engine),
+            rejections: Arc::clone(&self.rejections),
+        }
+    }
+}
+
+impl<A, T, D> Server<A, T, D>
+where
+    A: AccountStore + Send + 'static,
+    T: TransactionStore + Send + 'static,
+    D: DisputeStore + Send + 'static,
+{
+    pub fn new(engine: PaymentsEngine<A, T, D>, rejections: Box<dyn RejectionSink + Send>) -> Self {
+        Self {
+            engine: Arc::new(Mutex::new(engine)),
+            rejections: Arc::new(Mutex::new(rejections)),
+        }
+    }
+
+    // The engine it serves, for the admin interface to share it.
+    pub fn engine_handle(&self) -> Arc<Mutex<PaymentsEngine<A, T, D>>> {
+        Arc::clone(&self.engine)
+    }
+
+    // Accepts connections forever, handling each one on its own thread.
+    pub fn serve(&self, listener: TcpListener) {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let server = self.clone();
+                    thread::spawn(move || {
+                        let peer = stream.peer_addr();
+                        if let Err(e) = server.handle(stream) {
+                            eprintln!("Closed connection with {:?}: {}", peer, e);
+                        }
+                    });
+                }
+                // A connection that failed before being accepted is the partner's business.
+                Err(e) => eprintln!("Unable to accept a connection: {}", e),
+            }
+        }
+    }
+
+    fn handle(&self, stream: TcpStream) -> Result<(), Box<dyn Error>> {
+        let mut responses = BufWriter::new(stream.try_clone()?);
+        let mut reader = csv_reader_builder().has_headers(false).from_reader(stream);
+        let headers = input_headers();
+        for result in reader.records() {
+            match result {
+                Ok(record) if record.get(0) == Some("type") => {}
+                Ok(record) if record.len() == 1 && &record[0] == ACCOUNTS_QUERY => {
+                    self.write_accounts(&mut responses)?;
+                }
+                Ok(record) => {
+                    let mut engine = self.engine();
+                    engine.process_record(&record, Some(&headers), self.rejections().as_mut())?;
+                }
+                Err(e) if e.is_io_error() => return Err(Box::new(e)),
+                Err(e) => self.rejections().reject(Rejection::unreadable(&e))?,
+            }
+        }
+        self.rejections().flush()?;
+        if let Some(journal) = self.engine().journal.as_mut() {
+            journal.flush()?;
+        }
+        Ok(())
+    }
+
+    fn write_accounts(&self, output: &mut impl Write) -> Result<(), Box<dyn Error>> {
+        let (accounts, rounding) = {
+            let engine = self.engine();
+            (engine.accounts.all_accounts()?, engine.rounding)
+        };
+        for line in render_accounts(&accounts, rounding) {
+            writeln!(output, "{}", line)?;
+        }
+        writeln!(output)?;
+        output.flush()?;
+        Ok(())
+    }
+
+    // The engine is always locked before the rejections, never the other way around.
+    fn engine(&self) -> MutexGuard<'_, PaymentsEngine<A, T, D>> {
+        self.engine
+            .lock()
+            .expect("A connection panicked while using the engine")
+    }
+
+    fn rejections(&self) -> MutexGuard<'_, Box<dyn RejectionSink + Send>> {
+        self.rejections
+            .lock()
+            .expect("A connection panicked while reporting rejections")
+    }
+}
+
+// Rows are streamed without needing a headers row, so they are deserialized with the ones of the input files.
+fn input_headers() -> StringRecord {
+    StringRecord::from(vec![
+        "type",
+        "client",
+        "tx",
+        "amount",
+        "currency",
+        "to_currency",
+        "timestamp",
+        "to_client",
+        "partner",
+    ])
+}
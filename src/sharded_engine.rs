@@ -0,0 +1,460 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error, fmt,
+    io::Read,
+    sync::mpsc::{channel, Sender},
+    thread::{self, JoinHandle},
+};
+
+use csv::{Reader, StringRecord};
+
+use crate::{
+    blocklist::Blocklist,
+    limits::CreditLimits,
+    models::{
+        account::Account,
+        disputes::{Dispute, DisputeRules},
+        money::Rounding,
+        shared::{Amount, ClientID, TransactionID},
+        transaction::{Operation, Transaction, TransactionProcessingError},
+    },
+    payments_engine::{deserialize_transaction, InputProcessingError, PaymentsEngine},
+    rates::RateTable,
+    rejections::{Rejection, RejectionReason, RejectionSink},
+    rules::RuleSet,
+};
+
+#[derive(Debug)]
+pub enum ShardingError {
+    // The engine to split has something configured the shards can't do on their own, named as its option.
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for ShardingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShardingError::Unsupported(what) => {
+                write!(f, "{} can't be used with sharded processing", what)
+            }
+        }
+    }
+}
+
+impl error::Error for ShardingError {}
+
+// What the shard owning a transaction knows about it: which client recorded it, if any, and if it has a pending dispute.
+// A dispute that is not pending anymore can only be opened again by the client of the transaction, so it doesn't matter
+// to the other shards.
+type Lookup = (Option<ClientID>, bool);
+
+enum ShardMessage {
+    Process(StringRecord, Transaction),
+    // The transaction refers to a transaction ID that was recorded in another shard.
+    ProcessForeign(StringRecord, Transaction, ClientID, bool),
+    Lookup(TransactionID, Sender<Lookup>),
+    // Asks for the client a transfer recorded in the shard went to, if the transaction is one.
+    Recipient(TransactionID, Sender<Option<ClientID>>),
+    // Hands over an account, which leaves the shard until it's kept back.
+    Lend(ClientID, Sender<Option<Account>>),
+    Keep(Account),
+}
+
+struct Shard {
+    sender: Sender<ShardMessage>,
+    worker: JoinHandle<Result<(PaymentsEngine, Vec<Rejection>), TransactionProcessingError>>,
+}
+
+impl Shard {
+    fn spawn(mut pe: PaymentsEngine) -> Self {
+        let (sender, receiver) = channel::<ShardMessage>();
+        let worker = thread::spawn(move || {
+            let mut rejections = vec![];
+            for message in receiver {
+                let outcome = match message {
+                    ShardMessage::Process(record, tx) => {
+                        process_in_shard(&mut pe, &record, tx, &mut rejections)
+                    }
+                    ShardMessage::ProcessForeign(record, tx, recorder, disputed) => {
+                        process_foreign_in_shard(
+                            &mut pe,
+                            &record,
+                            tx,
+                            recorder,
+                            disputed,
+                            &mut rejections,
+                        )
+                    }
+                    ShardMessage::Lookup(tx_id, reply) => {
+                        let recorder = pe.transactions.get(&tx_id).map(|tx| tx.client_id);
+                        let disputed = pe.disputes.get(&tx_id).is_some_and(Dispute::is_open);
+                        // The router is waiting for it, there is nothing to do if it's gone.
+                        let _ = reply.send((recorder, disputed));
+                        Ok(())
+                    }
+                    ShardMessage::Recipient(tx_id, reply) => {
+                        let recipient = match pe.transactions.get(&tx_id) {
+                            Some(Transaction {
+                                operation: Operation::Transfer(_, to),
+                                ..
+                            }) => Some(*to),
+                            _ => None,
+                        };
+                        let _ = reply.send(recipient);
+                        Ok(())
+                    }
+                    ShardMessage::Lend(client_id, reply) => {
+                        let _ = reply.send(pe.accounts.remove(&client_id));
+                        Ok(())
+                    }
+                    ShardMessage::Keep(account) => {
+                        pe.accounts.insert(account.client_id(), account);
+                        Ok(())
+                    }
+                };
+                outcome?;
+            }
+            Ok((pe, rejections))
+        });
+        Self { sender, worker }
+    }
+}
+
+fn process_in_shard(
+    pe: &mut PaymentsEngine,
+    record: &StringRecord,
+    tx: Transaction,
+    rejections: &mut Vec<Rejection>,
+) -> Result<(), TransactionProcessingError> {
+    if let Err(e) = pe.process(tx) {
+        match RejectionReason::try_from(&e) {
+            Ok(reason) => rejections.push(Rejection::new(record, reason).with_rule_of(&e)),
+            Err(()) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+// Processes the transaction as the single-threaded engine would, seeing the transaction (and dispute)
+// that another shard recorded for the same ID. Since they belong to another client, the account logic
+// never applies anything on them, so they are only there for the duration of this processing.
+fn process_foreign_in_shard(
+    pe: &mut PaymentsEngine,
+    record: &StringRecord,
+    tx: Transaction,
+    recorder: ClientID,
+    disputed: bool,
+    rejections: &mut Vec<Rejection>,
+) -> Result<(), TransactionProcessingError> {
+    let tx_id = tx.tx_id;
+    let foreign = Transaction {
+        client_id: recorder,
+        tx_id,
+        operation: tx.operation.clone(),
+        currency: None,
+        fee: Amount::from(0),
+        disputed_amount: None,
+        timestamp: None,
+        partner: None,
+    };
+    pe.transactions.insert(tx_id, foreign.clone());
+    if disputed {
+        pe.disputes.insert(tx_id, Dispute::from(foreign));
+    }
+    let outcome = process_in_shard(pe, record, tx, rejections);
+    pe.transactions.remove(&tx_id);
+    if disputed {
+        pe.disputes.remove(&tx_id);
+    }
+    outcome
+}
+
+// Processes transactions in parallel, parsing them on the calling thread and routing them by client ID
+// to worker shards, each one owning the accounts, transactions and disputes of its clients.
+//
+// Since transaction IDs are global, the router keeps track of which client claimed each transaction ID
+// and, when a transaction refers to an ID claimed in another shard, it asks that shard about it
+// so the result is exactly the one of the single-threaded engine (including detecting `InconsistentOperation`).
+//
+// A transfer to a client of another shard (or a dispute, resolve or chargeback on it) is processed by the shard of
+// its client with the account of the recipient lent by its shard for the time being, so both legs are applied at once.
+pub struct ShardedPaymentsEngine {
+    shards: Vec<Shard>,
+    claims: HashMap<TransactionID, ClientID>,
+    // The transaction IDs any transfer came with, to only ask shards about the recipients of those.
+    transfers: HashSet<TransactionID>,
+    rejections: Vec<Rejection>,
+    rates: RateTable,
+    credit_limits: CreditLimits,
+    rules: RuleSet,
+    blocklist: Blocklist,
+    dispute_rules: DisputeRules,
+    authorization_expiry: Option<u32>,
+    rounding: Rounding,
+}
+
+impl ShardedPaymentsEngine {
+    pub fn new(workers: usize) -> Self {
+        ShardedPaymentsEngine::split(PaymentsEngine::default(), workers)
+            .expect("The default engine has nothing the shards can't do")
+    }
+
+    // Distributes the state of the given engine among the shards.
+    // The house account collects from every client and the clients of a partner are spread across the shards,
+    // so an engine with fees or ratio limits can't be split, nor one journaling (the shards apply in no global order).
+    // Each shard only knows the time of its own transactions either, so disputes can't expire nor be filed late.
+    pub fn split(pe: PaymentsEngine, workers: usize) -> Result<Self, ShardingError> {
+        if !pe.fees.is_empty() {
+            return Err(ShardingError::Unsupported("--fees"));
+        }
+        if !pe.ratios.is_empty() {
+            return Err(ShardingError::Unsupported("--ratio-limits"));
+        }
+        if pe.journal.is_some() {
+            return Err(ShardingError::Unsupported("--journal"));
+        }
+        if pe.dispute_rules.filing_window.is_some() {
+            return Err(ShardingError::Unsupported("--dispute-window-days"));
+        }
+        if pe.dispute_rules.expiry.is_some() {
+            return Err(ShardingError::Unsupported("--dispute-expiry-days"));
+        }
+        let workers = workers.max(1);
+        let mut engines: Vec<PaymentsEngine> = (0..workers)
+            .map(|_| PaymentsEngine {
+                rates: pe.rates.clone(),
+                credit_limits: pe.credit_limits.clone(),
+                rules: pe.rules.clone(),
+                blocklist: pe.blocklist.clone(),
+                dispute_rules: pe.dispute_rules,
+                authorization_expiry: pe.authorization_expiry,
+                clock: pe.clock,
+                ..Default::default()
+            })
+            .collect();
+        let mut claims = HashMap::new();
+        for (client_id, account) in pe.accounts {
+            engines[shard_index(client_id, workers)]
+                .accounts
+                .insert(client_id, account);
+        }
+        for (tx_id, tx) in pe.transactions {
+            claims.insert(tx_id, tx.client_id);
+            engines[shard_index(tx.client_id, workers)]
+                .transactions
+                .insert(tx_id, tx);
+        }
+        for (tx_id, dispute) in pe.disputes {
+            engines[shard_index(dispute.client_id, workers)]
+                .disputes
+                .insert(tx_id, dispute);
+        }
+        Ok(Self {
+            shards: engines.into_iter().map(Shard::spawn).collect(),
+            claims,
+            transfers: HashSet::new(),
+            rejections: vec![],
+            rates: pe.rates,
+            credit_limits: pe.credit_limits,
+            rules: pe.rules,
+            blocklist: pe.blocklist,
+            dispute_rules: pe.dispute_rules,
+            authorization_expiry: pe.authorization_expiry,
+            rounding: pe.rounding,
+        })
+    }
+
+    pub fn process_transactions_from<R: Read>(
+        &mut self,
+        mut reader: Reader<R>,
+    ) -> Result<(), InputProcessingError> {
+        let headers = if reader.has_headers() {
+            Some(
+                reader
+                    .headers()
+                    .map_err(InputProcessingError::UnreadableInput)?
+                    .clone(),
+            )
+        } else {
+            None
+        };
+        for result in reader.records() {
+            match result {
+                Ok(record) => match deserialize_transaction(&record, headers.as_ref()) {
+                    Ok(tx) => self.route(record, tx),
+                    Err(e) => self
+                        .rejections
+                        .push(Rejection::new(&record, RejectionReason::from(&e))),
+                },
+                Err(e) if e.is_io_error() => {
+                    return Err(InputProcessingError::UnreadableInput(e));
+                }
+                Err(e) => self.rejections.push(Rejection::unreadable(&e)),
+            }
+        }
+        Ok(())
+    }
+
+    fn route(&mut self, record: StringRecord, tx: Transaction) {
+        let workers = self.shards.len();
+        let shard = shard_index(tx.client_id, workers);
+        let recipient = match tx.operation {
+            Operation::Transfer(_, to) => {
+                self.transfers.insert(tx.tx_id);
+                Some(to)
+            }
+            Operation::Dispute | Operation::Resolve | Operation::Chargeback
+                if self.transfers.contains(&tx.tx_id) =>
+            {
+                self.recipient(tx.tx_id, shard)
+            }
+            _ => None,
+        };
+        // The recipient of the shard doesn't need to be lent.
+        let lender = recipient
+            .map(|to| (to, shard_index(to, workers)))
+            .filter(|(_, lender)| *lender != shard);
+        let claimant = self.claims.get(&tx.tx_id).copied();
+        let message = match claimant {
+            Some(claimant) if shard_index(claimant, workers) != shard => {
+                let (recorder, disputed) = self.lookup(tx.tx_id, claimant);
+                match recorder {
+                    Some(recorder) => {
+                        self.claims.insert(tx.tx_id, recorder);
+                        ShardMessage::ProcessForeign(record, tx, recorder, disputed)
+                    }
+                    None => {
+                        if records_transaction(&tx) {
+                            self.claims.insert(tx.tx_id, tx.client_id);
+                        }
+                        ShardMessage::Process(record, tx)
+                    }
+                }
+            }
+            Some(_) => ShardMessage::Process(record, tx),
+            None => {
+                if records_transaction(&tx) {
+                    self.claims.insert(tx.tx_id, tx.client_id);
+                }
+                ShardMessage::Process(record, tx)
+            }
+        };
+        match lender {
+            Some((recipient, lender)) => self.process_lending(shard, message, recipient, lender),
+            // A shard only stops receiving when it failed, which is reported when finishing.
+            None => {
+                let _ = self.shards[shard].sender.send(message);
+            }
+        }
+    }
+
+    // Has the shard process the message with the account of the recipient, taken from the shard lending it
+    // and given back to it afterwards. Nothing else is routed meanwhile, so no other transaction sees it missing.
+    fn process_lending(
+        &self,
+        shard: usize,
+        message: ShardMessage,
+        recipient: ClientID,
+        lender: usize,
+    ) {
+        if let Some(account) = self.borrow(lender, recipient) {
+            let _ = self.shards[shard].sender.send(ShardMessage::Keep(account));
+        }
+        let _ = self.shards[shard].sender.send(message);
+        if let Some(account) = self.borrow(shard, recipient) {
+            let _ = self.shards[lender].sender.send(ShardMessage::Keep(account));
+        }
+    }
+
+    // Takes the account out of the shard, once it processed everything routed to it before.
+    fn borrow(&self, shard: usize, client_id: ClientID) -> Option<Account> {
+        let (reply, answer) = channel();
+        self.shards[shard]
+            .sender
+            .send(ShardMessage::Lend(client_id, reply))
+            .ok()?;
+        answer.recv().ok().flatten()
+    }
+
+    // Asks the shard who the transfer recorded in it went to.
+    fn recipient(&self, tx_id: TransactionID, shard: usize) -> Option<ClientID> {
+        let (reply, answer) = channel();
+        self.shards[shard]
+            .sender
+            .send(ShardMessage::Recipient(tx_id, reply))
+            .ok()?;
+        answer.recv().ok().flatten()
+    }
+
+    // Asks the shard of the claimant about the transaction, waiting for it to process everything routed to it before.
+    fn lookup(&self, tx_id: TransactionID, claimant: ClientID) -> Lookup {
+        let (reply, answer) = channel();
+        let shard = &self.shards[shard_index(claimant, self.shards.len())];
+        if shard
+            .sender
+            .send(ShardMessage::Lookup(tx_id, reply))
+            .is_err()
+        {
+            return (None, false);
+        }
+        answer.recv().unwrap_or((None, false))
+    }
+
+    // Waits for the shards to process everything and merges their state into one engine,
+    // reporting the rejected records in the order they came.
+    pub fn finish(
+        self,
+        rejections: &mut dyn RejectionSink,
+    ) -> Result<PaymentsEngine, InputProcessingError> {
+        let mut merged = PaymentsEngine {
+            rates: self.rates,
+            credit_limits: self.credit_limits,
+            rules: self.rules,
+            blocklist: self.blocklist,
+            dispute_rules: self.dispute_rules,
+            authorization_expiry: self.authorization_expiry,
+            rounding: self.rounding,
+            ..Default::default()
+        };
+        let mut rejected = self.rejections;
+        for shard in self.shards {
+            drop(shard.sender);
+            let outcome = shard.worker.join().expect("A shard worker panicked");
+            let (pe, shard_rejections) = match outcome {
+                Ok(outcome) => outcome,
+                Err(TransactionProcessingError::StorageUnavailable(e)) => {
+                    return Err(InputProcessingError::UnavailableStorage(e));
+                }
+                Err(other) => unreachable!("{} is a reason to reject a record", other),
+            };
+            merged.accounts.extend(pe.accounts);
+            merged.transactions.extend(pe.transactions);
+            merged.disputes.extend(pe.disputes);
+            merged.flagged.extend(pe.flagged);
+            // Each shard only moved its clock with the transactions of its own clients.
+            merged.clock = merged.clock.max(pe.clock);
+            rejected.extend(shard_rejections);
+        }
+        rejected.sort_by_key(|rejection| rejection.line);
+        for rejection in rejected {
+            rejections.reject(rejection)?;
+        }
+        rejections.flush()?;
+        Ok(merged)
+    }
+}
+
+// The operations that get their transaction recorded when applied.
+fn records_transaction(tx: &Transaction) -> bool {
+    matches!(
+        tx.operation,
+        Operation::Deposit(_)
+            | Operation::Withdrawal(_)
+            | Operation::Convert(..)
+            | Operation::Transfer(..)
+            | Operation::Authorize(_)
+    )
+}
+
+fn shard_index(client_id: ClientID, workers: usize) -> usize {
+    client_id as usize % workers
+}
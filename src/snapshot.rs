@@ -0,0 +1,60 @@
+use std::{
+    error, fmt,
+    io::{Read, Write},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::{
+        account::Account, disputes::Dispute, shared::Timestamp, transaction::StoredTransaction,
+    },
+    ratios::RatioState,
+};
+
+// The full state of a `PaymentsEngine` at some point, including the pending disputes and
+// the transaction index needed to process future disputes on past transactions.
+// Amounts are serialized as exact fractions so restoring it is lossless.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub accounts: Vec<Account>,
+    pub transactions: Vec<StoredTransaction>,
+    pub disputes: Vec<Dispute>,
+    // The clock of the engine, absent in snapshots from before it had one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock: Option<Timestamp>,
+    // The dispute and chargeback ratios counted so far, and the partners restricted for them.
+    #[serde(default, skip_serializing_if = "RatioState::is_empty")]
+    pub ratios: RatioState,
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Unreadable(String),
+    Unwritable(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Unreadable(reason) => {
+                write!(f, "Unable to read the snapshot: {}", reason)
+            }
+            SnapshotError::Unwritable(reason) => {
+                write!(f, "Unable to write the snapshot: {}", reason)
+            }
+        }
+    }
+}
+
+impl error::Error for SnapshotError {}
+
+impl Snapshot {
+    pub fn read_from<R: Read>(input: R) -> Result<Snapshot, SnapshotError> {
+        serde_json::from_reader(input).map_err(|e| SnapshotError::Unreadable(e.to_string()))
+    }
+
+    pub fn write_to<W: Write>(&self, output: W) -> Result<(), SnapshotError> {
+        serde_json::to_writer(output, self).map_err(|e| SnapshotError::Unwritable(e.to_string()))
+    }
+}
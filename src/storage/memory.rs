@@ -0,0 +1,65 @@
+// The default, in-memory, implementation of the stores using the `HashMap` indexes of the models.
+use crate::models::{
+    account::{Account, Accounts},
+    disputes::{Dispute, Disputes},
+    shared::{ClientID, TransactionID},
+    transaction::{Transaction, Transactions},
+};
+
+use super::{AccountStore, DisputeStore, StorageError, TransactionStore};
+
+impl AccountStore for Accounts {
+    fn get_account(&self, client_id: ClientID) -> Result<Option<Account>, StorageError> {
+        Ok(self.get(&client_id).cloned())
+    }
+
+    fn put_account(&mut self, account: Account) -> Result<(), StorageError> {
+        self.insert(account.client_id(), account);
+        Ok(())
+    }
+
+    fn all_accounts(&self) -> Result<Vec<Account>, StorageError> {
+        let mut accounts: Vec<Account> = self.values().cloned().collect();
+        accounts.sort_by_key(|account| account.client_id());
+        Ok(accounts)
+    }
+}
+
+impl TransactionStore for Transactions {
+    fn get_transaction(&self, tx_id: TransactionID) -> Result<Option<Transaction>, StorageError> {
+        Ok(self.get(&tx_id).cloned())
+    }
+
+    fn put_transaction(&mut self, tx: Transaction) -> Result<(), StorageError> {
+        self.insert(tx.tx_id, tx);
+        Ok(())
+    }
+
+    fn all_transactions(&self) -> Result<Vec<Transaction>, StorageError> {
+        let mut transactions: Vec<Transaction> = self.values().cloned().collect();
+        transactions.sort_by_key(|tx| tx.tx_id);
+        Ok(transactions)
+    }
+}
+
+impl DisputeStore for Disputes {
+    fn get_dispute(&self, tx_id: TransactionID) -> Result<Option<Dispute>, StorageError> {
+        Ok(self.get(&tx_id).cloned())
+    }
+
+    fn put_dispute(&mut self, dispute: Dispute) -> Result<(), StorageError> {
+        self.insert(dispute.tx_id, dispute);
+        Ok(())
+    }
+
+    fn remove_dispute(&mut self, tx_id: TransactionID) -> Result<(), StorageError> {
+        self.remove(&tx_id);
+        Ok(())
+    }
+
+    fn all_disputes(&self) -> Result<Vec<Dispute>, StorageError> {
+        let mut disputes: Vec<Dispute> = self.values().cloned().collect();
+        disputes.sort_by_key(|dispute| dispute.tx_id);
+        Ok(disputes)
+    }
+}
@@ -0,0 +1,78 @@
+use std::{error, fmt};
+
+use crate::models::{
+    account::Account,
+    disputes::Dispute,
+    shared::{ClientID, TransactionID},
+    transaction::Transaction,
+};
+
+pub mod memory;
+pub mod sqlite;
+
+#[derive(Debug)]
+pub struct StorageError(pub String);
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Storage unavailable: {}", self.0)
+    }
+}
+
+impl error::Error for StorageError {}
+
+// Where the engine keeps the accounts, reachable by client ID.
+pub trait AccountStore {
+    fn get_account(&self, client_id: ClientID) -> Result<Option<Account>, StorageError>;
+    fn put_account(&mut self, account: Account) -> Result<(), StorageError>;
+    // All the accounts, sorted by client ID.
+    fn all_accounts(&self) -> Result<Vec<Account>, StorageError>;
+    // Everything written between `begin` and `commit` is kept all at once, and none of it after `rollback`.
+    // Stores that can't lose half of a write don't need to do anything.
+    fn begin(&mut self) -> Result<(), StorageError> {
+        Ok(())
+    }
+    fn commit(&mut self) -> Result<(), StorageError> {
+        Ok(())
+    }
+    fn rollback(&mut self) -> Result<(), StorageError> {
+        Ok(())
+    }
+}
+
+// Where the engine keeps the processed transactions, reachable by transaction ID.
+pub trait TransactionStore {
+    fn get_transaction(&self, tx_id: TransactionID) -> Result<Option<Transaction>, StorageError>;
+    fn put_transaction(&mut self, tx: Transaction) -> Result<(), StorageError>;
+    // All the transactions, sorted by transaction ID.
+    fn all_transactions(&self) -> Result<Vec<Transaction>, StorageError>;
+    // As for accounts.
+    fn begin(&mut self) -> Result<(), StorageError> {
+        Ok(())
+    }
+    fn commit(&mut self) -> Result<(), StorageError> {
+        Ok(())
+    }
+    fn rollback(&mut self) -> Result<(), StorageError> {
+        Ok(())
+    }
+}
+
+// Where the engine keeps the disputes, reachable by the ID of the disputed transaction.
+pub trait DisputeStore {
+    fn get_dispute(&self, tx_id: TransactionID) -> Result<Option<Dispute>, StorageError>;
+    fn put_dispute(&mut self, dispute: Dispute) -> Result<(), StorageError>;
+    fn remove_dispute(&mut self, tx_id: TransactionID) -> Result<(), StorageError>;
+    // All the disputes, sorted by the ID of the disputed transaction.
+    fn all_disputes(&self) -> Result<Vec<Dispute>, StorageError>;
+    // As for accounts.
+    fn begin(&mut self) -> Result<(), StorageError> {
+        Ok(())
+    }
+    fn commit(&mut self) -> Result<(), StorageError> {
+        Ok(())
+    }
+    fn rollback(&mut self) -> Result<(), StorageError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,220 @@
+// An embedded, on-disk, implementation of the stores for when the accounts and transactions don't fit in memory.
+// Every entry is kept as JSON (with amounts as exact fractions) in a table keyed by its ID.
+use std::sync::{Arc, Mutex};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::models::{
+    account::Account,
+    disputes::Dispute,
+    shared::{ClientID, TransactionID},
+    transaction::{StoredTransaction, Transaction},
+};
+
+use super::{AccountStore, DisputeStore, StorageError, TransactionStore};
+
+const ACCOUNTS: &str = "accounts";
+const TRANSACTIONS: &str = "transactions";
+const DISPUTES: &str = "disputes";
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(error: rusqlite::Error) -> Self {
+        StorageError(error.to_string())
+    }
+}
+
+impl From<serde_json::Error> for StorageError {
+    fn from(error: serde_json::Error) -> Self {
+        StorageError(error.to_string())
+    }
+}
+
+// The three stores share the same database connection.
+pub type SqliteStores = (SqliteAccounts, SqliteTransactions, SqliteDisputes);
+
+// Opens (or creates) the database at the given path.
+pub fn open(path: &str) -> Result<SqliteStores, StorageError> {
+    let connection = Connection::open(path)?;
+    // Trading some durability on power loss for speed, the database stays consistent anyway.
+    connection.pragma_update(None, "journal_mode", "WAL")?;
+    connection.pragma_update(None, "synchronous", "NORMAL")?;
+    stores_for(connection)
+}
+
+pub fn open_in_memory() -> Result<SqliteStores, StorageError> {
+    stores_for(Connection::open_in_memory()?)
+}
+
+fn stores_for(connection: Connection) -> Result<SqliteStores, StorageError> {
+    for table in [ACCOUNTS, TRANSACTIONS, DISPUTES] {
+        connection.execute(
+            &format!(
+                "CREATE TABLE IF NOT EXISTS {} (id INTEGER PRIMARY KEY, data TEXT NOT NULL)",
+                table
+            ),
+            [],
+        )?;
+    }
+    let connection = Arc::new(Mutex::new(connection));
+    Ok((
+        SqliteAccounts {
+            connection: connection.clone(),
+        },
+        SqliteTransactions {
+            connection: connection.clone(),
+        },
+        SqliteDisputes { connection },
+    ))
+}
+
+fn get<T: DeserializeOwned>(
+    connection: &Mutex<Connection>,
+    table: &str,
+    id: i64,
+) -> Result<Option<T>, StorageError> {
+    let connection = lock(connection)?;
+    let data: Option<String> = connection
+        .query_row(
+            &format!("SELECT data FROM {} WHERE id = ?1", table),
+            params![id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    match data {
+        None => Ok(None),
+        Some(data) => Ok(Some(serde_json::from_str(&data)?)),
+    }
+}
+
+fn put<T: Serialize>(
+    connection: &Mutex<Connection>,
+    table: &str,
+    id: i64,
+    value: &T,
+) -> Result<(), StorageError> {
+    let data = serde_json::to_string(value)?;
+    lock(connection)?.execute(
+        &format!(
+            "INSERT OR REPLACE INTO {} (id, data) VALUES (?1, ?2)",
+            table
+        ),
+        params![id, data],
+    )?;
+    Ok(())
+}
+
+fn remove(connection: &Mutex<Connection>, table: &str, id: i64) -> Result<(), StorageError> {
+    lock(connection)?.execute(&format!("DELETE FROM {} WHERE id = ?1", table), params![id])?;
+    Ok(())
+}
+
+fn all<T: DeserializeOwned>(
+    connection: &Mutex<Connection>,
+    table: &str,
+) -> Result<Vec<T>, StorageError> {
+    let connection = lock(connection)?;
+    let mut statement = connection.prepare(&format!("SELECT data FROM {} ORDER BY id", table))?;
+    let rows = statement.query_map([], |row| row.get::<_, String>(0))?;
+    let mut values = vec![];
+    for data in rows {
+        values.push(serde_json::from_str(&data?)?);
+    }
+    Ok(values)
+}
+
+fn execute(connection: &Mutex<Connection>, statement: &str) -> Result<(), StorageError> {
+    lock(connection)?.execute_batch(statement)?;
+    Ok(())
+}
+
+fn lock(
+    connection: &Mutex<Connection>,
+) -> Result<std::sync::MutexGuard<'_, Connection>, StorageError> {
+    connection
+        .lock()
+        .map_err(|_| StorageError("the database connection was poisoned".to_owned()))
+}
+
+pub struct SqliteAccounts {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl AccountStore for SqliteAccounts {
+    fn get_account(&self, client_id: ClientID) -> Result<Option<Account>, StorageError> {
+        get(&self.connection, ACCOUNTS, client_id.into())
+    }
+
+    fn put_account(&mut self, account: Account) -> Result<(), StorageError> {
+        put(
+            &self.connection,
+            ACCOUNTS,
+            account.client_id().into(),
+            &account,
+        )
+    }
+
+    fn all_accounts(&self) -> Result<Vec<Account>, StorageError> {
+        all(&self.connection, ACCOUNTS)
+    }
+
+    // Since the three stores share the connection, the transactions and disputes written in between are part of it too.
+    fn begin(&mut self) -> Result<(), StorageError> {
+        execute(&self.connection, "BEGIN IMMEDIATE")
+    }
+
+    fn commit(&mut self) -> Result<(), StorageError> {
+        execute(&self.connection, "COMMIT")
+    }
+
+    fn rollback(&mut self) -> Result<(), StorageError> {
+        execute(&self.connection, "ROLLBACK")
+    }
+}
+
+pub struct SqliteTransactions {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl TransactionStore for SqliteTransactions {
+    fn get_transaction(&self, tx_id: TransactionID) -> Result<Option<Transaction>, StorageError> {
+        let stored: Option<StoredTransaction> = get(&self.connection, TRANSACTIONS, tx_id.into())?;
+        Ok(stored.map(Transaction::from))
+    }
+
+    fn put_transaction(&mut self, tx: Transaction) -> Result<(), StorageError> {
+        put(
+            &self.connection,
+            TRANSACTIONS,
+            tx.tx_id.into(),
+            &StoredTransaction::from(&tx),
+        )
+    }
+
+    fn all_transactions(&self) -> Result<Vec<Transaction>, StorageError> {
+        let stored: Vec<StoredTransaction> = all(&self.connection, TRANSACTIONS)?;
+        Ok(stored.into_iter().map(Transaction::from).collect())
+    }
+}
+
+pub struct SqliteDisputes {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl DisputeStore for SqliteDisputes {
+    fn get_dispute(&self, tx_id: TransactionID) -> Result<Option<Dispute>, StorageError> {
+        get(&self.connection, DISPUTES, tx_id.into())
+    }
+
+    fn put_dispute(&mut self, dispute: Dispute) -> Result<(), StorageError> {
+        put(&self.connection, DISPUTES, dispute.tx_id.into(), &dispute)
+    }
+
+    fn remove_dispute(&mut self, tx_id: TransactionID) -> Result<(), StorageError> {
+        remove(&self.connection, DISPUTES, tx_id.into())
+    }
+
+    fn all_disputes(&self) -> Result<Vec<Dispute>, StorageError> {
+        all(&self.connection, DISPUTES)
+    }
+}
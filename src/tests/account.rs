@@ -1,7 +1,44 @@
 use csv::{ReaderBuilder, Trim};
 use fraction::Decimal;
 
-use crate::models::transaction::Transaction;
+use crate::{
+    admin::{AdminAction, AdminOperation},
+    input_ingestion::get_csv_reader_from,
+    models::{
+        account::{render_accounts, render_accounts_with_status, Account},
+        disputes::DisputeRules,
+        restrictions::{AccountStatus, Restriction},
+        transaction::Transaction,
+    },
+    payments_engine::PaymentsEngine,
+    rejections::{Rejection, RejectionReason},
+};
+
+// Client 1 has 10 deposited and 2 of them disputed, and gets the restriction.
+fn restricted_with(restriction: Restriction) -> PaymentsEngine {
+    let data = "type, client, tx, amount\ndeposit, 1, 1, 8.0\ndeposit, 1, 2, 2.0\ndispute, 1, 2,\n";
+    let mut pe = PaymentsEngine::default();
+    pe.process_transactions_from(get_csv_reader_from(data.as_bytes()))
+        .unwrap();
+    pe.apply_admin(AdminOperation {
+        client_id: 1,
+        action: AdminAction::Freeze(restriction),
+        operator: "jane".to_owned(),
+        reason: "Review".to_owned(),
+    })
+    .unwrap();
+    pe
+}
+
+fn rejected_lines(pe: &mut PaymentsEngine, data: &str) -> Vec<(u64, RejectionReason)> {
+    let mut rejections: Vec<Rejection> = vec![];
+    pe.process_transactions_reporting_to(get_csv_reader_from(data.as_bytes()), &mut rejections)
+        .unwrap();
+    rejections
+        .iter()
+        .map(|rejection| (rejection.line, rejection.reason))
+        .collect()
+}
 
 #[test]
 fn ignore_repeated_deposit() {
@@ -16,6 +53,140 @@ fn ignore_repeated_deposit() {
 
     assert_eq!(deposit.client_id, 328);
     assert_eq!(deposit.tx_id, 56);
-    assert_ne!(deposit.amount.unwrap(), Decimal::from(2.6));
-    assert_eq!(deposit.amount.unwrap(), Decimal::from(1.3));
+    assert_ne!(deposit.amount().unwrap(), Decimal::from(2.6));
+    assert_eq!(deposit.amount().unwrap(), Decimal::from(1.3));
+}
+
+#[test]
+fn lets_funds_in_but_not_out_under_fraud_review() {
+    let mut pe = restricted_with(Restriction::FraudReview);
+    let data = "type, client, tx, amount, to_client\ndeposit, 1, 3, 1.0,\nwithdrawal, 1, 4, 1.0,\ntransfer, 1, 5, 1.0, 2\nresolve, 1, 2,,\ntransfer, 2, 6, 0.0, 1\n";
+    assert_eq!(
+        rejected_lines(&mut pe, data),
+        vec![
+            (3, RejectionReason::TargetAccountLocked),
+            (4, RejectionReason::TargetAccountLocked),
+        ]
+    );
+    let account = pe.accounts.get(&1).unwrap();
+    assert_eq!(account.total, Decimal::from(11));
+    assert_eq!(account.held, Decimal::from(0));
+    assert!(account.is_locked());
+    assert_eq!(account.status(), AccountStatus::Restricted);
+}
+
+#[test]
+fn lets_only_disputes_go_on_under_compliance_hold() {
+    let mut pe = restricted_with(Restriction::ComplianceHold);
+    let data = "type, client, tx, amount, to_client\ndeposit, 1, 3, 1.0,\ndeposit, 2, 4, 1.0,\ntransfer, 2, 5, 1.0, 1\nchargeback, 1, 2,,\n";
+    assert_eq!(
+        rejected_lines(&mut pe, data),
+        vec![
+            (2, RejectionReason::TargetAccountLocked),
+            (4, RejectionReason::TargetAccountLocked),
+        ]
+    );
+    let account = pe.accounts.get(&1).unwrap();
+    assert_eq!(account.total, Decimal::from(8));
+    assert_eq!(
+        account.restrictions.iter().copied().collect::<Vec<_>>(),
+        vec![Restriction::Chargeback, Restriction::ComplianceHold]
+    );
+    assert_eq!(account.status(), AccountStatus::Locked);
+}
+
+#[test]
+fn releases_authorizations_on_accounts_allowing_resolves() {
+    // Nothing can come in with excessive disputes nor under compliance hold, but what was authorized can be released.
+    for restriction in [Restriction::ExcessiveDisputes, Restriction::ComplianceHold] {
+        let mut pe = PaymentsEngine::default();
+        let data = "type, client, tx, amount\ndeposit, 1, 1, 10.0\nauthorize, 1, 2, 4.0\n";
+        pe.process_transactions_from(get_csv_reader_from(data.as_bytes()))
+            .unwrap();
+        pe.apply_admin(AdminOperation {
+            client_id: 1,
+            action: AdminAction::Freeze(restriction),
+            operator: "jane".to_owned(),
+            reason: "Review".to_owned(),
+        })
+        .unwrap();
+        let data = "type, client, tx, amount\ndeposit, 1, 3, 1.0\nrelease, 1, 2,\n";
+        assert_eq!(
+            rejected_lines(&mut pe, data),
+            vec![(2, RejectionReason::TargetAccountLocked)]
+        );
+        let account = pe.accounts.get(&1).unwrap();
+        assert_eq!(account.total, Decimal::from(10));
+        assert_eq!(account.held, Decimal::from(0));
+    }
+}
+
+#[test]
+fn releases_authorizations_on_charged_back_accounts_settling_disputes() {
+    // Only with `--settle-disputes-when-locked`, as for resolves.
+    for settle_when_locked in [false, true] {
+        let mut pe = PaymentsEngine {
+            dispute_rules: DisputeRules {
+                settle_when_locked,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let data = "type, client, tx, amount\ndeposit, 1, 1, 10.0\ndeposit, 1, 2, 2.0\nauthorize, 1, 3, 4.0\ndispute, 1, 2,\nchargeback, 1, 2,\n";
+        pe.process_transactions_from(get_csv_reader_from(data.as_bytes()))
+            .unwrap();
+        let data = "type, client, tx, amount\nrelease, 1, 3,\n";
+        let rejected = rejected_lines(&mut pe, data);
+        let account = pe.accounts.get(&1).unwrap();
+        assert!(account.is_locked());
+        if settle_when_locked {
+            assert!(rejected.is_empty());
+            assert_eq!(account.held, Decimal::from(0));
+        } else {
+            assert_eq!(rejected, vec![(2, RejectionReason::TargetAccountLocked)]);
+            assert_eq!(account.held, Decimal::from(4));
+        }
+    }
+}
+
+#[test]
+fn blocks_everything_once_closed() {
+    let mut pe = restricted_with(Restriction::Closed);
+    let data = "type, client, tx, amount\ndeposit, 1, 3, 1.0\nresolve, 1, 2,\n";
+    assert_eq!(rejected_lines(&mut pe, data).len(), 2);
+    assert_eq!(pe.accounts.get(&1).unwrap().status(), AccountStatus::Closed);
+}
+
+#[test]
+fn renders_the_status_and_restrictions_only_when_asked_for() {
+    let pe = restricted_with(Restriction::FraudReview);
+    let accounts = vec![pe.accounts.get(&1).unwrap().clone(), Account::new(2)];
+    assert_eq!(
+        render_accounts(&accounts, pe.rounding),
+        vec![
+            "client, available, held, total, locked",
+            "1, 8.0000, 2.0000, 10.0000, true",
+            "2, 0.0000, 0.0000, 0.0000, false",
+        ]
+    );
+    assert_eq!(
+        render_accounts_with_status(&accounts, pe.rounding),
+        vec![
+            "client, available, held, total, locked, status, restrictions",
+            "1, 8.0000, 2.0000, 10.0000, true, restricted, fraud-review",
+            "2, 0.0000, 0.0000, 0.0000, false, active, ",
+        ]
+    );
+}
+
+#[test]
+fn restores_accounts_locked_before_restrictions_existed() {
+    let stored = serde_json::to_string(&Account::new(1))
+        .unwrap()
+        .replace("\"locked\":false", "\"locked\":true");
+    let account: Account = serde_json::from_str(&stored).unwrap();
+    assert!(account.restrictions.contains(&Restriction::Chargeback));
+    assert!(serde_json::to_string(&account)
+        .unwrap()
+        .contains("\"locked\":true"));
 }
@@ -0,0 +1,161 @@
+use std::{env, fs};
+
+use fraction::Decimal;
+
+use crate::{
+    admin::{open_admin_operations, read_admin_operations, AdminAction, AdminError},
+    input_ingestion::get_csv_reader_from,
+    journal::{read_journal, Applied, Journal},
+    models::{disputes::DisputeState, money::Money, restrictions::Restriction},
+    payments_engine::PaymentsEngine,
+    rejections::{Rejection, RejectionReason},
+};
+
+// Client 1 gets locked by a chargeback, client 2 has funds in USD.
+const LOCKING_INPUT: &str = "type, client, tx, amount, currency\ndeposit, 1, 1, 5.0,\ndeposit, 1, 2, 3.0,\ndispute, 1, 2,,\nchargeback, 1, 2,,\ndeposit, 2, 3, 1.0, USD\n";
+
+#[test]
+fn reads_the_operations_of_the_admin_file() {
+    let operations = open_admin_operations("resources/admin.csv").unwrap();
+    assert_eq!(operations.len(), 4);
+    assert_eq!(operations[0].client_id, 1);
+    assert_eq!(
+        operations[0].action,
+        AdminAction::Unlock(Some(Restriction::Chargeback))
+    );
+    assert_eq!(operations[0].operator, "jane");
+    assert_eq!(operations[0].reason, "Chargeback reversed by the partner");
+    assert_eq!(
+        operations[3].action,
+        AdminAction::Debit(Money {
+            amount: Decimal::from(0.5),
+            currency: Some("USD".to_owned()),
+        })
+    );
+}
+
+#[test]
+fn refuses_operations_without_operator_or_reason() {
+    let data = "action, client, amount, currency, restriction, operator, reason\nunlock, 1, , , , jane, Reviewed\nfreeze, 2, , , fraud-review, , Fraud review\n";
+    assert!(matches!(
+        read_admin_operations(data.as_bytes()),
+        Err(AdminError::InvalidOperation(3))
+    ));
+    let data = "action, client, amount, currency, operator, reason\nunlock, 1, , , jane,\n";
+    assert!(matches!(
+        read_admin_operations(data.as_bytes()),
+        Err(AdminError::InvalidOperation(2))
+    ));
+}
+
+#[test]
+fn refuses_freezes_without_a_restriction() {
+    let data = "action, client, amount, currency, restriction, operator, reason\nfreeze, 2, , , , joe, Fraud review\n";
+    assert!(matches!(
+        read_admin_operations(data.as_bytes()),
+        Err(AdminError::InvalidOperation(2))
+    ));
+}
+
+#[test]
+fn refuses_adjustments_without_a_positive_amount() {
+    let data =
+        "action, client, amount, currency, operator, reason\ncredit, 1, , , jane, Goodwill\n";
+    assert!(matches!(
+        read_admin_operations(data.as_bytes()),
+        Err(AdminError::InvalidOperation(2))
+    ));
+    let data = "action, client, amount, currency, operator, reason\ndebit, 1, -1.0, , jane, Fix\n";
+    assert!(matches!(
+        read_admin_operations(data.as_bytes()),
+        Err(AdminError::InvalidOperation(2))
+    ));
+}
+
+#[test]
+fn remediates_accounts_so_they_operate_again() {
+    let mut pe = PaymentsEngine::default();
+    pe.process_transactions_from(get_csv_reader_from(LOCKING_INPUT.as_bytes()))
+        .unwrap();
+    assert!(pe.accounts.get(&1).unwrap().is_locked());
+
+    pe.apply_admin_operations(open_admin_operations("resources/admin.csv").unwrap())
+        .unwrap();
+    let account = pe.accounts.get(&1).unwrap();
+    assert!(!account.is_locked());
+    assert_eq!(account.total, Decimal::from(7));
+    let account = pe.accounts.get(&2).unwrap();
+    assert!(account.is_locked());
+    assert_eq!(account.balance_in(Some("USD")).total, Decimal::from(0.5));
+
+    let data = "type, client, tx, amount\ndeposit, 1, 4, 1.0\ndeposit, 2, 5, 1.0\n";
+    let mut rejections: Vec<Rejection> = vec![];
+    pe.process_transactions_reporting_to(get_csv_reader_from(data.as_bytes()), &mut rejections)
+        .unwrap();
+    assert_eq!(pe.accounts.get(&1).unwrap().total, Decimal::from(8));
+    assert_eq!(rejections.len(), 1);
+    assert_eq!(rejections[0].reason, RejectionReason::TargetAccountLocked);
+}
+
+#[test]
+fn leaves_disputes_charged_back_in_full_over_after_unlocking() {
+    let mut pe = PaymentsEngine::default();
+    pe.process_transactions_from(get_csv_reader_from(LOCKING_INPUT.as_bytes()))
+        .unwrap();
+    pe.apply_admin_operations(open_admin_operations("resources/admin.csv").unwrap())
+        .unwrap();
+
+    let data = "type, client, tx, amount\ndispute, 1, 2,\nchargeback, 1, 2,\n";
+    pe.process_transactions_from(get_csv_reader_from(data.as_bytes()))
+        .unwrap();
+    let account = pe.accounts.get(&1).unwrap();
+    assert!(!account.is_locked());
+    assert_eq!(account.total, Decimal::from(7));
+    assert_eq!(account.held, Decimal::from(0));
+    let dispute = pe.disputes.get(&2).unwrap();
+    assert_eq!(dispute.reopenings, 0);
+    assert_eq!(
+        dispute.history,
+        vec![DisputeState::Open, DisputeState::ChargedBack]
+    );
+}
+
+#[test]
+fn journals_admin_operations_and_replays_them() {
+    let path = env::temp_dir().join(format!("tpe-admin-{}.jsonl", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let mut pe = PaymentsEngine {
+        journal: Some(Journal::open(path.to_str().unwrap()).unwrap()),
+        ..Default::default()
+    };
+    pe.process_transactions_from(get_csv_reader_from(LOCKING_INPUT.as_bytes()))
+        .unwrap();
+    pe.apply_admin_operations(open_admin_operations("resources/admin.csv").unwrap())
+        .unwrap();
+
+    let entries: Vec<_> = read_journal(fs::File::open(&path).unwrap())
+        .map(|entry| entry.unwrap())
+        .collect();
+    assert_eq!(entries.len(), 9);
+    match &entries[5].applied {
+        Applied::Admin { admin } => {
+            assert_eq!(
+                admin.action,
+                AdminAction::Unlock(Some(Restriction::Chargeback))
+            );
+            assert_eq!(admin.operator, "jane");
+        }
+        _ => panic!("The unlock was not journaled as an admin operation"),
+    }
+    assert!(!entries[5].locked);
+
+    let mut replayed = PaymentsEngine::default();
+    replayed.replay(fs::File::open(&path).unwrap()).unwrap();
+    for (client_id, account) in pe.accounts.iter() {
+        let replayed_account = replayed.accounts.get(client_id).unwrap();
+        assert_eq!(replayed_account.total, account.total);
+        assert_eq!(replayed_account.balances, account.balances);
+        assert_eq!(replayed_account.is_locked(), account.is_locked());
+    }
+    fs::remove_file(&path).unwrap();
+}
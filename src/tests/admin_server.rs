@@ -0,0 +1,115 @@
+use std::{
+    env, fs,
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use fraction::Decimal;
+
+use crate::{
+    admin_server::AdminServer,
+    blocklist::Blocklist,
+    input_ingestion::get_csv_reader_from,
+    models::{account::Account, restrictions::Restriction},
+    payments_engine::PaymentsEngine,
+};
+
+fn start_admin_server(engine: Arc<Mutex<PaymentsEngine>>) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = AdminServer::new(engine);
+    thread::spawn(move || server.serve(listener));
+    address
+}
+
+// Sends the lines and returns the answers to them, without the empty lines following each one.
+fn send(address: SocketAddr, lines: &str) -> Vec<String> {
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream.write_all(lines.as_bytes()).unwrap();
+    let answers = lines
+        .lines()
+        .filter(|line| !line.starts_with("action"))
+        .count();
+    BufReader::new(stream)
+        .lines()
+        .map(|line| line.unwrap())
+        .take(answers * 2)
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+fn account(engine: &Arc<Mutex<PaymentsEngine>>, client_id: u16) -> Account {
+    engine
+        .lock()
+        .unwrap()
+        .accounts
+        .get(&client_id)
+        .unwrap()
+        .clone()
+}
+
+#[test]
+fn applies_the_operations_of_operators_as_they_arrive() {
+    let mut pe = PaymentsEngine::default();
+    let data = "type, client, tx, amount\ndeposit, 1, 1, 5.0\ndispute, 1, 1,\nchargeback, 1, 1,\n";
+    pe.process_transactions_from(get_csv_reader_from(data.as_bytes()))
+        .unwrap();
+    let engine = Arc::new(Mutex::new(pe));
+    let address = start_admin_server(Arc::clone(&engine));
+
+    let answers = send(
+        address,
+        "action, client, amount, currency, restriction, operator, reason
+unlock, 1, , , chargeback, jane, Chargeback reversed by the partner
+credit, 1, 5.0, , , jane, Goodwill
+debit, 1, , , , jane, Nothing to take
+",
+    );
+    assert_eq!(answers[..2], ["applied", "applied"]);
+    assert_eq!(
+        answers[2],
+        "The admin operation at line 4 is not valid".to_owned()
+    );
+    let account = account(&engine, 1);
+    assert!(!account.restrictions.contains(&Restriction::Chargeback));
+    assert_eq!(account.total, Decimal::from(5));
+}
+
+#[test]
+fn reloads_the_blocklist_on_request() {
+    let path = env::temp_dir().join(format!("tpe-admin-blocklist-{}.csv", std::process::id()));
+    fs::write(&path, "scope, id\n").unwrap();
+    let engine = Arc::new(Mutex::new(PaymentsEngine {
+        blocklist: Blocklist::open(path.to_str().unwrap()).unwrap(),
+        ..Default::default()
+    }));
+    let address = start_admin_server(Arc::clone(&engine));
+
+    fs::write(&path, "scope, id\nclient, 2\npartner, 9\n").unwrap();
+    assert_eq!(
+        send(address, "reload-blocklist\n"),
+        vec!["blocklist, 1 clients, 1 partners"]
+    );
+    assert!(engine.lock().unwrap().blocklist.blocks_client(2));
+
+    // An invalid file leaves the previous blocklist in place.
+    fs::write(&path, "scope, id\nclient, someone\n").unwrap();
+    assert_eq!(
+        send(address, "reload-blocklist\n"),
+        vec!["The blocklist entry at line 2 is not valid"]
+    );
+    assert_eq!(engine.lock().unwrap().blocklist.len(), (1, 1));
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn refuses_reloading_a_blocklist_without_a_file() {
+    let engine = Arc::new(Mutex::new(PaymentsEngine::default()));
+    let address = start_admin_server(engine);
+    assert_eq!(
+        send(address, "reload-blocklist\n"),
+        vec!["There is no blocklist file to reload from"]
+    );
+}
@@ -0,0 +1,132 @@
+use std::{
+    env,
+    fs::{self, File},
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use fraction::Decimal;
+
+use crate::{
+    blocklist::{Blocklist, BlocklistError},
+    input_ingestion::get_csv_reader,
+    models::transaction::TransactionProcessingError,
+    payments_engine::PaymentsEngine,
+    rejections::RejectionReason,
+    tests::{engine_with, transaction},
+};
+
+fn with_blocklist(pe: &mut PaymentsEngine) {
+    pe.blocklist = Blocklist::open("resources/blocklist.csv").unwrap();
+}
+
+fn blocklist_path(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("tpe-{}-{}.csv", name, std::process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn rejects_transactions_of_blocked_clients_before_creating_their_account() {
+    let mut pe = engine_with(with_blocklist);
+    assert!(matches!(
+        pe.process(transaction("deposit, 3, 1, 10.0")),
+        Err(TransactionProcessingError::Blocked(1))
+    ));
+    assert!(!pe.accounts.contains_key(&3));
+}
+
+#[test]
+fn refuses_entries_without_an_id() {
+    let data = "scope, id\nclient, 3\npartner,\n";
+    assert!(matches!(
+        Blocklist::read_from(data.as_bytes()),
+        Err(BlocklistError::InvalidEntry(3))
+    ));
+}
+
+#[test]
+fn reloads_the_file_it_was_opened_from() {
+    let path = blocklist_path("blocklist");
+    fs::write(&path, "scope, id\nclient, 1\n").unwrap();
+    let mut pe = PaymentsEngine {
+        blocklist: Blocklist::open(path.to_str().unwrap()).unwrap(),
+        ..Default::default()
+    };
+    pe.process(transaction("deposit, 2, 1, 10.0")).unwrap();
+
+    fs::write(&path, "scope, id\nclient, 2\n").unwrap();
+    pe.blocklist.reload().unwrap();
+    pe.process(transaction("deposit, 1, 2, 10.0")).unwrap();
+    assert!(matches!(
+        pe.process(transaction("withdrawal, 2, 3, 1.0")),
+        Err(TransactionProcessingError::Blocked(3))
+    ));
+
+    // An invalid file leaves the previous blocklist in place.
+    fs::write(&path, "scope, id\nclient,\n").unwrap();
+    assert!(pe.blocklist.reload().is_err());
+    assert_eq!(pe.blocklist.len(), (1, 0));
+    assert!(pe.blocklist.blocks_client(2));
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn refuses_reloading_without_a_file() {
+    let mut blocklist = Blocklist::default();
+    assert!(matches!(
+        blocklist.reload(),
+        Err(BlocklistError::NothingToReload)
+    ));
+    assert!(!blocklist.reload_if_modified().unwrap());
+}
+
+#[test]
+fn reloads_only_when_the_file_was_modified() {
+    let path = blocklist_path("modified");
+    fs::write(&path, "scope, id\nclient, 1\n").unwrap();
+    let mut blocklist = Blocklist::open(path.to_str().unwrap()).unwrap();
+    assert!(!blocklist.reload_if_modified().unwrap());
+
+    // The modification time is moved on explicitly, as writes in a row can share the same one.
+    fs::write(&path, "scope, id\nclient, 2\npartner, 9\n").unwrap();
+    let later = SystemTime::now() + Duration::from_secs(60);
+    File::options()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_modified(later)
+        .unwrap();
+    assert!(blocklist.reload_if_modified().unwrap());
+    assert_eq!(blocklist.len(), (1, 1));
+    assert!(blocklist.blocks_client(2));
+    assert!(!blocklist.reload_if_modified().unwrap());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn case30() {
+    // Deposits of a blocked client, through a blocked partner, and transfers to a blocked client.
+    let reader = get_csv_reader("resources/case-inputs/case30.csv".to_owned());
+    let mut pe = engine_with(with_blocklist);
+    let mut rejections = vec![];
+    pe.process_transactions_reporting_to(reader.unwrap(), &mut rejections)
+        .unwrap();
+
+    assert_eq!(pe.accounts.get(&1).unwrap().total, Decimal::from(5));
+    assert_eq!(pe.accounts.get(&2).unwrap().total, Decimal::from(6));
+    assert!(!pe.accounts.contains_key(&3));
+
+    let rejected: Vec<(u64, RejectionReason)> = rejections
+        .iter()
+        .map(|rejection| (rejection.line, rejection.reason))
+        .collect();
+    assert_eq!(
+        rejected,
+        vec![
+            (3, RejectionReason::Blocked),
+            (4, RejectionReason::Blocked),
+            (5, RejectionReason::Blocked),
+        ]
+    );
+}
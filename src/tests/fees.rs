@@ -0,0 +1,94 @@
+use fraction::Decimal;
+
+use crate::{
+    fees::{FeeSchedule, FeesError},
+    tests::transaction,
+};
+
+fn schedule() -> FeeSchedule {
+    FeeSchedule::open("resources/fees.csv", 9).unwrap()
+}
+
+#[test]
+fn charges_the_fee_of_the_tier_of_the_amount() {
+    let fees = schedule();
+    // 0.5 + 1% of 10
+    assert_eq!(
+        fees.fee_for(&transaction("withdrawal, 1, 1, 10.0")),
+        Decimal::from(0.6)
+    );
+    // 0.5% of 200, from the tier starting at 100
+    assert_eq!(
+        fees.fee_for(&transaction("withdrawal, 1, 2, 200.0")),
+        Decimal::from(1)
+    );
+    assert_eq!(
+        fees.fee_for(&transaction("deposit, 1, 3, 200.0")),
+        Decimal::from(0.1)
+    );
+}
+
+#[test]
+fn charges_the_fees_of_the_client_over_the_ones_of_everybody() {
+    let fees = schedule();
+    assert_eq!(
+        fees.fee_for(&transaction("withdrawal, 2, 1, 10.0")),
+        Decimal::from(0)
+    );
+    // Client 2 only has its own fee for withdrawals.
+    assert_eq!(
+        fees.fee_for(&transaction("deposit, 2, 2, 10.0")),
+        Decimal::from(0.1)
+    );
+}
+
+#[test]
+fn never_charges_more_than_a_deposit_brings_nor_the_house_account() {
+    let fees = schedule();
+    assert_eq!(
+        fees.fee_for(&transaction("deposit, 1, 1, 0.05")),
+        Decimal::from(0.05)
+    );
+    assert_eq!(
+        fees.fee_for(&transaction("withdrawal, 9, 2, 10.0")),
+        Decimal::from(0)
+    );
+    assert_eq!(
+        fees.fee_for(&transaction("dispute, 1, 1,")),
+        Decimal::from(0)
+    );
+}
+
+#[test]
+fn charges_the_fees_of_the_currency_of_the_transaction() {
+    let fees = schedule();
+    // The schedule has no fees in BRL, and the 0.1 for deposits is in the default currency.
+    assert_eq!(
+        fees.fee_for(&transaction("deposit, 1, 1, 200.0, , BRL")),
+        Decimal::from(0)
+    );
+    // 0.1 + 1% of 10, with the tiers of USD, not the 0.5% from 100 of the default currency.
+    assert_eq!(
+        fees.fee_for(&transaction("withdrawal, 1, 2, 10.0, , USD")),
+        Decimal::from(0.2)
+    );
+    assert_eq!(
+        fees.fee_for(&transaction("withdrawal, 1, 3, 200.0, , usd")),
+        Decimal::from(2.1)
+    );
+}
+
+#[test]
+fn refuses_fees_that_are_not_for_deposits_or_withdrawals() {
+    let data =
+        "client, type, from_amount, flat, percentage\n, deposit, 0, 0.1, 0\n, dispute, 0, 1, 0\n";
+    assert!(matches!(
+        FeeSchedule::read_from(data.as_bytes(), 9),
+        Err(FeesError::InvalidFee(3))
+    ));
+    let data = "client, type, from_amount, flat, percentage\n, deposit, 0, -0.1, 0\n";
+    assert!(matches!(
+        FeeSchedule::read_from(data.as_bytes(), 9),
+        Err(FeesError::InvalidFee(2))
+    ));
+}
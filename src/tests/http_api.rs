@@ -0,0 +1,164 @@
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    body::Body,
+    http::{Method, Request, StatusCode},
+    Router,
+};
+use http_body_util::BodyExt;
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+use crate::{http_api::router, payments_engine::PaymentsEngine};
+
+fn new_api() -> Router {
+    router(Arc::new(Mutex::new(PaymentsEngine::default())))
+}
+
+async fn send(api: &Router, method: Method, uri: &str, body: &str) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .body(Body::from(body.to_owned()))
+        .unwrap();
+    let response = api.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = response.into_body().collect().await.unwrap().to_bytes();
+    let value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    (status, value)
+}
+
+#[tokio::test]
+async fn applies_a_transaction_and_returns_the_account() {
+    let api = new_api();
+    let (status, outcome) = send(
+        &api,
+        Method::POST,
+        "/transactions",
+        r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "2.5"}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(outcome, json!({"status": "applied"}));
+
+    let (status, account) = send(&api, Method::GET, "/accounts/1", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        account,
+        json!({"client": 1, "available": "2.5000", "held": "0.0000", "total": "2.5000", "locked": false, "status": "active"})
+    );
+
+    let (status, _) = send(&api, Method::GET, "/accounts/2", "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn returns_why_a_transaction_was_rejected() {
+    let api = new_api();
+    let (status, outcome) = send(
+        &api,
+        Method::POST,
+        "/transactions",
+        r#"{"type": "withdrawal", "client": 1, "tx": 1, "amount": "2.5"}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(outcome["status"], "rejected");
+    assert_eq!(outcome["reason"], "InsufficientAvailableFunds");
+
+    let (status, outcome) = send(
+        &api,
+        Method::POST,
+        "/transactions",
+        r#"{"type": "deposit", "client": 1, "tx": 2, "amount": "-1"}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(outcome["reason"], "NegativeAmount");
+
+    let (status, outcome) = send(
+        &api,
+        Method::POST,
+        "/transactions",
+        r#"{"type": "dispute", "client": 1, "tx": 3}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(outcome["reason"], "NotFound");
+}
+
+#[tokio::test]
+async fn tells_when_a_transaction_was_ignored() {
+    // A repeated transaction ID and a resolve of a transaction that is not disputed change nothing.
+    let api = new_api();
+    for (body, expected) in [
+        (
+            r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "2.5"}"#,
+            "applied",
+        ),
+        (
+            r#"{"type": "deposit", "client": 1, "tx": 1, "amount": "2.5"}"#,
+            "ignored",
+        ),
+        (r#"{"type": "resolve", "client": 1, "tx": 1}"#, "ignored"),
+    ] {
+        let (status, outcome) = send(&api, Method::POST, "/transactions", body).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(outcome, json!({"status": expected}));
+    }
+    let (_, account) = send(&api, Method::GET, "/accounts/1", "").await;
+    assert_eq!(account["total"], "2.5000");
+}
+
+#[tokio::test]
+async fn returns_the_outcome_of_every_row_of_a_batch() {
+    let api = new_api();
+    let (status, outcomes) = send(
+        &api,
+        Method::POST,
+        "/transactions/batch",
+        "type, client, tx, amount\ndeposit, 1, 1, 3.0\nwithdrawal, 1, 2, 5.0\ndeposit, 2, 3, abc\ndeposit, 2, 4, 1.0\n",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(outcomes[0], json!({"line": 2, "status": "applied"}));
+    assert_eq!(outcomes[1]["line"], 3);
+    assert_eq!(outcomes[1]["reason"], "InsufficientAvailableFunds");
+    assert_eq!(outcomes[2]["line"], 4);
+    assert_eq!(outcomes[2]["reason"], "UnableToParseAmount");
+    assert_eq!(outcomes[3], json!({"line": 5, "status": "applied"}));
+}
+
+#[tokio::test]
+async fn lists_locked_accounts_and_pending_disputes() {
+    let api = new_api();
+    send(
+        &api,
+        Method::POST,
+        "/transactions/batch",
+        "type, client, tx, amount\ndeposit, 1, 1, 3.0\ndeposit, 2, 2, 1.0\ndeposit, 2, 3, 1.0\ndispute, 1, 1,\nchargeback, 1, 1,\ndispute, 2, 3,\n",
+    )
+    .await;
+
+    let (status, locked) = send(&api, Method::GET, "/accounts/locked", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        locked,
+        json!([{"client": 1, "available": "0.0000", "held": "0.0000", "total": "0.0000", "locked": true, "status": "locked", "restrictions": ["chargeback"]}])
+    );
+
+    let (status, disputes) = send(&api, Method::GET, "/disputes", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        disputes,
+        json!([{"client_id": 2, "tx_id": 3, "state": "Open", "reopenings": 0, "history": ["Open"], "holds_funds": true, "outstanding": "1.0000", "charged_back": "0.0000"}])
+    );
+
+    let (status, dispute) = send(&api, Method::GET, "/disputes/1", "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(dispute["state"], "ChargedBack");
+    assert_eq!(dispute["history"], json!(["Open", "ChargedBack"]));
+
+    let (status, _) = send(&api, Method::GET, "/disputes/2", "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
@@ -1,7 +1,7 @@
 use csv::{ReaderBuilder, Trim};
 use fraction::Decimal;
 
-use crate::models::transaction::{Transaction, TransactionType};
+use crate::models::transaction::{Operation, Transaction, TransactionType};
 
 #[test]
 fn can_parse_one_deposit() {
@@ -12,10 +12,10 @@ fn can_parse_one_deposit() {
         .delimiter(b',')
         .from_reader(data.as_bytes());
     let deposit = reader.deserialize::<Transaction>().next().unwrap().unwrap();
-    assert!(matches!(deposit.tx_type, TransactionType::Deposit));
+    assert!(matches!(deposit.tx_type(), TransactionType::Deposit));
     assert_eq!(deposit.client_id, 1);
     assert_eq!(deposit.tx_id, 1);
-    assert_eq!(deposit.amount.unwrap(), Decimal::from(1.0));
+    assert_eq!(deposit.amount().unwrap(), Decimal::from(1.0));
 }
 
 #[test]
@@ -30,13 +30,13 @@ fn can_parse_one_deposit_and_one_withdrawal() {
 
     assert_eq!(deposit.client_id, 1);
     assert_eq!(deposit.tx_id, 1);
-    assert_eq!(deposit.amount.unwrap(), Decimal::from(1.0));
+    assert_eq!(deposit.amount().unwrap(), Decimal::from(1.0));
 
     let withdrawal = reader.deserialize::<Transaction>().next().unwrap().unwrap();
-    assert!(matches!(withdrawal.tx_type, TransactionType::Withdrawal));
+    assert!(matches!(withdrawal.tx_type(), TransactionType::Withdrawal));
     assert_eq!(withdrawal.client_id, 1);
     assert_eq!(withdrawal.tx_id, 4);
-    assert_eq!(withdrawal.amount.unwrap(), Decimal::from(1.5));
+    assert_eq!(withdrawal.amount().unwrap(), Decimal::from(1.5));
 }
 
 #[test]
@@ -59,21 +59,22 @@ fn can_parse_one_deposit_and_one_dispute_on_it() {
         .delimiter(b',')
         .from_reader(data.as_bytes());
     let deposit = reader.deserialize::<Transaction>().next().unwrap().unwrap();
-    assert!(matches!(deposit.tx_type, TransactionType::Deposit));
+    assert!(matches!(deposit.tx_type(), TransactionType::Deposit));
     assert_eq!(deposit.client_id, 1);
     assert_eq!(deposit.tx_id, 1);
-    assert_eq!(deposit.amount.unwrap(), Decimal::from(1.0));
+    assert_eq!(deposit.amount().unwrap(), Decimal::from(1.0));
 
     let dispute = reader.deserialize::<Transaction>().next().unwrap().unwrap();
-    assert!(matches!(dispute.tx_type, TransactionType::Dispute));
+    assert!(matches!(dispute.tx_type(), TransactionType::Dispute));
     assert_eq!(dispute.client_id, 1);
     assert_eq!(dispute.tx_id, 1);
-    assert_eq!(dispute.amount, None);
+    assert_eq!(dispute.amount(), None);
 }
 
 #[test]
 fn can_parse_one_deposit_and_one_dispute_and_one_resolve_on_it() {
-    let data = "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndispute, 1, 1, \nresolve, 1, 1, \n".to_string();
+    let data = "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndispute, 1, 1, \nresolve, 1, 1, \n"
+        .to_string();
     let mut reader = ReaderBuilder::new()
         .has_headers(true)
         .trim(Trim::All)
@@ -81,47 +82,130 @@ fn can_parse_one_deposit_and_one_dispute_and_one_resolve_on_it() {
         .from_reader(data.as_bytes());
 
     let deposit = reader.deserialize::<Transaction>().next().unwrap().unwrap();
-    assert!(matches!(deposit.tx_type, TransactionType::Deposit));
+    assert!(matches!(deposit.tx_type(), TransactionType::Deposit));
     assert_eq!(deposit.client_id, 1);
     assert_eq!(deposit.tx_id, 1);
-    assert_eq!(deposit.amount.unwrap(), Decimal::from(1.0));
+    assert_eq!(deposit.amount().unwrap(), Decimal::from(1.0));
 
     let dispute = reader.deserialize::<Transaction>().next().unwrap().unwrap();
-    assert!(matches!(dispute.tx_type, TransactionType::Dispute));
+    assert!(matches!(dispute.tx_type(), TransactionType::Dispute));
     assert_eq!(dispute.client_id, 1);
     assert_eq!(dispute.tx_id, 1);
-    assert_eq!(dispute.amount, None);
+    assert_eq!(dispute.amount(), None);
 
     let resolve = reader.deserialize::<Transaction>().next().unwrap().unwrap();
-    assert!(matches!(resolve.tx_type, TransactionType::Resolve));
+    assert!(matches!(resolve.tx_type(), TransactionType::Resolve));
     assert_eq!(resolve.client_id, 1);
     assert_eq!(resolve.tx_id, 1);
-    assert_eq!(resolve.amount, None);
+    assert_eq!(resolve.amount(), None);
 }
 
 #[test]
 fn can_parse_one_deposit_and_one_dispute_then_a_chargeback() {
-    let data = "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndispute, 1, 1, \nchargeback, 1, 1, \n".to_string();
+    let data =
+        "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndispute, 1, 1, \nchargeback, 1, 1, \n"
+            .to_string();
     let mut reader = ReaderBuilder::new()
         .has_headers(true)
         .trim(Trim::All)
         .delimiter(b',')
         .from_reader(data.as_bytes());
     let deposit = reader.deserialize::<Transaction>().next().unwrap().unwrap();
-    assert!(matches!(deposit.tx_type, TransactionType::Deposit));
+    assert!(matches!(deposit.tx_type(), TransactionType::Deposit));
     assert_eq!(deposit.client_id, 1);
     assert_eq!(deposit.tx_id, 1);
-    assert_eq!(deposit.amount.unwrap(), Decimal::from(1.0));
+    assert_eq!(deposit.amount().unwrap(), Decimal::from(1.0));
 
     let dispute = reader.deserialize::<Transaction>().next().unwrap().unwrap();
-    assert!(matches!(dispute.tx_type, TransactionType::Dispute));
+    assert!(matches!(dispute.tx_type(), TransactionType::Dispute));
     assert_eq!(dispute.client_id, 1);
     assert_eq!(dispute.tx_id, 1);
-    assert_eq!(dispute.amount, None);
+    assert_eq!(dispute.amount(), None);
 
     let chargeback = reader.deserialize::<Transaction>().next().unwrap().unwrap();
-    assert!(matches!(chargeback.tx_type, TransactionType::Chargeback));
+    assert!(matches!(chargeback.tx_type(), TransactionType::Chargeback));
     assert_eq!(chargeback.client_id, 1);
     assert_eq!(chargeback.tx_id, 1);
-    assert_eq!(chargeback.amount, None);
+    assert_eq!(chargeback.amount(), None);
+}
+
+#[test]
+fn cannot_parse_one_deposit_without_amount() {
+    let data = "type, client, tx, amount\ndeposit, 1, 5, \n".to_string();
+    let mut reader = ReaderBuilder::new()
+        .has_headers(true)
+        .trim(Trim::All)
+        .delimiter(b',')
+        .from_reader(data.as_bytes());
+    assert!(reader.deserialize::<Transaction>().next().unwrap().is_err())
+}
+
+#[test]
+fn can_parse_one_partial_dispute() {
+    let data = "type, client, tx, amount\ndispute, 1, 1, 1.0\n".to_string();
+    let mut reader = ReaderBuilder::new()
+        .has_headers(true)
+        .trim(Trim::All)
+        .delimiter(b',')
+        .from_reader(data.as_bytes());
+    let dispute = reader.deserialize::<Transaction>().next().unwrap().unwrap();
+    assert!(dispute.amount().is_none());
+    assert_eq!(dispute.disputed_amount, Some(Decimal::from(1)));
+}
+
+#[test]
+fn can_parse_one_transfer() {
+    let data = "type, client, tx, amount, to_client\ntransfer, 1, 1, 1.0, 2\n".to_string();
+    let mut reader = ReaderBuilder::new()
+        .has_headers(true)
+        .trim(Trim::All)
+        .delimiter(b',')
+        .from_reader(data.as_bytes());
+    let transfer = reader.deserialize::<Transaction>().next().unwrap().unwrap();
+    assert!(matches!(transfer.tx_type(), TransactionType::Transfer));
+    assert_eq!(transfer.client_id, 1);
+    assert!(matches!(transfer.operation, Operation::Transfer(_, 2)));
+    assert_eq!(transfer.amount().unwrap(), Decimal::from(1.0));
+}
+
+#[test]
+fn cannot_parse_one_transfer_without_recipient() {
+    let data = "type, client, tx, amount, to_client\ntransfer, 1, 1, 1.0, \n".to_string();
+    let mut reader = ReaderBuilder::new()
+        .has_headers(true)
+        .trim(Trim::All)
+        .delimiter(b',')
+        .from_reader(data.as_bytes());
+    assert!(reader.deserialize::<Transaction>().next().unwrap().is_err())
+}
+
+#[test]
+fn can_parse_an_authorization_and_its_capture() {
+    let data = "type, client, tx, amount\nauthorize, 1, 1, 1.0\ncapture, 1, 1,\n".to_string();
+    let mut reader = ReaderBuilder::new()
+        .has_headers(true)
+        .trim(Trim::All)
+        .delimiter(b',')
+        .from_reader(data.as_bytes());
+    let mut transactions = reader.deserialize::<Transaction>();
+    let authorization = transactions.next().unwrap().unwrap();
+    assert!(matches!(
+        authorization.tx_type(),
+        TransactionType::Authorize
+    ));
+    assert_eq!(authorization.amount().unwrap(), Decimal::from(1.0));
+    let capture = transactions.next().unwrap().unwrap();
+    assert!(matches!(capture.operation, Operation::Capture));
+    assert!(capture.amount().is_none());
+}
+
+#[test]
+fn cannot_parse_one_authorization_without_amount() {
+    let data = "type, client, tx, amount\nauthorize, 1, 1,\n".to_string();
+    let mut reader = ReaderBuilder::new()
+        .has_headers(true)
+        .trim(Trim::All)
+        .delimiter(b',')
+        .from_reader(data.as_bytes());
+    assert!(reader.deserialize::<Transaction>().next().unwrap().is_err())
 }
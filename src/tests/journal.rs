@@ -0,0 +1,257 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use fraction::Decimal;
+
+use crate::{
+    fees::FeeSchedule,
+    input_ingestion::{get_csv_reader, get_csv_reader_from},
+    journal::{read_journal, Journal, JournalError},
+    models::disputes::DisputeRules,
+    payments_engine::PaymentsEngine,
+};
+
+fn journal_path(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("tpe-{}-{}.jsonl", name, std::process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+// An output every write to fails, as a full disk would.
+pub struct UnwritableOutput;
+
+impl io::Write for UnwritableOutput {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::other("No space left"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn process_journaling(case: &str, path: &Path) -> PaymentsEngine {
+    let reader = get_csv_reader(format!("resources/case-inputs/{}.csv", case));
+    let mut pe = PaymentsEngine {
+        journal: Some(Journal::open(path.to_str().unwrap()).unwrap()),
+        ..Default::default()
+    };
+    pe.process_transactions_from(reader.unwrap()).unwrap();
+    pe
+}
+
+#[test]
+fn journals_only_applied_operations() {
+    // case7 has 9 records but the deposit arriving after the chargeback is not applied.
+    let path = journal_path("applied");
+    process_journaling("case7", &path);
+
+    let entries: Vec<_> = read_journal(fs::File::open(&path).unwrap())
+        .map(|entry| entry.unwrap())
+        .collect();
+    assert_eq!(entries.len(), 8);
+    assert_eq!(entries.first().unwrap().sequence, 1);
+    let last = entries.last().unwrap();
+    assert_eq!(last.sequence, 8);
+    assert_eq!(last.total, Decimal::from(4));
+    assert_eq!(last.held, Decimal::from(0));
+    assert!(last.locked);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn replay_rebuilds_the_same_state() {
+    let path = journal_path("replay");
+    let pe = process_journaling("case14", &path);
+
+    let mut replayed = PaymentsEngine::default();
+    replayed.replay(fs::File::open(&path).unwrap()).unwrap();
+    assert_eq!(replayed.accounts.len(), pe.accounts.len());
+    for (client_id, account) in pe.accounts.iter() {
+        let replayed_account = replayed.accounts.get(client_id).unwrap();
+        assert_eq!(replayed_account.total, account.total);
+        assert_eq!(replayed_account.held, account.held);
+        assert_eq!(replayed_account.is_locked(), account.is_locked());
+    }
+    let mut tx_ids: Vec<_> = pe.transactions.keys().collect();
+    let mut replayed_tx_ids: Vec<_> = replayed.transactions.keys().collect();
+    tx_ids.sort();
+    replayed_tx_ids.sort();
+    assert_eq!(replayed_tx_ids, tx_ids);
+    let mut disputed: Vec<_> = pe.disputes.keys().collect();
+    let mut replayed_disputed: Vec<_> = replayed.disputes.keys().collect();
+    disputed.sort();
+    replayed_disputed.sort();
+    assert_eq!(replayed_disputed, disputed);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn replay_rebuilds_the_balances_in_every_currency() {
+    let path = journal_path("currencies");
+    let pe = process_journaling("case18", &path);
+
+    let mut replayed = PaymentsEngine::default();
+    replayed.replay(fs::File::open(&path).unwrap()).unwrap();
+    for (client_id, account) in pe.accounts.iter() {
+        let replayed_account = replayed.accounts.get(client_id).unwrap();
+        assert_eq!(replayed_account.balances, account.balances);
+        assert_eq!(replayed_account.total, account.total);
+    }
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn journal_keeps_appending_after_a_replay() {
+    // Recovering after a crash: rebuild from the journal and keep journaling on it.
+    let path = journal_path("append");
+    process_journaling("case1", &path);
+    let mut pe = PaymentsEngine::default();
+    pe.replay(fs::File::open(&path).unwrap()).unwrap();
+    pe.journal = Some(Journal::open(path.to_str().unwrap()).unwrap());
+    let data = "type, client, tx, amount\ndeposit, 1, 10, 1.0\nwithdrawal, 1, 11, 0.5\n";
+    pe.process_transactions_from(get_csv_reader_from(data.as_bytes()))
+        .unwrap();
+
+    let sequences: Vec<u64> = read_journal(fs::File::open(&path).unwrap())
+        .map(|entry| entry.unwrap().sequence)
+        .collect();
+    assert_eq!(sequences, (1..=8).collect::<Vec<u64>>());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn replay_detects_a_tampered_journal() {
+    let path = journal_path("tampered");
+    process_journaling("case3", &path);
+    let journal = fs::read_to_string(&path).unwrap();
+    let tampered = journal.replacen("\"locked\":false", "\"locked\":true", 2);
+    let replayed = PaymentsEngine::default().replay(tampered.as_bytes());
+    assert!(matches!(replayed, Err(JournalError::Diverged(1))));
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn replay_rebuilds_the_same_accounts_despite_rejected_transactions() {
+    // A rejected withdrawal of a new client is not journaled, so it must not leave an account behind either.
+    let path = journal_path("rejected");
+    let mut pe = PaymentsEngine {
+        journal: Some(Journal::open(path.to_str().unwrap()).unwrap()),
+        ..Default::default()
+    };
+    let data = "type, client, tx, amount\ndeposit, 1, 1, 1.0\nwithdrawal, 2, 2, 1.0\n";
+    pe.process_transactions_from(get_csv_reader_from(data.as_bytes()))
+        .unwrap();
+    assert!(!pe.accounts.contains_key(&2));
+
+    let mut replayed = PaymentsEngine::default();
+    replayed.replay(fs::File::open(&path).unwrap()).unwrap();
+    assert_eq!(
+        serde_json::to_string(&replayed.snapshot().unwrap()).unwrap(),
+        serde_json::to_string(&pe.snapshot().unwrap()).unwrap(),
+    );
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn replay_rebuilds_the_same_accounts_despite_ignored_transactions() {
+    // A resolve of a new client without any dispute is ignored, and leaves no account behind either.
+    let path = journal_path("ignored");
+    let mut pe = PaymentsEngine {
+        journal: Some(Journal::open(path.to_str().unwrap()).unwrap()),
+        ..Default::default()
+    };
+    let data = "type, client, tx, amount\ndeposit, 1, 1, 1.0\nresolve, 8, 1,\n";
+    pe.process_transactions_from(get_csv_reader_from(data.as_bytes()))
+        .unwrap();
+    assert!(!pe.accounts.contains_key(&8));
+
+    let mut replayed = PaymentsEngine::default();
+    replayed.replay(fs::File::open(&path).unwrap()).unwrap();
+    assert_eq!(
+        serde_json::to_string(&replayed.snapshot().unwrap()).unwrap(),
+        serde_json::to_string(&pe.snapshot().unwrap()).unwrap(),
+    );
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn replay_rebuilds_the_fees_and_the_house_account_without_the_schedule() {
+    let path = journal_path("fees");
+    let reader = get_csv_reader("resources/case-inputs/case20.csv".to_owned());
+    let mut pe = PaymentsEngine {
+        fees: FeeSchedule::open("resources/fees.csv", 9).unwrap(),
+        journal: Some(Journal::open(path.to_str().unwrap()).unwrap()),
+        ..Default::default()
+    };
+    pe.process_transactions_from(reader.unwrap()).unwrap();
+
+    // Every fee collected is journaled on the house account.
+    let house_entries = read_journal(fs::File::open(&path).unwrap())
+        .map(|entry| entry.unwrap())
+        .filter(|entry| entry.client_id == 9)
+        .count();
+    assert_eq!(house_entries, 7);
+    let mut replayed = PaymentsEngine::default();
+    replayed.replay(fs::File::open(&path).unwrap()).unwrap();
+    assert_eq!(
+        serde_json::to_string(&replayed.snapshot().unwrap()).unwrap(),
+        serde_json::to_string(&pe.snapshot().unwrap()).unwrap(),
+    );
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn replay_moves_the_clock_as_the_rejected_transactions_did() {
+    // The rejected withdrawal is not journaled, but it moved the clock past the expiry of the dispute.
+    let path = journal_path("clock");
+    let mut pe = PaymentsEngine {
+        journal: Some(Journal::open(path.to_str().unwrap()).unwrap()),
+        dispute_rules: DisputeRules {
+            expiry: Some(24 * 60 * 60),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let data = "type, client, tx, amount, timestamp
+deposit, 1, 1, 10.0, 0
+dispute, 1, 1, , 10
+withdrawal, 2, 2, 1.0, 100000000
+";
+    pe.process_transactions_from(get_csv_reader_from(data.as_bytes()))
+        .unwrap();
+    assert_eq!(pe.accounts.get(&1).unwrap().held, Decimal::from(0));
+
+    let mut replayed = PaymentsEngine {
+        dispute_rules: pe.dispute_rules,
+        ..Default::default()
+    };
+    replayed.replay(fs::File::open(&path).unwrap()).unwrap();
+    assert_eq!(replayed.clock, pe.clock);
+    assert_eq!(
+        serde_json::to_string(&replayed.snapshot().unwrap()).unwrap(),
+        serde_json::to_string(&pe.snapshot().unwrap()).unwrap(),
+    );
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn stops_processing_once_the_journal_failed() {
+    // Nothing is applied after the first failure, so the journal has no gap replays can't tell.
+    let mut pe = PaymentsEngine {
+        journal: Some(Journal::new(UnwritableOutput)),
+        ..Default::default()
+    };
+    let data = "type, client, tx, amount\ndeposit, 1, 1, 1.0\n";
+    assert!(pe
+        .process_transactions_from(get_csv_reader_from(data.as_bytes()))
+        .is_err());
+    assert!(pe.journal.as_ref().unwrap().check().is_err());
+    let data = "type, client, tx, amount\ndeposit, 2, 2, 1.0\n";
+    assert!(pe
+        .process_transactions_from(get_csv_reader_from(data.as_bytes()))
+        .is_err());
+    assert!(!pe.accounts.contains_key(&2));
+}
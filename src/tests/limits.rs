@@ -0,0 +1,21 @@
+use fraction::Decimal;
+
+use crate::limits::{CreditLimits, LimitsError};
+
+#[test]
+fn reads_the_credit_limit_of_every_client() {
+    let limits = CreditLimits::open("resources/limits.csv").unwrap();
+    assert_eq!(limits.of(1), Decimal::from(50));
+    assert_eq!(limits.of(2), Decimal::from(0));
+    // Clients without a limit have none.
+    assert_eq!(limits.of(3), Decimal::from(0));
+}
+
+#[test]
+fn refuses_negative_limits() {
+    let data = "client, credit_limit\n1, 50.0\n2, -10.0\n";
+    assert!(matches!(
+        CreditLimits::read_from(data.as_bytes()),
+        Err(LimitsError::InvalidLimit(3))
+    ));
+}
@@ -1,4 +1,44 @@
+pub mod admin;
+pub mod admin_server;
+pub mod blocklist;
+pub mod fees;
+pub mod http_api;
 pub mod input_ingestion;
+pub mod journal;
+pub mod limits;
+pub mod money;
 
 pub mod account;
 pub mod payments_engine;
+pub mod rates;
+pub mod ratios;
+pub mod rejections;
+pub mod reports;
+pub mod rules;
+pub mod server;
+pub mod sharded_engine;
+pub mod snapshot;
+pub mod storage;
+
+use crate::{
+    input_ingestion::get_csv_reader_from,
+    models::transaction::Transaction,
+    payments_engine::{deserialize_transaction, PaymentsEngine},
+};
+
+// The transaction in a row of the input format, which can leave out the trailing columns,
+// up to the partner and then the currency.
+pub fn transaction(row: &str) -> Transaction {
+    let data = format!("type, client, tx, amount, partner, currency\n{}\n", row);
+    let mut reader = get_csv_reader_from(data.as_bytes());
+    let headers = reader.headers().unwrap().clone();
+    let record = reader.records().next().unwrap().unwrap();
+    deserialize_transaction(&record, Some(&headers)).unwrap()
+}
+
+// The default engine, with what the tests of a module look at (like its rules or its blocklist) set up on it.
+pub fn engine_with(set_up: fn(&mut PaymentsEngine)) -> PaymentsEngine {
+    let mut pe = PaymentsEngine::default();
+    set_up(&mut pe);
+    pe
+}
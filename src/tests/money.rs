@@ -0,0 +1,55 @@
+use fraction::Decimal;
+
+use crate::models::money::Rounding;
+
+fn amount(value: &str) -> Decimal {
+    value.parse().unwrap()
+}
+
+#[test]
+fn renders_with_every_rounding_rule() {
+    let cases = [
+        (
+            "1.23456",
+            ["1.2345", "1.2346", "1.2346", "1.2345", "1.2346"],
+        ),
+        (
+            "1.23445",
+            ["1.2344", "1.2345", "1.2344", "1.2344", "1.2345"],
+        ),
+        (
+            "1.23455",
+            ["1.2345", "1.2346", "1.2346", "1.2345", "1.2346"],
+        ),
+        (
+            "-1.23456",
+            ["-1.2345", "-1.2346", "-1.2346", "-1.2346", "-1.2345"],
+        ),
+        ("2", ["2.0000", "2.0000", "2.0000", "2.0000", "2.0000"]),
+    ];
+    let rules = [
+        Rounding::Truncate,
+        Rounding::HalfUp,
+        Rounding::HalfEven,
+        Rounding::Floor,
+        Rounding::Ceil,
+    ];
+    for (value, expected) in cases {
+        for (rule, expected) in rules.iter().zip(expected) {
+            assert_eq!(
+                rule.render(&amount(value)),
+                expected,
+                "{} with {:?}",
+                value,
+                rule
+            );
+        }
+    }
+}
+
+#[test]
+fn parses_the_rounding_rules() {
+    assert_eq!("half-even".parse::<Rounding>().unwrap(), Rounding::HalfEven);
+    assert_eq!("truncate".parse::<Rounding>().unwrap(), Rounding::default());
+    assert!("nearest".parse::<Rounding>().is_err());
+}
@@ -1,7 +1,24 @@
+use std::io::{Cursor, Read};
+
 use csv::{ReaderBuilder, Trim};
 use fraction::Decimal;
 
-use crate::{input_ingestion::get_csv_reader, payments_engine::PaymentsEngine};
+use crate::{
+    fees::FeeSchedule,
+    input_ingestion::{get_csv_reader, get_csv_reader_from},
+    limits::CreditLimits,
+    models::{
+        account::render_accounts,
+        disputes::{DisputeRules, DisputeState, WithdrawalDisputePolicy},
+        money::Rounding,
+        transaction::TransactionProcessingError,
+    },
+    payments_engine::PaymentsEngine,
+    rates::RateTable,
+    rejections::{Rejection, RejectionReason},
+    storage::AccountStore,
+    tests::{engine_with, transaction},
+};
 
 #[test]
 fn can_process_input_as_stream_of_bytes() {
@@ -13,13 +30,26 @@ fn can_process_input_as_stream_of_bytes() {
         .delimiter(b',')
         .from_reader(data.as_bytes());
     let mut pe = PaymentsEngine::default();
-    pe.process_transactions_streaming_input(reader).unwrap();
+    pe.process_transactions_from(reader).unwrap();
     let account = pe.accounts.get(&1).unwrap();
     assert_eq!(account.get_available(), Decimal::from(1));
     let account = pe.accounts.get(&2).unwrap();
     assert_eq!(account.get_available(), Decimal::from(2))
 }
 
+#[test]
+fn can_process_input_from_any_reader() {
+    // The input can come chained from different sources, as if it were a pipe or a socket.
+    let headers = Cursor::new("type, client, tx, amount\n");
+    let rows = Cursor::new("deposit, 1, 1, 3.0\nwithdrawal, 1, 2, 1.0\n");
+    let reader = get_csv_reader_from(headers.chain(rows));
+    let mut pe = PaymentsEngine::default();
+    pe.process_transactions_from(reader).unwrap();
+    let account = pe.accounts.get(&1).unwrap();
+    assert_eq!(account.get_available(), Decimal::from(2));
+    assert_eq!(account.total, Decimal::from(2));
+}
+
 #[test]
 fn case1() {
     // 3 deposits in 2 accounts then 2 withdrawals
@@ -33,7 +63,7 @@ fn case1() {
     assert_eq!(account.get_available(), expected_available_and_total);
     assert_eq!(account.total, expected_available_and_total);
     assert_eq!(account.held, Decimal::from(0));
-    assert!(!account.locked);
+    assert!(!account.is_locked());
 }
 
 #[test]
@@ -46,7 +76,7 @@ fn case2() {
     assert_eq!(account.get_available(), Decimal::from(1));
     assert_eq!(account.total, Decimal::from(5));
     assert_eq!(account.held, Decimal::from(4));
-    assert!(!account.locked)
+    assert!(!account.is_locked())
 }
 
 #[test]
@@ -59,7 +89,7 @@ fn case3() {
     assert_eq!(account.get_available(), Decimal::from(5));
     assert_eq!(account.total, Decimal::from(5));
     assert_eq!(account.held, Decimal::from(0));
-    assert!(!account.locked);
+    assert!(!account.is_locked());
 }
 
 #[test]
@@ -73,7 +103,7 @@ fn case4() {
     assert_eq!(account.get_available(), Decimal::from(1));
     assert_eq!(account.total, Decimal::from(5));
     assert_eq!(account.held, Decimal::from(4));
-    assert!(!account.locked);
+    assert!(!account.is_locked());
 }
 
 #[test]
@@ -87,7 +117,7 @@ fn case5() {
     assert_eq!(account.get_available(), Decimal::from(9));
     assert_eq!(account.total, Decimal::from(9));
     assert_eq!(account.held, Decimal::from(0));
-    assert!(!account.locked);
+    assert!(!account.is_locked());
 }
 
 #[test]
@@ -101,7 +131,7 @@ fn case6() {
     assert_eq!(account.get_available(), Decimal::from(8));
     assert_eq!(account.total, Decimal::from(8));
     assert_eq!(account.held, Decimal::from(0));
-    assert!(!account.locked);
+    assert!(!account.is_locked());
 }
 
 #[test]
@@ -116,7 +146,7 @@ fn case7() {
     assert_eq!(account.get_available(), Decimal::from(4));
     assert_eq!(account.total, Decimal::from(4));
     assert_eq!(account.held, Decimal::from(0));
-    assert!(account.locked);
+    assert!(account.is_locked());
 }
 
 #[test]
@@ -130,7 +160,7 @@ fn case8() {
     assert_eq!(account.get_available(), Decimal::from(1));
     assert_eq!(account.total, Decimal::from(5));
     assert_eq!(account.held, Decimal::from(4));
-    assert!(!account.locked);
+    assert!(!account.is_locked());
 }
 
 #[test]
@@ -145,28 +175,45 @@ fn case9() {
     assert_eq!(account.get_available(), Decimal::from(4));
     assert_eq!(account.total, Decimal::from(4));
     assert_eq!(account.held, Decimal::from(0));
-    assert!(!account.locked);
+    assert!(!account.is_locked());
 
     let account = pe.accounts.get(&2).unwrap();
     assert_eq!(account.get_available(), Decimal::from(3));
     assert_eq!(account.total, Decimal::from(3));
     assert_eq!(account.held, Decimal::from(0));
-    assert!(!account.locked);
+    assert!(!account.is_locked());
 }
 
 #[test]
 fn case10() {
     // Two deposits to account 1 and one withdrawal followed by a dispute to that withdrawal and a new deposit.
-    // The dispute on withdrawal has its value held.
+    // By default, nothing is held for the dispute on withdrawal.
     let reader = get_csv_reader("resources/case-inputs/case10.csv".to_owned());
     let mut pe = PaymentsEngine::default();
     pe.process_transactions_from(reader.unwrap()).unwrap();
 
+    let account = pe.accounts.get(&1).unwrap();
+    assert_eq!(account.get_available(), Decimal::from(3));
+    assert_eq!(account.total, Decimal::from(3));
+    assert_eq!(account.held, Decimal::from(0));
+    assert!(!account.is_locked());
+
+    // Unless it's asked for, having the dispute on withdrawal hold its value.
+    let reader = get_csv_reader("resources/case-inputs/case10.csv".to_owned());
+    let mut pe = PaymentsEngine {
+        dispute_rules: DisputeRules {
+            withdrawals: WithdrawalDisputePolicy::HoldAsNegativeAvailable,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    pe.process_transactions_from(reader.unwrap()).unwrap();
+
     let account = pe.accounts.get(&1).unwrap();
     assert_eq!(account.get_available(), Decimal::from(2));
     assert_eq!(account.total, Decimal::from(3));
     assert_eq!(account.held, Decimal::from(1));
-    assert!(!account.locked);
+    assert!(!account.is_locked());
 }
 
 #[test]
@@ -181,7 +228,7 @@ fn case11() {
     assert_eq!(account.get_available(), Decimal::from(1));
     assert_eq!(account.total, Decimal::from(1));
     assert_eq!(account.held, Decimal::from(0));
-    assert!(account.locked);
+    assert!(account.is_locked());
 }
 
 #[test]
@@ -196,7 +243,7 @@ fn case12() {
     assert_eq!(account.get_available(), Decimal::from(3));
     assert_eq!(account.total, Decimal::from(3));
     assert_eq!(account.held, Decimal::from(0));
-    assert!(account.locked);
+    assert!(account.is_locked());
 }
 
 #[test]
@@ -209,7 +256,7 @@ fn case13() {
     assert_eq!(account.get_available(), Decimal::from(3.5));
     assert_eq!(account.total, Decimal::from(3.5));
     assert_eq!(account.held, Decimal::from(0));
-    assert!(account.locked);
+    assert!(account.is_locked());
 }
 
 #[test]
@@ -224,13 +271,13 @@ fn case14() {
     assert_eq!(account.get_available(), Decimal::from(5));
     assert_eq!(account.total, Decimal::from(6));
     assert_eq!(account.held, Decimal::from(1));
-    assert!(!account.locked);
+    assert!(!account.is_locked());
 
     let account = pe.accounts.get(&2).unwrap();
     assert_eq!(account.get_available(), Decimal::from(4));
     assert_eq!(account.total, Decimal::from(4));
     assert_eq!(account.held, Decimal::from(0));
-    assert!(!account.locked);
+    assert!(!account.is_locked());
 }
 
 #[test]
@@ -247,5 +294,732 @@ fn case15() {
     );
     assert_eq!(account.total, Decimal::from("0.9999999999999999999"));
     assert_eq!(account.held, Decimal::from(0));
-    assert!(!account.locked);
+    assert!(!account.is_locked());
+}
+
+#[test]
+fn case16() {
+    // A dispute on account 1 and then a resolve with the same disputed tx id but set for a different account.
+    // The resolves aimed to an account that refer to transactions that happened in a different account are ignored.
+    let reader = get_csv_reader("resources/case-inputs/case16.csv".to_owned());
+    let mut pe = PaymentsEngine::default();
+    pe.process_transactions_from(reader.unwrap()).unwrap();
+
+    let account = pe.accounts.get(&1).unwrap();
+    assert_eq!(account.get_available(), Decimal::from(0));
+    assert_eq!(account.total, Decimal::from(3));
+    assert_eq!(account.held, Decimal::from(3));
+    assert!(!account.is_locked());
+
+    let account = pe.accounts.get(&2).unwrap();
+    assert_eq!(account.get_available(), Decimal::from(3));
+    assert_eq!(account.total, Decimal::from(3));
+    assert_eq!(account.held, Decimal::from(0));
+    assert!(!account.is_locked());
+}
+
+#[test]
+fn case17() {
+    // A dispute resolved, opened again and charged back, and another one resolved.
+    // Disputes are kept with every state they went through.
+    let reader = get_csv_reader("resources/case-inputs/case17.csv".to_owned());
+    let mut pe = PaymentsEngine::default();
+    pe.process_transactions_from(reader.unwrap()).unwrap();
+
+    let account = pe.accounts.get(&1).unwrap();
+    assert_eq!(account.get_available(), Decimal::from(2));
+    assert_eq!(account.total, Decimal::from(2));
+    assert_eq!(account.held, Decimal::from(0));
+    assert!(account.is_locked());
+
+    let dispute = pe.dispute_history(1).unwrap().unwrap();
+    assert_eq!(dispute.state, DisputeState::ChargedBack);
+    assert_eq!(dispute.reopenings, 1);
+    assert_eq!(
+        dispute.history,
+        vec![
+            DisputeState::Open,
+            DisputeState::Resolved,
+            DisputeState::Open,
+            DisputeState::ChargedBack
+        ]
+    );
+
+    let dispute = pe.dispute_history(2).unwrap().unwrap();
+    assert_eq!(dispute.state, DisputeState::Resolved);
+    assert_eq!(dispute.reopenings, 0);
+    assert!(pe.pending_disputes().unwrap().is_empty());
+}
+
+#[test]
+fn case18() {
+    // Deposits and withdrawals in several currencies (and in the default one, without currency).
+    // Withdrawals only use the funds in their own currency, disputes and chargebacks apply in the currency of the disputed transaction.
+    let reader = get_csv_reader("resources/case-inputs/case18.csv".to_owned());
+    let mut pe = PaymentsEngine::default();
+    let mut rejections = vec![];
+    pe.process_transactions_reporting_to(reader.unwrap(), &mut rejections)
+        .unwrap();
+
+    let account = pe.accounts.get(&1).unwrap();
+    assert_eq!(account.total, Decimal::from(2));
+    assert_eq!(account.held, Decimal::from(0));
+    let usd = account.balance_in(Some("USD"));
+    assert_eq!(usd.get_available(), Decimal::from(-4));
+    assert_eq!(usd.held, Decimal::from(10));
+    assert_eq!(usd.total, Decimal::from(6));
+    let eur = account.balance_in(Some("EUR"));
+    assert_eq!(eur.total, Decimal::from(5));
+    assert!(!account.is_locked());
+
+    let account = pe.accounts.get(&2).unwrap();
+    assert_eq!(account.balance_in(Some("BRL")).total, Decimal::from(0));
+    assert!(account.is_locked());
+
+    // Only the withdrawal of EUR 6.0 exceeded the available funds in its currency.
+    assert_eq!(rejections.len(), 1);
+    assert_eq!(rejections[0].line, 5);
+    assert_eq!(
+        rejections[0].reason,
+        RejectionReason::InsufficientAvailableFunds
+    );
+
+    assert_eq!(
+        render_accounts(&pe.accounts.all_accounts().unwrap(), pe.rounding),
+        vec![
+            "client, available, held, total, locked, currency",
+            "1, 2.0000, 0.0000, 2.0000, false, ",
+            "1, 5.0000, 0.0000, 5.0000, false, EUR",
+            "1, -4.0000, 10.0000, 6.0000, false, USD",
+            "2, 0.0000, 0.0000, 0.0000, true, BRL",
+            "3, 1.0000, 0.0000, 1.0000, false, ",
+        ]
+    );
+}
+
+#[test]
+fn case19() {
+    // Conversions between the currencies of the same client, with the rates effective for their transaction IDs.
+    // The converted amounts are kept without loss and only rounded when rendered.
+    let reader = get_csv_reader("resources/case-inputs/case19.csv".to_owned());
+    let mut pe = PaymentsEngine {
+        rates: RateTable::open("resources/rates.csv").unwrap(),
+        ..Default::default()
+    };
+    let mut rejections = vec![];
+    pe.process_transactions_reporting_to(reader.unwrap(), &mut rejections)
+        .unwrap();
+
+    // USD 100 - 10 + 3 / 0.9 (the inverse of the rate from USD to EUR) - 10 = 250 / 3, exactly.
+    let account = pe.accounts.get(&1).unwrap();
+    let usd = account.balance_in(Some("USD"));
+    assert_eq!(usd.total * Decimal::from(3), Decimal::from(250));
+    // EUR 10 * 0.9 - 3 + 10 * 0.8 (the rate effective from tx 5)
+    assert_eq!(account.balance_in(Some("EUR")).total, Decimal::from(14));
+
+    let account = pe.accounts.get(&2).unwrap();
+    assert_eq!(account.balance_in(Some("EUR")).total, Decimal::from(9));
+    assert_eq!(account.balance_in(Some("GBP")).total, Decimal::from(0));
+
+    let reasons: Vec<(u64, RejectionReason)> = rejections
+        .iter()
+        .map(|rejection| (rejection.line, rejection.reason))
+        .collect();
+    assert_eq!(
+        reasons,
+        vec![
+            (5, RejectionReason::InconsistentOperation),
+            (7, RejectionReason::InsufficientAvailableFunds),
+            (8, RejectionReason::RateNotFound),
+            (9, RejectionReason::MissingCurrency),
+        ]
+    );
+
+    pe.rounding = Rounding::Ceil;
+    assert_eq!(
+        render_accounts(&pe.accounts.all_accounts().unwrap(), pe.rounding),
+        vec![
+            "client, available, held, total, locked, currency",
+            "1, 14.0000, 0.0000, 14.0000, false, EUR",
+            "1, 83.3334, 0.0000, 83.3334, false, USD",
+            "2, 9.0000, 0.0000, 9.0000, false, EUR",
+            "2, 0.0000, 0.0000, 0.0000, false, GBP",
+        ]
+    );
+}
+
+#[test]
+fn case20() {
+    // Deposits and withdrawals charged with the fees of the schedule, collected into the house account 9.
+    // Charging back a deposit gives its fee back.
+    let reader = get_csv_reader("resources/case-inputs/case20.csv".to_owned());
+    let mut pe = PaymentsEngine {
+        fees: FeeSchedule::open("resources/fees.csv", 9).unwrap(),
+        ..Default::default()
+    };
+    let mut rejections = vec![];
+    pe.process_transactions_reporting_to(reader.unwrap(), &mut rejections)
+        .unwrap();
+
+    // 50 - 0.1, then 10 + 0.6 withdrawn, then 50 charged back and its 0.1 fee given back.
+    let account = pe.accounts.get(&1).unwrap();
+    assert_eq!(account.total, Decimal::from(-10.6));
+    assert!(account.is_locked());
+    // Client 2 doesn't pay for withdrawals.
+    assert_eq!(pe.accounts.get(&2).unwrap().total, Decimal::from(49.9));
+    // 0.5% for withdrawals from 100.
+    assert_eq!(pe.accounts.get(&3).unwrap().total, Decimal::from(98.9));
+    // The whole deposit went to the fee.
+    assert_eq!(pe.accounts.get(&4).unwrap().total, Decimal::from(0));
+    // 0.1 + 0.6 + 0.1 + 0.1 + 1 - 0.1 (given back) + 0.05
+    assert_eq!(pe.accounts.get(&9).unwrap().total, Decimal::from(1.85));
+
+    // The withdrawal of 39 plus its 0.89 fee exceeded the 39.3 available.
+    assert_eq!(rejections.len(), 1);
+    assert_eq!(rejections[0].line, 4);
+    assert_eq!(
+        rejections[0].reason,
+        RejectionReason::InsufficientAvailableFunds
+    );
+
+    // What the clients paid is what the house account got.
+    let total: Decimal = pe.accounts.values().map(|account| account.total).sum();
+    assert_eq!(
+        total,
+        Decimal::from(50 + 200 - 150 + 300 - 200 - 10 - 50) + Decimal::from(0.05)
+    );
+}
+
+#[test]
+fn reports_the_withdrawal_with_its_fee_as_what_is_missing() {
+    let mut pe = engine_with(|pe| pe.fees = FeeSchedule::open("resources/fees.csv", 9).unwrap());
+    pe.process(transaction("deposit, 1, 1, 10.1")).unwrap();
+    // 10 available, and 10 plus the 0.6 fee needed.
+    assert!(matches!(
+        pe.process(transaction("withdrawal, 1, 2, 10.0")),
+        Err(TransactionProcessingError::InsufficientAvailableFunds((2, amount)))
+            if amount == Decimal::from(10.6)
+    ));
+}
+
+// Disputes on withdrawals, charged back (client 1), resolved (client 2) and still pending (client 3).
+fn case21_with(policy: WithdrawalDisputePolicy) -> (PaymentsEngine, Vec<Rejection>) {
+    let reader = get_csv_reader("resources/case-inputs/case21.csv".to_owned());
+    let mut pe = PaymentsEngine {
+        dispute_rules: DisputeRules {
+            withdrawals: policy,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut rejections = vec![];
+    pe.process_transactions_reporting_to(reader.unwrap(), &mut rejections)
+        .unwrap();
+    (pe, rejections)
+}
+
+#[test]
+fn case21_holding_disputed_withdrawals() {
+    let (pe, rejections) = case21_with(WithdrawalDisputePolicy::HoldAsNegativeAvailable);
+    assert!(rejections.is_empty());
+
+    let account = pe.accounts.get(&1).unwrap();
+    assert_eq!(account.total, Decimal::from(10));
+    assert_eq!(account.held, Decimal::from(0));
+    assert!(account.is_locked());
+    let account = pe.accounts.get(&2).unwrap();
+    assert_eq!(account.total, Decimal::from(6));
+    assert_eq!(account.held, Decimal::from(0));
+    // The withdrawn amount is held on top of what is left.
+    let account = pe.accounts.get(&3).unwrap();
+    assert_eq!(account.get_available(), Decimal::from(2));
+    assert_eq!(account.held, Decimal::from(4));
+}
+
+#[test]
+fn case21_crediting_disputed_withdrawals_on_chargeback_only() {
+    let (pe, rejections) = case21_with(WithdrawalDisputePolicy::CreditOnChargebackOnly);
+    assert!(rejections.is_empty());
+
+    let account = pe.accounts.get(&1).unwrap();
+    assert_eq!(account.total, Decimal::from(10));
+    assert_eq!(account.held, Decimal::from(0));
+    assert!(account.is_locked());
+    let account = pe.accounts.get(&2).unwrap();
+    assert_eq!(account.total, Decimal::from(6));
+    assert_eq!(account.held, Decimal::from(0));
+    // Nothing is held while disputed.
+    let account = pe.accounts.get(&3).unwrap();
+    assert_eq!(account.get_available(), Decimal::from(6));
+    assert_eq!(account.held, Decimal::from(0));
+    assert!(pe.disputes.get(&6).unwrap().is_open());
+}
+
+#[test]
+fn case21_rejecting_disputed_withdrawals() {
+    let (pe, rejections) = case21_with(WithdrawalDisputePolicy::Reject);
+    let rejected: Vec<(u64, RejectionReason)> = rejections
+        .iter()
+        .map(|rejection| (rejection.line, rejection.reason))
+        .collect();
+    assert_eq!(
+        rejected,
+        vec![
+            (4, RejectionReason::WithdrawalDisputeRefused),
+            (8, RejectionReason::WithdrawalDisputeRefused),
+            (12, RejectionReason::WithdrawalDisputeRefused),
+        ]
+    );
+
+    // Without a dispute, the chargeback and the resolve are ignored.
+    for client in 1..=3 {
+        let account = pe.accounts.get(&client).unwrap();
+        assert_eq!(account.total, Decimal::from(6));
+        assert_eq!(account.held, Decimal::from(0));
+        assert!(!account.is_locked());
+    }
+    assert!(pe.disputes.is_empty());
+}
+
+#[test]
+fn case22() {
+    // Disputes, resolves and chargebacks on part of a deposit, the engine keeping track of what is in dispute.
+    let reader = get_csv_reader("resources/case-inputs/case22.csv".to_owned());
+    let mut pe = PaymentsEngine::default();
+    let mut rejections = vec![];
+    pe.process_transactions_reporting_to(reader.unwrap(), &mut rejections)
+        .unwrap();
+
+    // 30 disputed, 10 resolved and the remaining 20 charged back.
+    let account = pe.accounts.get(&1).unwrap();
+    assert_eq!(account.total, Decimal::from(80));
+    assert_eq!(account.held, Decimal::from(0));
+    assert!(account.is_locked());
+    let dispute = pe.disputes.get(&1).unwrap();
+    assert_eq!(
+        dispute.history,
+        vec![DisputeState::Open, DisputeState::ChargedBack]
+    );
+    assert_eq!(dispute.charged_back, Decimal::from(20));
+
+    // 20 disputed and resolved, then all of it disputed again and 15 charged back, leaving 35 in dispute.
+    let account = pe.accounts.get(&2).unwrap();
+    assert_eq!(account.total, Decimal::from(35));
+    assert_eq!(account.held, Decimal::from(35));
+    assert!(account.is_locked());
+    let dispute = pe.disputes.get(&2).unwrap();
+    assert!(dispute.is_open());
+    assert_eq!(dispute.outstanding, Some(Decimal::from(35)));
+    assert_eq!(dispute.charged_back, Decimal::from(15));
+
+    let rejected: Vec<(u64, RejectionReason)> = rejections
+        .iter()
+        .map(|rejection| (rejection.line, rejection.reason))
+        .collect();
+    assert_eq!(
+        rejected,
+        vec![
+            (5, RejectionReason::ExceedsDisputedAmount),
+            (8, RejectionReason::ExceedsDisputedAmount),
+        ]
+    );
+}
+
+#[test]
+fn settles_the_rest_of_a_partly_charged_back_dispute() {
+    // Dispute part, resolve part, charge back part and then the rest, or resolve the rest, without
+    // `--settle-disputes-when-locked`. Other disputes are still rejected on the locked account.
+    let data = "type, client, tx, amount
+deposit, 1, 1, 100.0
+deposit, 1, 2, 10.0
+dispute, 1, 1, 60.0
+resolve, 1, 1, 20.0
+chargeback, 1, 1, 10.0
+chargeback, 1, 1,
+dispute, 1, 2,
+deposit, 2, 3, 100.0
+dispute, 2, 3, 60.0
+chargeback, 2, 3, 10.0
+resolve, 2, 3,
+";
+    let mut pe = PaymentsEngine::default();
+    let mut rejections: Vec<Rejection> = vec![];
+    pe.process_transactions_reporting_to(get_csv_reader_from(data.as_bytes()), &mut rejections)
+        .unwrap();
+
+    // 10 and then the 30 left charged back.
+    let account = pe.accounts.get(&1).unwrap();
+    assert_eq!(account.total, Decimal::from(70));
+    assert_eq!(account.held, Decimal::from(0));
+    assert!(account.is_locked());
+    let dispute = pe.disputes.get(&1).unwrap();
+    assert_eq!(dispute.state, DisputeState::ChargedBack);
+    assert_eq!(dispute.charged_back, Decimal::from(40));
+    // 10 charged back and the 50 left resolved.
+    let account = pe.accounts.get(&2).unwrap();
+    assert_eq!(account.total, Decimal::from(90));
+    assert_eq!(account.held, Decimal::from(0));
+    assert_eq!(pe.disputes.get(&3).unwrap().state, DisputeState::Resolved);
+
+    let rejected: Vec<(u64, RejectionReason)> = rejections
+        .iter()
+        .map(|rejection| (rejection.line, rejection.reason))
+        .collect();
+    assert_eq!(rejected, vec![(8, RejectionReason::TargetAccountLocked)]);
+}
+
+#[test]
+fn case23() {
+    // Deposits disputed too late, and disputes left pending for longer than allowed.
+    let reader = get_csv_reader("resources/case-inputs/case23.csv".to_owned());
+    let day = 24 * 60 * 60;
+    let mut pe = PaymentsEngine {
+        dispute_rules: DisputeRules {
+            filing_window: Some(30 * day),
+            expiry: Some(10 * day),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut rejections = vec![];
+    pe.process_transactions_reporting_to(reader.unwrap(), &mut rejections)
+        .unwrap();
+
+    // Disputed on day 2, expired by day 14.
+    let account = pe.accounts.get(&2).unwrap();
+    assert_eq!(account.total, Decimal::from(50));
+    assert_eq!(account.held, Decimal::from(0));
+    let dispute = pe.disputes.get(&2).unwrap();
+    assert_eq!(
+        dispute.history,
+        vec![DisputeState::Open, DisputeState::Expired]
+    );
+    // Disputed on day 2 as well, since it has no time of its own, resolved and opened again on day 6,
+    // expired by day 31.
+    let account = pe.accounts.get(&3).unwrap();
+    assert_eq!(account.total, Decimal::from(20));
+    assert_eq!(account.held, Decimal::from(0));
+    let dispute = pe.disputes.get(&3).unwrap();
+    assert_eq!(
+        dispute.history,
+        vec![
+            DisputeState::Open,
+            DisputeState::Resolved,
+            DisputeState::Open,
+            DisputeState::Expired
+        ]
+    );
+    assert!(pe.pending_disputes().unwrap().is_empty());
+
+    // Made at the start of the run and disputed on day 31.
+    assert!(!pe.disputes.contains_key(&1));
+    let rejected: Vec<(u64, RejectionReason)> = rejections
+        .iter()
+        .map(|rejection| (rejection.line, rejection.reason))
+        .collect();
+    assert_eq!(rejected, vec![(10, RejectionReason::DisputeWindowClosed)]);
+}
+
+#[test]
+fn case23_without_rules() {
+    // Without a window nor an expiry, timestamps change nothing.
+    let reader = get_csv_reader("resources/case-inputs/case23.csv".to_owned());
+    let mut pe = PaymentsEngine::default();
+    let mut rejections = vec![];
+    pe.process_transactions_reporting_to(reader.unwrap(), &mut rejections)
+        .unwrap();
+    assert!(rejections.is_empty());
+    assert_eq!(pe.pending_disputes().unwrap().len(), 3);
+}
+
+#[test]
+fn case24() {
+    // Transfers between clients, disputed and charged back, and refused by locks on either side.
+    let reader = get_csv_reader("resources/case-inputs/case24.csv".to_owned());
+    let mut pe = PaymentsEngine::default();
+    let mut rejections = vec![];
+    pe.process_transactions_reporting_to(reader.unwrap(), &mut rejections)
+        .unwrap();
+
+    // 30 transferred to client 2, then 20 of them charged back.
+    let account = pe.accounts.get(&1).unwrap();
+    assert_eq!(account.total, Decimal::from(90));
+    assert_eq!(account.held, Decimal::from(0));
+    assert!(account.is_locked());
+    let account = pe.accounts.get(&2).unwrap();
+    assert_eq!(account.total, Decimal::from(20));
+    assert_eq!(account.held, Decimal::from(0));
+    assert!(!account.is_locked());
+    assert!(!pe.accounts.contains_key(&3));
+    assert_eq!(pe.accounts.get(&4).unwrap().total, Decimal::from(0));
+    assert_eq!(pe.accounts.get(&5).unwrap().total, Decimal::from(5));
+
+    let rejected: Vec<(u64, RejectionReason)> = rejections
+        .iter()
+        .map(|rejection| (rejection.line, rejection.reason))
+        .collect();
+    assert_eq!(
+        rejected,
+        vec![
+            (5, RejectionReason::InsufficientAvailableFunds),
+            (6, RejectionReason::MissingDestination),
+            (7, RejectionReason::InconsistentOperation),
+            (12, RejectionReason::TargetAccountLocked),
+            (13, RejectionReason::TargetAccountLocked),
+        ]
+    );
+}
+
+#[test]
+fn holds_disputed_transfers_in_the_account_they_went_to() {
+    let data = "type, client, tx, amount, to_client\ndeposit, 1, 1, 10.0,\ntransfer, 1, 2, 4.0, 2\ndispute, 1, 2,,\n";
+    let mut pe = PaymentsEngine::default();
+    pe.process_transactions_from(get_csv_reader_from(data.as_bytes()))
+        .unwrap();
+    let account = pe.accounts.get(&1).unwrap();
+    assert_eq!(account.get_available(), Decimal::from(6));
+    assert_eq!(account.held, Decimal::from(0));
+    let account = pe.accounts.get(&2).unwrap();
+    assert_eq!(account.get_available(), Decimal::from(0));
+    assert_eq!(account.held, Decimal::from(4));
+}
+
+#[test]
+fn leaves_the_recipient_of_an_ignored_transfer_without_account() {
+    // The transfer reuses the ID of the deposit, so it's ignored.
+    let data =
+        "type, client, tx, amount, to_client\ndeposit, 1, 1, 10.0,\ntransfer, 1, 1, 4.0, 7\n";
+    let mut pe = PaymentsEngine::default();
+    pe.process_transactions_from(get_csv_reader_from(data.as_bytes()))
+        .unwrap();
+    assert_eq!(pe.accounts.get(&1).unwrap().total, Decimal::from(10));
+    assert!(!pe.accounts.contains_key(&7));
+}
+
+#[test]
+fn case25() {
+    // Disputes left open on an account locked by the chargeback of another one.
+    let reader = get_csv_reader("resources/case-inputs/case25.csv".to_owned());
+    let mut pe = PaymentsEngine::default();
+    let mut rejections = vec![];
+    pe.process_transactions_reporting_to(reader.unwrap(), &mut rejections)
+        .unwrap();
+
+    // The other two disputes keep their funds held.
+    let account = pe.accounts.get(&1).unwrap();
+    assert_eq!(account.total, Decimal::from(8));
+    assert_eq!(account.held, Decimal::from(8));
+    assert!(account.is_locked());
+    let stranded = pe.stranded_disputes().unwrap();
+    assert_eq!(
+        stranded
+            .iter()
+            .map(|dispute| (dispute.tx_id, dispute.held.amount, dispute.settleable))
+            .collect::<Vec<_>>(),
+        vec![(2, Decimal::from(5), false), (3, Decimal::from(3), false)]
+    );
+
+    let rejected: Vec<(u64, RejectionReason)> = rejections
+        .iter()
+        .map(|rejection| (rejection.line, rejection.reason))
+        .collect();
+    assert_eq!(
+        rejected,
+        vec![
+            (9, RejectionReason::TargetAccountLocked),
+            (10, RejectionReason::TargetAccountLocked),
+            (11, RejectionReason::TargetAccountLocked),
+            (12, RejectionReason::TargetAccountLocked),
+        ]
+    );
+}
+
+#[test]
+fn case25_settling_when_locked() {
+    // The open disputes are settled after the lock, but nothing else gets through.
+    let reader = get_csv_reader("resources/case-inputs/case25.csv".to_owned());
+    let mut pe = PaymentsEngine {
+        dispute_rules: DisputeRules {
+            settle_when_locked: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut rejections = vec![];
+    pe.process_transactions_reporting_to(reader.unwrap(), &mut rejections)
+        .unwrap();
+
+    let account = pe.accounts.get(&1).unwrap();
+    assert_eq!(account.total, Decimal::from(5));
+    assert_eq!(account.held, Decimal::from(0));
+    assert!(account.is_locked());
+    assert!(pe.pending_disputes().unwrap().is_empty());
+    assert!(pe.stranded_disputes().unwrap().is_empty());
+
+    let rejected: Vec<(u64, RejectionReason)> = rejections
+        .iter()
+        .map(|rejection| (rejection.line, rejection.reason))
+        .collect();
+    assert_eq!(
+        rejected,
+        vec![
+            (11, RejectionReason::TargetAccountLocked),
+            (12, RejectionReason::TargetAccountLocked),
+        ]
+    );
+}
+
+#[test]
+fn gives_back_the_part_of_the_fee_of_what_is_charged_back() {
+    let data =
+        "type, client, tx, amount\ndeposit, 1, 1, 100.0\ndispute, 1, 1, 50.0\nchargeback, 1, 1,\n";
+    let mut pe = PaymentsEngine {
+        fees: FeeSchedule::open("resources/fees.csv", 9).unwrap(),
+        ..Default::default()
+    };
+    pe.process_transactions_from(get_csv_reader_from(data.as_bytes()))
+        .unwrap();
+    // 100 - 0.1 deposited, 50 charged back and half of the fee given back.
+    assert_eq!(
+        pe.rounding.render(&pe.accounts.get(&1).unwrap().total),
+        "49.9500"
+    );
+    assert_eq!(
+        pe.rounding.render(&pe.accounts.get(&9).unwrap().total),
+        "0.0500"
+    );
+}
+
+#[test]
+fn renders_the_output_without_currencies_as_always() {
+    let reader = get_csv_reader("resources/case-inputs/case2.csv".to_owned());
+    let mut pe = PaymentsEngine::default();
+    pe.process_transactions_from(reader.unwrap()).unwrap();
+    assert_eq!(
+        render_accounts(&pe.accounts.all_accounts().unwrap(), pe.rounding),
+        vec![
+            "client, available, held, total, locked",
+            "1, 1.0000, 4.0000, 5.0000, false",
+        ]
+    );
+}
+
+#[test]
+fn case26() {
+    // Authorizations holding funds until they are captured or released.
+    let reader = get_csv_reader("resources/case-inputs/case26.csv".to_owned());
+    let mut pe = PaymentsEngine::default();
+    let mut rejections = vec![];
+    pe.process_transactions_reporting_to(reader.unwrap(), &mut rejections)
+        .unwrap();
+
+    // 30 captured, 20 released and 10 still held.
+    let account = pe.accounts.get(&1).unwrap();
+    assert_eq!(account.total, Decimal::from(70));
+    assert_eq!(account.held, Decimal::from(10));
+    assert_eq!(account.get_available(), Decimal::from(60));
+    assert_eq!(account.authorizations.keys().collect::<Vec<_>>(), vec![&5]);
+    let account = pe.accounts.get(&2).unwrap();
+    assert_eq!(account.total, Decimal::from(8));
+    assert_eq!(account.held, Decimal::from(5));
+
+    let rejected: Vec<(u64, RejectionReason)> = rejections
+        .iter()
+        .map(|rejection| (rejection.line, rejection.reason))
+        .collect();
+    assert_eq!(
+        rejected,
+        vec![
+            (5, RejectionReason::InsufficientAvailableFunds),
+            (9, RejectionReason::NotFound),
+            (10, RejectionReason::InconsistentOperation),
+            (11, RejectionReason::InconsistentOperation),
+            (12, RejectionReason::InconsistentOperation),
+            (14, RejectionReason::InsufficientAvailableFunds),
+        ]
+    );
+}
+
+#[test]
+fn case26_with_authorization_expiry() {
+    // The authorization of client 2 expires after its three later deposits, the one of client 1 is still in time.
+    let reader = get_csv_reader("resources/case-inputs/case26.csv".to_owned());
+    let mut pe = PaymentsEngine {
+        authorization_expiry: Some(3),
+        ..Default::default()
+    };
+    pe.process_transactions_from(reader.unwrap()).unwrap();
+
+    let account = pe.accounts.get(&1).unwrap();
+    assert_eq!(account.total, Decimal::from(70));
+    assert_eq!(account.held, Decimal::from(10));
+    assert_eq!(account.authorizations.get(&5).unwrap().age, 0);
+    let account = pe.accounts.get(&2).unwrap();
+    assert_eq!(account.total, Decimal::from(8));
+    assert_eq!(account.held, Decimal::from(0));
+    assert!(account.authorizations.is_empty());
+}
+
+#[test]
+fn ages_authorizations_only_with_the_transactions_applied() {
+    // Repeated transaction IDs are ignored, and leave the authorization as it was.
+    let mut pe = PaymentsEngine {
+        authorization_expiry: Some(2),
+        ..Default::default()
+    };
+    for row in [
+        "deposit, 1, 1, 100.0",
+        "authorize, 1, 2, 40.0",
+        "deposit, 1, 1, 100.0",
+        "deposit, 1, 1, 100.0",
+    ] {
+        pe.process(transaction(row)).unwrap();
+    }
+    let account = pe.accounts.get(&1).unwrap();
+    assert_eq!(account.held, Decimal::from(40));
+    assert_eq!(account.authorizations.get(&2).unwrap().age, 0);
+}
+
+#[test]
+fn case27() {
+    // Overdrafts down to the credit limit of the client, and no further.
+    let reader = get_csv_reader("resources/case-inputs/case27.csv".to_owned());
+    let mut pe = PaymentsEngine {
+        credit_limits: CreditLimits::open("resources/limits.csv").unwrap(),
+        ..Default::default()
+    };
+    let mut rejections = vec![];
+    pe.process_transactions_reporting_to(reader.unwrap(), &mut rejections)
+        .unwrap();
+
+    // All of the credit used, by the withdrawal and the authorization.
+    let account = pe.accounts.get(&1).unwrap();
+    assert_eq!(account.total, Decimal::from(-30));
+    assert_eq!(account.get_available(), Decimal::from(-50));
+    assert_eq!(account.used_credit_in(None), Decimal::from(50));
+
+    let rejected: Vec<(u64, RejectionReason)> = rejections
+        .iter()
+        .map(|rejection| (rejection.line, rejection.reason))
+        .collect();
+    assert_eq!(
+        rejected,
+        vec![
+            (4, RejectionReason::CreditLimitExceeded),
+            (7, RejectionReason::InsufficientAvailableFunds),
+            (9, RejectionReason::InsufficientAvailableFunds),
+        ]
+    );
+
+    let mut accounts = pe.accounts.values().cloned().collect::<Vec<_>>();
+    accounts.sort_by_key(|account| account.client_id());
+    assert_eq!(
+        render_accounts(&accounts, Rounding::default()),
+        vec![
+            "client, available, held, total, locked, credit",
+            "1, -50.0000, 20.0000, -30.0000, false, 50.0000",
+            "2, 10.0000, 0.0000, 10.0000, false, 0.0000",
+            "3, 5.0000, 0.0000, 5.0000, false, 0.0000",
+        ]
+    );
 }
@@ -0,0 +1,53 @@
+use fraction::Decimal;
+
+use crate::{
+    models::money::Money,
+    rates::{RateTable, RatesError},
+};
+
+fn rates() -> RateTable {
+    RateTable::open("resources/rates.csv").unwrap()
+}
+
+#[test]
+fn uses_the_rate_effective_for_the_transaction() {
+    let rates = rates();
+    assert_eq!(rates.rate("USD", "EUR", 1), Some(Decimal::from(0.9)));
+    assert_eq!(rates.rate("USD", "EUR", 4), Some(Decimal::from(0.9)));
+    assert_eq!(rates.rate("USD", "EUR", 5), Some(Decimal::from(0.8)));
+    assert_eq!(rates.rate("USD", "EUR", 500), Some(Decimal::from(0.8)));
+    assert_eq!(rates.rate("USD", "EUR", 0), None);
+}
+
+#[test]
+fn uses_the_inverse_rate_when_there_is_none_the_other_way_around() {
+    let rates = rates();
+    // Decimals only compare up to the digits they show, so exact values are checked multiplying them back.
+    let rate = rates.rate("EUR", "USD", 2).unwrap();
+    assert_eq!(rate * Decimal::from(9), Decimal::from(10));
+    assert_eq!(rates.rate("GBP", "USD", 2), None);
+    assert_eq!(rates.rate("JPY", "JPY", 2), Some(Decimal::from(1)));
+}
+
+#[test]
+fn converts_without_loss() {
+    let rates = rates();
+    let money = Money {
+        amount: Decimal::from(3),
+        currency: Some("EUR".to_owned()),
+    };
+    let converted = rates.convert(&money, "USD", 2).unwrap();
+    assert_eq!(converted.amount * Decimal::from(3), Decimal::from(10));
+    assert_eq!(converted.currency.as_deref(), Some("USD"));
+    let back = rates.convert(&converted, "EUR", 2).unwrap();
+    assert_eq!(back, money);
+}
+
+#[test]
+fn refuses_rates_that_are_not_positive() {
+    let data = "from, to, rate, effective_from_tx\nUSD, EUR, 0.9, 1\nUSD, EUR, 0, 5\n";
+    assert!(matches!(
+        RateTable::read_from(data.as_bytes()),
+        Err(RatesError::InvalidRate(3))
+    ));
+}
@@ -0,0 +1,175 @@
+use fraction::Decimal;
+
+use crate::{
+    input_ingestion::get_csv_reader,
+    models::{
+        restrictions::{AccountStatus, Restriction},
+        transaction::TransactionProcessingError,
+    },
+    payments_engine::PaymentsEngine,
+    ratios::{Counts, Ratio, RatioMonitor, RatiosError, Subject},
+    rejections::RejectionReason,
+    reports::write_ratio_report,
+    snapshot::Snapshot,
+    tests::{engine_with, transaction},
+};
+
+fn with_ratio_limits(pe: &mut PaymentsEngine) {
+    pe.ratios = RatioMonitor::open("resources/ratio-limits.csv").unwrap();
+}
+
+#[test]
+fn refuses_negative_limits() {
+    let data = "scope, ratio, limit, min_transactions\nclient, dispute, 0.1, 5\npartner, chargeback, -1, 5\n";
+    assert!(matches!(
+        RatioMonitor::read_from(data.as_bytes()),
+        Err(RatiosError::InvalidLimit(3))
+    ));
+}
+
+#[test]
+fn waits_for_the_minimum_transactions_before_restricting() {
+    let mut pe = engine_with(with_ratio_limits);
+    pe.process(transaction("deposit, 1, 1, 10.0,")).unwrap();
+    pe.process(transaction("dispute, 1, 1, ,")).unwrap();
+    assert!(pe.accounts.get(&1).unwrap().restrictions.is_empty());
+
+    pe.process(transaction("deposit, 1, 2, 10.0,")).unwrap();
+    pe.process(transaction("dispute, 1, 2, ,")).unwrap();
+    let account = pe.accounts.get(&1).unwrap();
+    assert!(account
+        .restrictions
+        .contains(&Restriction::ExcessiveDisputes));
+    assert_eq!(account.status(), AccountStatus::Restricted);
+}
+
+#[test]
+fn attributes_disputes_to_the_partner_of_the_disputed_transaction() {
+    let mut pe = engine_with(with_ratio_limits);
+    pe.process(transaction("deposit, 1, 1, 10.0, 7")).unwrap();
+    pe.process(transaction("deposit, 1, 2, 10.0,")).unwrap();
+    pe.process(transaction("dispute, 1, 1, , 8")).unwrap();
+    assert_eq!(
+        pe.ratios.counts_of(Subject::Partner(7)),
+        Counts {
+            transactions: 1,
+            disputes: 1,
+            chargebacks: 0
+        }
+    );
+    assert_eq!(pe.ratios.counts_of(Subject::Partner(8)), Counts::default());
+}
+
+#[test]
+fn case29() {
+    // Deposits through a partner and from a client stop once their dispute ratio goes over its limit.
+    let reader = get_csv_reader("resources/case-inputs/case29.csv".to_owned());
+    let mut pe = engine_with(with_ratio_limits);
+    let mut rejections = vec![];
+    pe.process_transactions_reporting_to(reader.unwrap(), &mut rejections)
+        .unwrap();
+
+    // Withdrawals and disputes go on once deposits are restricted.
+    let account = pe.accounts.get(&1).unwrap();
+    assert_eq!(account.total, Decimal::from(15));
+    assert_eq!(account.held, Decimal::from(10));
+    assert_eq!(
+        account.restrictions.iter().collect::<Vec<_>>(),
+        vec![&Restriction::ExcessiveDisputes]
+    );
+    assert!(pe.accounts.get(&2).unwrap().restrictions.is_empty());
+    assert_eq!(pe.accounts.get(&3).unwrap().total, Decimal::from(20));
+    assert!(pe.ratios.restricts(7));
+    assert!(!pe.ratios.restricts(8));
+
+    let rejected: Vec<(u64, RejectionReason)> = rejections
+        .iter()
+        .map(|rejection| (rejection.line, rejection.reason))
+        .collect();
+    assert_eq!(
+        rejected,
+        vec![
+            (9, RejectionReason::PartnerRestricted),
+            (12, RejectionReason::TargetAccountLocked),
+        ]
+    );
+    assert!(matches!(
+        pe.process(transaction("deposit, 2, 10, 10.0, 7")),
+        Err(TransactionProcessingError::PartnerRestricted(10))
+    ));
+
+    assert_eq!(
+        pe.ratios
+            .triggers
+            .iter()
+            .map(|trigger| (trigger.tx_id, trigger.subject, trigger.ratio))
+            .collect::<Vec<_>>(),
+        vec![
+            (3, Subject::Partner(7), Ratio::Dispute),
+            (2, Subject::Client(1), Ratio::Dispute),
+        ]
+    );
+    let mut output = vec![];
+    write_ratio_report(&pe.ratios.triggers, pe.rounding, &mut output).unwrap();
+    assert_eq!(
+        std::str::from_utf8(&output).unwrap(),
+        "tx,scope,id,ratio,value,limit,transactions,disputes,chargebacks\n\
+         3,partner,7,dispute,0.5000,0.2500,4,2,0\n\
+         2,client,1,dispute,1.0000,0.5000,2,2,0\n"
+    );
+}
+
+#[test]
+fn counts_only_the_disputes_opened_and_the_chargebacks_made() {
+    // Chargebacks without a dispute, and a dispute sent again, are ignored and don't count.
+    let limits = "scope, ratio, limit, min_transactions\nclient, dispute, 0.5, 2\nclient, chargeback, 0.5, 2\n";
+    let mut pe = PaymentsEngine {
+        ratios: RatioMonitor::read_from(limits.as_bytes()).unwrap(),
+        ..Default::default()
+    };
+    for row in [
+        "deposit, 1, 1, 10.0,",
+        "deposit, 1, 2, 10.0,",
+        "chargeback, 1, 1, ,",
+        "chargeback, 1, 1, ,",
+        "chargeback, 1, 2, ,",
+        "dispute, 1, 1, ,",
+        "dispute, 1, 1, ,",
+    ] {
+        pe.process(transaction(row)).unwrap();
+    }
+    assert_eq!(
+        pe.ratios.counts_of(Subject::Client(1)),
+        Counts {
+            transactions: 2,
+            disputes: 1,
+            chargebacks: 0
+        }
+    );
+    assert!(pe.accounts.get(&1).unwrap().restrictions.is_empty());
+    pe.process(transaction("deposit, 1, 3, 10.0,")).unwrap();
+    assert_eq!(pe.accounts.get(&1).unwrap().total, Decimal::from(30));
+}
+
+#[test]
+fn keeps_the_counts_and_the_restricted_partners_in_snapshots() {
+    let reader = get_csv_reader("resources/case-inputs/case29.csv".to_owned());
+    let mut pe = engine_with(with_ratio_limits);
+    pe.process_transactions_from(reader.unwrap()).unwrap();
+
+    let mut output = vec![];
+    pe.snapshot().unwrap().write_to(&mut output).unwrap();
+    let mut restored = engine_with(with_ratio_limits);
+    restored
+        .restore(Snapshot::read_from(output.as_slice()).unwrap())
+        .unwrap();
+    assert!(restored.ratios.restricts(7));
+    assert_eq!(
+        restored.ratios.counts_of(Subject::Partner(7)),
+        pe.ratios.counts_of(Subject::Partner(7))
+    );
+    assert!(matches!(
+        restored.process(transaction("deposit, 2, 10, 10.0, 7")),
+        Err(TransactionProcessingError::PartnerRestricted(10))
+    ));
+}
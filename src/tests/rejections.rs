@@ -0,0 +1,138 @@
+use fraction::Decimal;
+
+use crate::{
+    input_ingestion::get_csv_reader_from,
+    payments_engine::PaymentsEngine,
+    rejections::{
+        CsvRejectionWriter, JsonlRejectionWriter, Rejection, RejectionReason, RejectionSink,
+    },
+};
+
+fn process_reporting_rejections(data: &str) -> (PaymentsEngine, Vec<Rejection>) {
+    let reader = get_csv_reader_from(data.as_bytes());
+    let mut pe = PaymentsEngine::default();
+    let mut rejections = vec![];
+    pe.process_transactions_reporting_to(reader, &mut rejections)
+        .unwrap();
+    (pe, rejections)
+}
+
+#[test]
+fn reports_unparseable_records_with_their_position() {
+    let data = "type, client, tx, amount\ndeposit, 1, 1, 1.0\ndeposit, 1, 2, -1.0\ndeposit, 1, 3, abc\nrefund, 1, 4, 1.0\n";
+    let (pe, rejections) = process_reporting_rejections(data);
+
+    assert_eq!(pe.accounts.get(&1).unwrap().total, Decimal::from(1));
+    assert_eq!(rejections.len(), 3);
+
+    assert_eq!(rejections[0].line, 3);
+    assert_eq!(rejections[0].byte_offset, 44);
+    assert_eq!(rejections[0].record, "deposit,1,2,-1.0");
+    assert_eq!(rejections[0].reason, RejectionReason::NegativeAmount);
+
+    assert_eq!(rejections[1].line, 4);
+    assert_eq!(rejections[1].record, "deposit,1,3,abc");
+    assert_eq!(rejections[1].reason, RejectionReason::UnableToParseAmount);
+
+    assert_eq!(rejections[2].line, 5);
+    assert_eq!(rejections[2].record, "refund,1,4,1.0");
+    assert_eq!(rejections[2].reason, RejectionReason::MalformedRecord);
+}
+
+#[test]
+fn reports_records_with_their_fields_quoted_as_needed() {
+    let data = "type, client, tx, amount\ndeposit, 1, 1,\"1,5\"\ndeposit, 1, 2,\"1\"\"5\"\n";
+    let (_pe, rejections) = process_reporting_rejections(data);
+    let records: Vec<&str> = rejections
+        .iter()
+        .map(|rejection| rejection.record.as_str())
+        .collect();
+    assert_eq!(
+        records,
+        vec!["deposit,1,1,\"1,5\"", "deposit,1,2,\"1\"\"5\""]
+    );
+}
+
+#[test]
+fn reports_records_that_could_not_be_processed() {
+    let data = "type, client, tx, amount\ndeposit, 1, 1, 1.0\nwithdrawal, 1, 2, 5.0\ndispute, 1, 9,\ndispute, 2, 1,\ndispute, 1, 1,\nchargeback, 1, 1,\ndeposit, 1, 3, 1.0\n";
+    let (_pe, rejections) = process_reporting_rejections(data);
+
+    let reasons: Vec<(u64, RejectionReason)> = rejections
+        .iter()
+        .map(|rejection| (rejection.line, rejection.reason))
+        .collect();
+    assert_eq!(
+        reasons,
+        vec![
+            (3, RejectionReason::InsufficientAvailableFunds),
+            (4, RejectionReason::NotFound),
+            (5, RejectionReason::InconsistentOperation),
+            (8, RejectionReason::TargetAccountLocked),
+        ]
+    );
+}
+
+#[test]
+fn reports_records_with_an_amount_inconsistent_with_their_type() {
+    let data = "type, client, tx, amount\ndeposit, 1, 1,\nwithdrawal, 1, 2\ndeposit, 1, 3, 2.0\ndispute, 1, 3, 2.5\n";
+    let (pe, rejections) = process_reporting_rejections(data);
+
+    assert_eq!(pe.accounts.get(&1).unwrap().total, Decimal::from(2));
+    assert_eq!(pe.accounts.get(&1).unwrap().held, Decimal::from(0));
+    let reasons: Vec<(u64, RejectionReason)> = rejections
+        .iter()
+        .map(|rejection| (rejection.line, rejection.reason))
+        .collect();
+    assert_eq!(
+        reasons,
+        vec![
+            (2, RejectionReason::MissingAmount),
+            (3, RejectionReason::MissingAmount),
+            (5, RejectionReason::ExceedsDisputedAmount),
+        ]
+    );
+}
+
+#[test]
+fn accepts_disputes_without_trailing_amount_column() {
+    let data = "type, client, tx, amount\ndeposit, 1, 1, 4.0\ndispute, 1, 1\n";
+    let (pe, rejections) = process_reporting_rejections(data);
+
+    assert!(rejections.is_empty());
+    assert_eq!(pe.accounts.get(&1).unwrap().held, Decimal::from(4));
+}
+
+#[test]
+fn can_write_rejections_as_csv() {
+    let data = "type, client, tx, amount\nwithdrawal, 1, 1, 1.0\n";
+    let (_pe, rejections) = process_reporting_rejections(data);
+    let mut output = vec![];
+    {
+        let mut writer = CsvRejectionWriter::new(&mut output);
+        for rejection in rejections {
+            writer.reject(rejection).unwrap();
+        }
+    }
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "line,byte_offset,record,reason,rule\n2,25,\"withdrawal,1,1,1.0\",InsufficientAvailableFunds,\n"
+    );
+}
+
+#[test]
+fn can_write_rejections_as_jsonl() {
+    let data = "type, client, tx, amount\nwithdrawal, 1, 1, 1.0\n";
+    let (_pe, rejections) = process_reporting_rejections(data);
+    let mut output = vec![];
+    {
+        let mut writer = JsonlRejectionWriter::new(&mut output);
+        for rejection in rejections {
+            writer.reject(rejection).unwrap();
+        }
+    }
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "{\"line\":2,\"byte_offset\":25,\"record\":\"withdrawal,1,1,1.0\",\"reason\":\"InsufficientAvailableFunds\",\"rule\":null}\n"
+    );
+}
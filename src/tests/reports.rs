@@ -0,0 +1,55 @@
+use crate::{
+    input_ingestion::get_csv_reader,
+    models::money::Rounding,
+    payments_engine::PaymentsEngine,
+    reports::{write_dispute_report, write_stranded_report},
+};
+
+#[test]
+fn reports_every_dispute_with_its_history() {
+    let reader = get_csv_reader("resources/case-inputs/case17.csv".to_owned());
+    let mut pe = PaymentsEngine::default();
+    pe.process_transactions_from(reader.unwrap()).unwrap();
+
+    let mut output = vec![];
+    write_dispute_report(
+        &pe.disputes.values().cloned().collect::<Vec<_>>(),
+        &mut output,
+    )
+    .unwrap();
+    let mut lines: Vec<&str> = std::str::from_utf8(&output).unwrap().lines().collect();
+    lines[1..].sort();
+    assert_eq!(
+        lines,
+        vec![
+            "tx,client,state,reopenings,history",
+            "1,1,ChargedBack,1,Open > Resolved > Open > ChargedBack",
+            "2,1,Resolved,0,Open > Resolved",
+        ]
+    );
+}
+
+#[test]
+fn reports_the_disputes_stranded_on_locked_accounts() {
+    let reader = get_csv_reader("resources/case-inputs/case25.csv".to_owned());
+    let mut pe = PaymentsEngine::default();
+    pe.process_transactions_from(reader.unwrap()).unwrap();
+
+    let mut output = vec![];
+    write_stranded_report(
+        &pe.stranded_disputes().unwrap(),
+        Rounding::default(),
+        &mut output,
+    )
+    .unwrap();
+    let mut lines: Vec<&str> = std::str::from_utf8(&output).unwrap().lines().collect();
+    lines[1..].sort();
+    assert_eq!(
+        lines,
+        vec![
+            "tx,client,held,currency,status,restrictions,settleable",
+            "2,1,5.0000,,locked,chargeback,false",
+            "3,1,3.0000,,locked,chargeback,false",
+        ]
+    );
+}
@@ -0,0 +1,160 @@
+use fraction::Decimal;
+
+use crate::{
+    input_ingestion::{get_csv_reader, get_csv_reader_from},
+    models::transaction::TransactionProcessingError,
+    payments_engine::PaymentsEngine,
+    rejections::RejectionReason,
+    reports::write_flagged_report,
+    rules::{RuleSet, RulesError},
+    tests::{engine_with, transaction},
+};
+
+fn with_rules(pe: &mut PaymentsEngine) {
+    pe.rules = RuleSet::open("resources/rules.csv").unwrap();
+}
+
+#[test]
+fn names_the_rule_rejecting_a_transaction() {
+    let mut pe = engine_with(with_rules);
+    match pe.process(transaction("deposit, 1, 1, 1000.5")) {
+        Err(TransactionProcessingError::RuleViolated((tx_id, rule))) => {
+            assert_eq!(tx_id, 1);
+            assert_eq!(rule, "large-deposit");
+        }
+        other => panic!("Expected the deposit to break a rule, got {:?}", other),
+    }
+    assert!(!pe.accounts.contains_key(&1));
+}
+
+#[test]
+fn refuses_rules_without_a_window_to_look_at() {
+    let data = "name, rule, type, limit, last_transactions, window_seconds, action\nfrequent, max-count, withdrawal, 3, , , reject\n";
+    assert!(matches!(
+        RuleSet::read_from(data.as_bytes()),
+        Err(RulesError::InvalidRule(2))
+    ));
+    let data = "name, rule, type, limit, last_transactions, window_seconds, action\nlarge, max-amount, dispute, 3, , , reject\n";
+    assert!(matches!(
+        RuleSet::read_from(data.as_bytes()),
+        Err(RulesError::InvalidRule(2))
+    ));
+}
+
+#[test]
+fn case28() {
+    // Amounts, withdrawals in the last transactions or in a day, and disputes per transaction, limited by rules.
+    let reader = get_csv_reader("resources/case-inputs/case28.csv".to_owned());
+    let mut pe = engine_with(with_rules);
+    let mut rejections = vec![];
+    pe.process_transactions_reporting_to(reader.unwrap(), &mut rejections)
+        .unwrap();
+
+    // The withdrawal a day later is out of the window of the one before it.
+    let account = pe.accounts.get(&1).unwrap();
+    assert_eq!(account.total, Decimal::from(90));
+    let account = pe.accounts.get(&2).unwrap();
+    assert_eq!(account.held, Decimal::from(20));
+
+    let rejected: Vec<(u64, RejectionReason, Option<&str>)> = rejections
+        .iter()
+        .map(|rejection| (rejection.line, rejection.reason, rejection.rule.as_deref()))
+        .collect();
+    assert_eq!(
+        rejected,
+        vec![
+            (2, RejectionReason::RuleViolated, Some("large-deposit")),
+            (
+                6,
+                RejectionReason::RuleViolated,
+                Some("frequent-withdrawals")
+            ),
+            (10, RejectionReason::RuleViolated, Some("daily-withdrawals")),
+        ]
+    );
+
+    let mut output = vec![];
+    write_flagged_report(&pe.flagged, &mut output).unwrap();
+    assert_eq!(
+        std::str::from_utf8(&output).unwrap(),
+        "tx,client,rule\n3,1,large-withdrawal\n12,2,disputes\n"
+    );
+}
+
+#[test]
+fn counts_only_the_disputes_that_were_opened() {
+    // The dispute sent again is ignored, so it doesn't take the client to more than half of its deposits disputed.
+    let mut pe = engine_with(with_rules);
+    for row in [
+        "deposit, 1, 1, 10.0",
+        "deposit, 1, 2, 10.0",
+        "deposit, 1, 3, 10.0",
+        "deposit, 1, 4, 10.0",
+        "dispute, 1, 1,",
+        "dispute, 1, 1,",
+        "dispute, 1, 2,",
+    ] {
+        pe.process(transaction(row)).unwrap();
+    }
+    assert!(pe.flagged.is_empty());
+    assert_eq!(pe.accounts.get(&1).unwrap().held, Decimal::from(20));
+}
+
+#[test]
+fn limits_amounts_in_the_currency_of_the_rule() {
+    let rules = "name, rule, type, limit, last_transactions, window_seconds, action, currency
+large-deposit, max-amount, deposit, 100, , , reject,
+large-usd-deposit, max-amount, deposit, 10, , , reject, usd
+usd-deposits, max-sum, deposit, 15, 10, , reject, USD
+";
+    let mut pe = PaymentsEngine {
+        rules: RuleSet::read_from(rules.as_bytes()).unwrap(),
+        ..Default::default()
+    };
+    // 500 BRL are neither over the limit in the default currency nor over the one in USD, and don't add up to the USD.
+    let data = "type, client, tx, amount, currency
+deposit, 1, 1, 500.0, BRL
+deposit, 1, 2, 50.0,
+deposit, 1, 3, 10.0, USD
+deposit, 1, 4, 11.0, USD
+deposit, 1, 5, 6.0, USD
+deposit, 1, 6, 101.0,
+";
+    let mut rejections = vec![];
+    pe.process_transactions_reporting_to(get_csv_reader_from(data.as_bytes()), &mut rejections)
+        .unwrap();
+    let rejected: Vec<(u64, Option<&str>)> = rejections
+        .iter()
+        .map(|rejection| (rejection.line, rejection.rule.as_deref()))
+        .collect();
+    assert_eq!(
+        rejected,
+        vec![
+            (5, Some("large-usd-deposit")),
+            (6, Some("usd-deposits")),
+            (7, Some("large-deposit")),
+        ]
+    );
+    assert!(matches!(
+        RuleSet::read_from(
+            "name, rule, type, limit, last_transactions, window_seconds, action, currency\nfrequent, max-count, deposit, 3, 10, , reject, USD\n"
+                .as_bytes()
+        ),
+        Err(RulesError::InvalidRule(2))
+    ));
+}
+
+#[test]
+fn flags_only_the_transactions_that_were_applied() {
+    // The repeated withdrawal is flagged by its amount, but it's ignored.
+    let mut pe = engine_with(with_rules);
+    for row in [
+        "deposit, 1, 1, 900.0",
+        "deposit, 1, 2, 900.0",
+        "withdrawal, 1, 3, 600.0",
+        "withdrawal, 1, 3, 600.0",
+    ] {
+        pe.process(transaction(row)).unwrap();
+    }
+    assert_eq!(pe.flagged.len(), 1);
+}
@@ -0,0 +1,115 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    thread,
+};
+
+use crate::{
+    journal::Journal, payments_engine::PaymentsEngine, rejections::IgnoredRejections,
+    server::Server, tests::journal::UnwritableOutput,
+};
+
+fn start_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = Server::new(PaymentsEngine::default(), Box::new(IgnoredRejections));
+    thread::spawn(move || server.serve(listener));
+    address
+}
+
+// Sends the rows followed by an accounts query and returns the lines of the answer.
+fn send_and_query(address: SocketAddr, rows: &str) -> Vec<String> {
+    let mut stream = TcpStream::connect(address).unwrap();
+    stream.write_all(rows.as_bytes()).unwrap();
+    stream.write_all(b"accounts\n").unwrap();
+    BufReader::new(stream)
+        .lines()
+        .map(|line| line.unwrap())
+        .take_while(|line| !line.is_empty())
+        .collect()
+}
+
+#[test]
+fn processes_rows_and_answers_accounts_queries() {
+    let address = start_server();
+    let accounts = send_and_query(
+        address,
+        "type, client, tx, amount\ndeposit, 1, 1, 3.0\nwithdrawal, 1, 2, 1.0\ndeposit, 2, 3, 2.0\ndispute, 2, 3\n",
+    );
+    assert_eq!(
+        accounts,
+        vec![
+            "client, available, held, total, locked",
+            "1, 2.0000, 0.0000, 2.0000, false",
+            "2, 0.0000, 2.0000, 2.0000, false",
+        ]
+    );
+}
+
+#[test]
+fn keeps_the_connection_going_after_invalid_rows() {
+    let address = start_server();
+    let accounts = send_and_query(
+        address,
+        "deposit, 1, 1, -3.0\nrefund, 1, 2, 1.0\nwithdrawal, 1, 3, 1.0\ndeposit, 1, 4, 1.5\n",
+    );
+    assert_eq!(
+        accounts,
+        vec![
+            "client, available, held, total, locked",
+            "1, 1.5000, 0.0000, 1.5000, false",
+        ]
+    );
+}
+
+#[test]
+fn feeds_concurrent_connections_into_one_engine() {
+    let address = start_server();
+    let partners: Vec<_> = (0..8u32)
+        .map(|partner| {
+            thread::spawn(move || {
+                // Every partner deposits 1.0 ten times for each of two clients, shared with another partner.
+                let rows: String = (0..10u32)
+                    .flat_map(|i| {
+                        let tx = partner * 100 + i * 2;
+                        [
+                            format!("deposit, {}, {}, 1.0\n", partner, tx),
+                            format!("deposit, {}, {}, 1.0\n", (partner + 1) % 8, tx + 1),
+                        ]
+                    })
+                    .collect();
+                send_and_query(address, &rows)
+            })
+        })
+        .collect();
+    for partner in partners {
+        partner.join().unwrap();
+    }
+
+    let accounts = send_and_query(address, "");
+    assert_eq!(accounts.len(), 9);
+    for (client, line) in accounts[1..].iter().enumerate() {
+        assert_eq!(
+            line,
+            &format!("{}, 20.0000, 0.0000, 20.0000, false", client)
+        );
+    }
+}
+
+#[test]
+fn stops_taking_rows_from_every_connection_once_the_journal_failed() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let pe = PaymentsEngine {
+        journal: Some(Journal::new(UnwritableOutput)),
+        ..Default::default()
+    };
+    let server = Server::new(pe, Box::new(IgnoredRejections));
+    let engine = server.engine_handle();
+    thread::spawn(move || server.serve(listener));
+
+    // The connection failing to journal its row is closed, and so is the next one sending a row.
+    assert!(send_and_query(address, "deposit, 1, 1, 1.0\n").is_empty());
+    assert!(send_and_query(address, "deposit, 2, 2, 1.0\n").is_empty());
+    assert!(!engine.lock().unwrap().accounts.contains_key(&2));
+}
@@ -0,0 +1,296 @@
+use std::fs;
+
+use fraction::Decimal;
+
+use crate::{
+    blocklist::Blocklist,
+    fees::FeeSchedule,
+    input_ingestion::get_csv_reader_from,
+    models::disputes::DisputeRules,
+    payments_engine::PaymentsEngine,
+    rates::RateTable,
+    ratios::RatioMonitor,
+    rejections::{Rejection, RejectionReason},
+    reports::write_flagged_report,
+    rules::RuleSet,
+    sharded_engine::{ShardedPaymentsEngine, ShardingError},
+};
+
+// Every engine converts with the rates of the conversion case.
+fn new_engine() -> PaymentsEngine {
+    PaymentsEngine {
+        rates: RateTable::open("resources/rates.csv").unwrap(),
+        ..Default::default()
+    }
+}
+
+fn process_single(data: &str) -> (PaymentsEngine, Vec<Rejection>) {
+    let mut pe = new_engine();
+    let mut rejections = vec![];
+    pe.process_transactions_reporting_to(get_csv_reader_from(data.as_bytes()), &mut rejections)
+        .unwrap();
+    (pe, rejections)
+}
+
+fn process_sharded(data: &str, workers: usize) -> (PaymentsEngine, Vec<Rejection>) {
+    let mut sharded = ShardedPaymentsEngine::split(new_engine(), workers).unwrap();
+    sharded
+        .process_transactions_from(get_csv_reader_from(data.as_bytes()))
+        .unwrap();
+    let mut rejections = vec![];
+    let pe = sharded.finish(&mut rejections).unwrap();
+    (pe, rejections)
+}
+
+// Both engines must end up in exactly the same state and reject exactly the same records.
+fn assert_same_outcome(data: &str, workers: usize) {
+    let (single, single_rejections) = process_single(data);
+    let (sharded, sharded_rejections) = process_sharded(data, workers);
+    assert_eq!(
+        serde_json::to_string(&single.snapshot().unwrap()).unwrap(),
+        serde_json::to_string(&sharded.snapshot().unwrap()).unwrap(),
+    );
+    assert_eq!(
+        serde_json::to_string(&single_rejections).unwrap(),
+        serde_json::to_string(&sharded_rejections).unwrap(),
+    );
+}
+
+#[test]
+fn gives_the_same_outcome_as_the_single_engine_for_every_case() {
+    for case in 1..=30 {
+        let data = fs::read_to_string(format!("resources/case-inputs/case{}.csv", case)).unwrap();
+        for workers in [1, 2, 3, 8] {
+            assert_same_outcome(&data, workers);
+        }
+    }
+}
+
+#[test]
+fn ignores_deposits_reusing_a_transaction_id_of_another_shard() {
+    let data =
+        "type, client, tx, amount\ndeposit, 1, 1, 5.0\ndeposit, 2, 1, 7.0\ndeposit, 2, 2, 1.0\n";
+    assert_same_outcome(data, 2);
+
+    let (pe, rejections) = process_sharded(data, 2);
+    assert_eq!(pe.accounts.get(&1).unwrap().total, Decimal::from(5));
+    assert_eq!(pe.accounts.get(&2).unwrap().total, Decimal::from(1));
+    assert!(rejections.is_empty());
+}
+
+#[test]
+fn rejects_disputes_of_a_transaction_recorded_in_another_shard() {
+    let data = "type, client, tx, amount\ndeposit, 1, 1, 5.0\ndispute, 2, 1,\ndispute, 1, 1,\nresolve, 2, 1,\nchargeback, 2, 1,\n";
+    assert_same_outcome(data, 2);
+
+    let (pe, rejections) = process_sharded(data, 2);
+    assert_eq!(pe.accounts.get(&1).unwrap().held, Decimal::from(5));
+    assert_eq!(rejections.len(), 3);
+    assert!(rejections
+        .iter()
+        .all(|r| r.reason == RejectionReason::InconsistentOperation));
+    assert_eq!(
+        rejections.iter().map(|r| r.line).collect::<Vec<_>>(),
+        vec![3, 5, 6]
+    );
+}
+
+#[test]
+fn lets_another_client_take_a_transaction_id_that_was_never_recorded() {
+    // The withdrawal of client 1 fails, so the deposit of client 2 with the same ID is the one recorded.
+    // The deposit of client 1 repeating it is ignored, so client 1 never gets an account.
+    let data = "type, client, tx, amount\nwithdrawal, 1, 1, 5.0\ndeposit, 2, 1, 3.0\ndispute, 2, 1,\ndeposit, 1, 1, 4.0\n";
+    assert_same_outcome(data, 2);
+
+    let (pe, rejections) = process_sharded(data, 2);
+    assert!(!pe.accounts.contains_key(&1));
+    assert_eq!(pe.accounts.get(&2).unwrap().held, Decimal::from(3));
+    assert_eq!(rejections.len(), 1);
+    assert_eq!(
+        rejections[0].reason,
+        RejectionReason::InsufficientAvailableFunds
+    );
+}
+
+#[test]
+fn keeps_processing_on_top_of_a_split_engine() {
+    let (pe, _) =
+        process_single("type, client, tx, amount\ndeposit, 1, 1, 5.0\ndeposit, 2, 2, 2.0\n");
+    let mut sharded = ShardedPaymentsEngine::split(pe, 2).unwrap();
+    sharded
+        .process_transactions_from(get_csv_reader_from(
+            "type, client, tx, amount\ndeposit, 2, 1, 9.0\ndispute, 2, 1,\ndispute, 1, 1,\n"
+                .as_bytes(),
+        ))
+        .unwrap();
+    let mut rejections = vec![];
+    let pe = sharded.finish(&mut rejections).unwrap();
+    assert_eq!(pe.accounts.get(&1).unwrap().held, Decimal::from(5));
+    assert_eq!(pe.accounts.get(&2).unwrap().total, Decimal::from(2));
+    assert_eq!(rejections.len(), 1);
+    assert_eq!(rejections[0].reason, RejectionReason::InconsistentOperation);
+}
+
+#[test]
+fn transfers_to_clients_of_another_shard() {
+    // Client 2 spends what it got from client 1 right away, then the transfer is disputed and charged back.
+    let data = "type, client, tx, amount, to_client\ndeposit, 1, 1, 10.0,\ntransfer, 1, 2, 4.0, 2\nwithdrawal, 2, 3, 1.0,\ndispute, 1, 2,,\nchargeback, 1, 2,,\n";
+    assert_same_outcome(data, 2);
+
+    let (pe, rejections) = process_sharded(data, 2);
+    let account = pe.accounts.get(&1).unwrap();
+    assert_eq!(account.total, Decimal::from(10));
+    assert!(account.is_locked());
+    let account = pe.accounts.get(&2).unwrap();
+    assert_eq!(account.total, Decimal::from(-1));
+    assert_eq!(account.held, Decimal::from(0));
+    assert!(rejections.is_empty());
+}
+
+#[test]
+fn keeps_the_dispute_rules_after_merging_the_shards() {
+    let data = "type, client, tx, amount\ndeposit, 1, 1, 10.0\ndeposit, 1, 2, 5.0\ndispute, 1, 1,\ndispute, 1, 2,\nchargeback, 1, 1,\n";
+    let pe = PaymentsEngine {
+        dispute_rules: DisputeRules {
+            settle_when_locked: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut sharded = ShardedPaymentsEngine::split(pe, 2).unwrap();
+    sharded
+        .process_transactions_from(get_csv_reader_from(data.as_bytes()))
+        .unwrap();
+    let pe = sharded.finish(&mut vec![]).unwrap();
+
+    assert!(pe.dispute_rules.settle_when_locked);
+    let stranded = pe.stranded_disputes().unwrap();
+    assert_eq!(stranded.len(), 1);
+    assert_eq!(stranded[0].held.amount, Decimal::from(5));
+    assert!(stranded[0].settleable);
+}
+
+#[test]
+fn applies_the_rules_as_the_single_engine() {
+    let data = fs::read_to_string("resources/case-inputs/case28.csv").unwrap();
+    let with_rules = || PaymentsEngine {
+        rules: RuleSet::open("resources/rules.csv").unwrap(),
+        ..Default::default()
+    };
+    let mut single = with_rules();
+    let mut single_rejections = vec![];
+    single
+        .process_transactions_reporting_to(
+            get_csv_reader_from(data.as_bytes()),
+            &mut single_rejections,
+        )
+        .unwrap();
+    let mut single_flagged = vec![];
+    write_flagged_report(&single.flagged, &mut single_flagged).unwrap();
+    for workers in [2, 3] {
+        let mut sharded = ShardedPaymentsEngine::split(with_rules(), workers).unwrap();
+        sharded
+            .process_transactions_from(get_csv_reader_from(data.as_bytes()))
+            .unwrap();
+        let mut rejections = vec![];
+        let pe = sharded.finish(&mut rejections).unwrap();
+        assert_eq!(
+            serde_json::to_string(&single_rejections).unwrap(),
+            serde_json::to_string(&rejections).unwrap(),
+        );
+        let mut flagged = vec![];
+        write_flagged_report(&pe.flagged, &mut flagged).unwrap();
+        assert_eq!(single_flagged, flagged);
+    }
+}
+
+#[test]
+fn blocks_as_the_single_engine() {
+    let data = fs::read_to_string("resources/case-inputs/case30.csv").unwrap();
+    let with_blocklist = || PaymentsEngine {
+        blocklist: Blocklist::open("resources/blocklist.csv").unwrap(),
+        ..Default::default()
+    };
+    let mut single = with_blocklist();
+    let mut single_rejections = vec![];
+    single
+        .process_transactions_reporting_to(
+            get_csv_reader_from(data.as_bytes()),
+            &mut single_rejections,
+        )
+        .unwrap();
+    for workers in [2, 3] {
+        let mut sharded = ShardedPaymentsEngine::split(with_blocklist(), workers).unwrap();
+        sharded
+            .process_transactions_from(get_csv_reader_from(data.as_bytes()))
+            .unwrap();
+        let mut rejections = vec![];
+        let pe = sharded.finish(&mut rejections).unwrap();
+        assert_eq!(
+            serde_json::to_string(&single_rejections).unwrap(),
+            serde_json::to_string(&rejections).unwrap(),
+        );
+        assert_eq!(
+            serde_json::to_string(&single.snapshot().unwrap()).unwrap(),
+            serde_json::to_string(&pe.snapshot().unwrap()).unwrap(),
+        );
+        assert!(pe.blocklist.blocks_client(3));
+    }
+}
+
+#[test]
+fn refuses_to_split_what_the_shards_cant_do() {
+    let pe = PaymentsEngine {
+        fees: FeeSchedule::open("resources/fees.csv", 0).unwrap(),
+        ..Default::default()
+    };
+    assert!(matches!(
+        ShardedPaymentsEngine::split(pe, 2),
+        Err(ShardingError::Unsupported("--fees"))
+    ));
+    let pe = PaymentsEngine {
+        ratios: RatioMonitor::open("resources/ratio-limits.csv").unwrap(),
+        ..Default::default()
+    };
+    assert!(matches!(
+        ShardedPaymentsEngine::split(pe, 2),
+        Err(ShardingError::Unsupported("--ratio-limits"))
+    ));
+    let pe = PaymentsEngine {
+        dispute_rules: DisputeRules {
+            expiry: Some(100),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    assert!(matches!(
+        ShardedPaymentsEngine::split(pe, 2),
+        Err(ShardingError::Unsupported("--dispute-expiry-days"))
+    ));
+    let pe = PaymentsEngine {
+        dispute_rules: DisputeRules {
+            filing_window: Some(100),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    assert!(matches!(
+        ShardedPaymentsEngine::split(pe, 2),
+        Err(ShardingError::Unsupported("--dispute-window-days"))
+    ));
+}
+
+#[test]
+fn keeps_the_clock_after_merging_the_shards() {
+    let pe = PaymentsEngine {
+        clock: Some(100),
+        ..Default::default()
+    };
+    let mut sharded = ShardedPaymentsEngine::split(pe, 2).unwrap();
+    let data = "type, client, tx, amount, timestamp\ndeposit, 1, 1, 1.0, 50\n";
+    sharded
+        .process_transactions_from(get_csv_reader_from(data.as_bytes()))
+        .unwrap();
+    let pe = sharded.finish(&mut vec![]).unwrap();
+    assert_eq!(pe.clock, Some(100));
+}
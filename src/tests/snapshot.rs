@@ -0,0 +1,113 @@
+use fraction::Decimal;
+
+use crate::{
+    input_ingestion::{get_csv_reader, get_csv_reader_from},
+    models::disputes::DisputeRules,
+    payments_engine::PaymentsEngine,
+    snapshot::Snapshot,
+};
+
+fn restored_from_snapshot_of(pe: &PaymentsEngine) -> PaymentsEngine {
+    let mut output = vec![];
+    pe.snapshot().unwrap().write_to(&mut output).unwrap();
+    let mut restored = PaymentsEngine::default();
+    restored
+        .restore(Snapshot::read_from(output.as_slice()).unwrap())
+        .unwrap();
+    restored
+}
+
+#[test]
+fn restores_accounts_transactions_and_pending_disputes() {
+    // case14 leaves a pending dispute on tx 1 of account 1.
+    let reader = get_csv_reader("resources/case-inputs/case14.csv".to_owned());
+    let mut pe = PaymentsEngine::default();
+    pe.process_transactions_from(reader.unwrap()).unwrap();
+
+    let mut restored = restored_from_snapshot_of(&pe);
+    assert_eq!(restored.accounts.len(), 2);
+    assert_eq!(restored.transactions.len(), pe.transactions.len());
+    assert!(restored.disputes.contains_key(&1));
+
+    // The next day, the pending dispute gets a chargeback and a past deposit gets disputed.
+    let data = "type, client, tx, amount\nchargeback, 1, 1,\ndispute, 2, 5,\n";
+    restored
+        .process_transactions_from(get_csv_reader_from(data.as_bytes()))
+        .unwrap();
+
+    let account = restored.accounts.get(&1).unwrap();
+    assert_eq!(account.get_available(), Decimal::from(5));
+    assert_eq!(account.total, Decimal::from(5));
+    assert_eq!(account.held, Decimal::from(0));
+    assert!(account.is_locked());
+
+    let account = restored.accounts.get(&2).unwrap();
+    assert_eq!(account.get_available(), Decimal::from(3));
+    assert_eq!(account.total, Decimal::from(4));
+    assert_eq!(account.held, Decimal::from(1));
+    assert!(!account.is_locked());
+}
+
+#[test]
+fn restores_amounts_without_loss() {
+    let reader = get_csv_reader("resources/case-inputs/case1.csv".to_owned());
+    let mut pe = PaymentsEngine::default();
+    pe.process_transactions_from(reader.unwrap()).unwrap();
+    let reader = get_csv_reader("resources/case-inputs/case15.csv".to_owned());
+    let mut tiny = PaymentsEngine::default();
+    tiny.process_transactions_from(reader.unwrap()).unwrap();
+
+    let restored = restored_from_snapshot_of(&pe);
+    let account = restored.accounts.get(&1).unwrap();
+    assert_eq!(account.total, pe.accounts.get(&1).unwrap().total);
+    assert_eq!(
+        account.total,
+        Decimal::from("987654321987654.1001") + Decimal::from(2) + Decimal::from(0.0101)
+            - Decimal::from(1.5)
+    );
+
+    let restored = restored_from_snapshot_of(&tiny);
+    assert_eq!(
+        restored.accounts.get(&1).unwrap().total,
+        Decimal::from("0.9999999999999999999")
+    );
+}
+
+#[test]
+fn same_state_produces_the_same_snapshot() {
+    let reader = get_csv_reader("resources/case-inputs/case13.csv".to_owned());
+    let mut pe = PaymentsEngine::default();
+    pe.process_transactions_from(reader.unwrap()).unwrap();
+
+    let mut output = vec![];
+    pe.snapshot().unwrap().write_to(&mut output).unwrap();
+    let mut restored_output = vec![];
+    restored_from_snapshot_of(&pe)
+        .snapshot()
+        .unwrap()
+        .write_to(&mut restored_output)
+        .unwrap();
+    assert_eq!(restored_output, output);
+}
+
+#[test]
+fn restores_the_clock_expiring_the_restored_disputes_as_it_moves() {
+    let day = 24 * 60 * 60;
+    let rules = DisputeRules {
+        expiry: Some(day),
+        ..Default::default()
+    };
+    let data = "type, client, tx, amount, timestamp\ndeposit, 1, 1, 10.0, 0\ndispute, 1, 1, , 10\n";
+    let mut pe = PaymentsEngine {
+        dispute_rules: rules,
+        ..Default::default()
+    };
+    pe.process_transactions_from(get_csv_reader_from(data.as_bytes()))
+        .unwrap();
+
+    let mut restored = restored_from_snapshot_of(&pe);
+    assert_eq!(restored.clock, Some(10));
+    restored.dispute_rules = rules;
+    restored.advance_clock_to(10 + day).unwrap();
+    assert_eq!(restored.accounts.get(&1).unwrap().held, Decimal::from(0));
+}
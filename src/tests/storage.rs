@@ -0,0 +1,128 @@
+use std::{env, fs};
+
+use fraction::Decimal;
+
+use crate::{
+    input_ingestion::{get_csv_reader, get_csv_reader_from},
+    models::{account::Account, shared::ClientID},
+    payments_engine::{InputProcessingError, PaymentsEngine},
+    storage::{sqlite, AccountStore, DisputeStore, StorageError, TransactionStore},
+};
+
+#[test]
+fn sqlite_stores_process_like_in_memory_ones() {
+    for case in 1..=15 {
+        let path = format!("resources/case-inputs/case{}.csv", case);
+        let mut in_memory = PaymentsEngine::default();
+        in_memory
+            .process_transactions_from(get_csv_reader(path.clone()).unwrap())
+            .unwrap();
+        let (accounts, transactions, disputes) = sqlite::open_in_memory().unwrap();
+        let mut on_sqlite = PaymentsEngine::with_stores(accounts, transactions, disputes);
+        on_sqlite
+            .process_transactions_from(get_csv_reader(path).unwrap())
+            .unwrap();
+
+        let expected = in_memory.accounts.all_accounts().unwrap();
+        let accounts = on_sqlite.accounts.all_accounts().unwrap();
+        assert_eq!(accounts.len(), expected.len());
+        for (account, expected) in accounts.iter().zip(expected.iter()) {
+            assert_eq!(account.client_id(), expected.client_id());
+            assert_eq!(account.total, expected.total);
+            assert_eq!(account.held, expected.held);
+            assert_eq!(account.is_locked(), expected.is_locked());
+        }
+        assert_eq!(
+            on_sqlite.transactions.all_transactions().unwrap().len(),
+            in_memory.transactions.len()
+        );
+        assert_eq!(
+            on_sqlite.disputes.all_disputes().unwrap().len(),
+            in_memory.disputes.len()
+        );
+    }
+}
+
+#[test]
+fn sqlite_stores_keep_the_state_across_runs() {
+    let path = env::temp_dir().join(format!("tpe-storage-{}.sqlite", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let path = path.to_str().unwrap().to_owned();
+    {
+        // case2 leaves a pending dispute on tx 2.
+        let (accounts, transactions, disputes) = sqlite::open(&path).unwrap();
+        let mut pe = PaymentsEngine::with_stores(accounts, transactions, disputes);
+        pe.process_transactions_from(
+            get_csv_reader("resources/case-inputs/case2.csv".to_owned()).unwrap(),
+        )
+        .unwrap();
+    }
+    let (accounts, transactions, disputes) = sqlite::open(&path).unwrap();
+    assert!(transactions.get_transaction(2).unwrap().is_some());
+    assert!(disputes.get_dispute(2).unwrap().is_some());
+    let account = accounts.get_account(1).unwrap().unwrap();
+    assert_eq!(account.total, Decimal::from(5));
+    assert_eq!(account.held, Decimal::from(4));
+
+    let mut pe = PaymentsEngine::with_stores(accounts, transactions, disputes);
+    pe.process_transactions_from(
+        get_csv_reader("resources/case-inputs/case3.csv".to_owned()).unwrap(),
+    )
+    .unwrap();
+    let account = pe.accounts.get_account(1).unwrap().unwrap();
+    assert_eq!(account.total, Decimal::from(5));
+    assert_eq!(account.held, Decimal::from(0));
+    assert!(!pe.disputes.get_dispute(2).unwrap().unwrap().is_open());
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = fs::remove_file(format!("{}{}", path, suffix));
+    }
+}
+
+// Accounts on SQLite that can't keep the account of one client, as if the disk failed right then.
+struct FailingFor(sqlite::SqliteAccounts, ClientID);
+
+impl AccountStore for FailingFor {
+    fn get_account(&self, client_id: ClientID) -> Result<Option<Account>, StorageError> {
+        self.0.get_account(client_id)
+    }
+
+    fn put_account(&mut self, account: Account) -> Result<(), StorageError> {
+        match account.client_id() == self.1 {
+            true => Err(StorageError("disk failure".to_owned())),
+            false => self.0.put_account(account),
+        }
+    }
+
+    fn all_accounts(&self) -> Result<Vec<Account>, StorageError> {
+        self.0.all_accounts()
+    }
+
+    fn begin(&mut self) -> Result<(), StorageError> {
+        self.0.begin()
+    }
+
+    fn commit(&mut self) -> Result<(), StorageError> {
+        self.0.commit()
+    }
+
+    fn rollback(&mut self) -> Result<(), StorageError> {
+        self.0.rollback()
+    }
+}
+
+#[test]
+fn sqlite_stores_keep_neither_leg_of_a_transfer_failing_to_be_stored() {
+    let (accounts, transactions, disputes) = sqlite::open_in_memory().unwrap();
+    let mut pe = PaymentsEngine::with_stores(FailingFor(accounts, 2), transactions, disputes);
+    let data =
+        "type, client, tx, amount, to_client\ndeposit, 1, 1, 10.0,\ntransfer, 1, 2, 4.0, 2\n";
+    let processed = pe.process_transactions_from(get_csv_reader_from(data.as_bytes()));
+    assert!(matches!(
+        processed,
+        Err(InputProcessingError::UnavailableStorage(_))
+    ));
+
+    let account = pe.accounts.get_account(1).unwrap().unwrap();
+    assert_eq!(account.total, Decimal::from(10));
+    assert!(pe.transactions.get_transaction(2).unwrap().is_none());
+}